@@ -0,0 +1,673 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    ops::Range,
+};
+
+use arcstr::Substr;
+
+use crate::{
+    ast::{Ast, Def, Expr, GenericDef, Name, Root, TypeDef, TypeRef, ValueDef},
+    diagnostic::{Code, Diagnostic, Severity},
+    value::BUILTINS,
+};
+
+// the types every program can name without defining them
+pub const BUILTIN_TYPES: &[&str] = &["Int", "Float", "Bool"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Value,
+    Function,
+    Parameter,
+    Type,
+    Field,
+    Generic,
+    TypeParameter,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Named(String, Vec<Type>),
+    Function(Box<Type>, Box<Type>),
+    Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: Name,
+    pub kind: SymbolKind,
+    // the whole definition the name belongs to
+    pub text: Substr,
+    pub ty: Option<Type>,
+    pub bounds: Vec<Type>,
+    pub children: Vec<usize>,
+    // where the name can be used, in bytes. names that can't be used anywhere by themselves, like
+    // fields, have none
+    pub scope: Vec<Range<usize>>,
+}
+
+// every name in a file, what it's bound to and where it's used. symbols are referred to by index
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub top_level: Vec<usize>,
+    pub references: Vec<(Name, usize)>,
+    // names that aren't bound to anything, and types given the wrong number of arguments
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Analysis {
+    pub fn new(root: &Root) -> Self {
+        let mut resolver = Resolver::default();
+        let everywhere = 0..usize::MAX;
+
+        // top-level definitions can refer to each other in any order
+        let defs: Vec<Option<usize>> = root
+            .defs
+            .iter()
+            .map(|def| match def {
+                Def::Value(value_def) => {
+                    Some(resolver.declare_value(value_def, everywhere.clone()))
+                }
+                Def::Type(type_def) => Some(resolver.declare_type(type_def)),
+                Def::Generic(generic_def) => Some(resolver.declare_generic(generic_def)),
+                Def::Fixity(_) | Def::Test(_) => None,
+            })
+            .collect();
+
+        for (def, symbol) in root.defs.iter().zip(&defs) {
+            match def {
+                Def::Value(value_def) => {
+                    resolver.with_generics(&value_def.name, &value_def.text, |r| {
+                        r.expr(&value_def.body)
+                    })
+                }
+                Def::Type(type_def) => {
+                    resolver.with_generics(&type_def.name, &type_def.text, |r| {
+                        r.type_def(type_def, symbol.unwrap())
+                    })
+                }
+                Def::Generic(generic_def) => resolver.generic_def(generic_def),
+                // fixities only ever name operators that are defined elsewhere
+                Def::Fixity(fixity_def) => {
+                    for op in &fixity_def.operators {
+                        resolver.value_ref(op);
+                    }
+                }
+                Def::Test(test_def) => resolver.expr(&test_def.body),
+            }
+        }
+
+        resolver.infer_all();
+
+        let mut analysis = resolver.analysis;
+        analysis.top_level = defs.into_iter().flatten().collect();
+
+        analysis
+    }
+
+    // the symbol whose name, or a use of it, is under `offset`. the end is inclusive so a cursor just
+    // past a name still counts
+    pub fn symbol_at(&self, offset: usize) -> Option<(&Name, usize)> {
+        let contains = |name: &Name| {
+            name.text().range().contains(&offset) || name.text().range().end == offset
+        };
+
+        self.symbols
+            .iter()
+            .enumerate()
+            .map(|(i, symbol)| (&symbol.name, i))
+            .chain(self.references.iter().map(|(name, i)| (name, *i)))
+            .find(|(name, _)| contains(name))
+    }
+
+    pub fn references_to(&self, symbol: usize) -> impl Iterator<Item = &Name> {
+        self.references
+            .iter()
+            .filter(move |(_, i)| *i == symbol)
+            .map(|(name, _)| name)
+    }
+
+    // everything that can be named at `offset`, innermost first so shadowed names come last
+    pub fn visible(&self, offset: usize) -> Vec<usize> {
+        let mut visible: Vec<(usize, usize)> = self
+            .symbols
+            .iter()
+            .enumerate()
+            .filter_map(|(i, symbol)| {
+                symbol
+                    .scope
+                    .iter()
+                    .filter(|range| range.start <= offset && offset <= range.end)
+                    .map(|range| range.end - range.start)
+                    .min()
+                    .map(|size| (size, i))
+            })
+            .collect();
+        visible.sort();
+
+        let mut seen = HashSet::new();
+        visible
+            .into_iter()
+            .map(|(_, i)| i)
+            .filter(|i| seen.insert(self.symbols[*i].name.text().to_string()))
+            .collect()
+    }
+}
+
+impl Symbol {
+    pub fn detail(&self) -> Option<String> {
+        match &self.ty {
+            Some(Type::Unknown) => None,
+            Some(ty) => Some(ty.to_string()),
+            None if !self.bounds.is_empty() => Some(
+                self.bounds
+                    .iter()
+                    .map(Type::to_string)
+                    .collect::<Vec<_>>()
+                    .join(" & "),
+            ),
+            None => None,
+        }
+    }
+}
+
+impl Type {
+    pub fn is_known(&self) -> bool {
+        match self {
+            Type::Named(_, args) => args.iter().all(Type::is_known),
+            Type::Function(arg, ret) => arg.is_known() && ret.is_known(),
+            Type::Unknown => false,
+        }
+    }
+
+    // `Int -> Float -> Int` takes `[Int, Float]` and gives `Int`
+    pub fn params(&self) -> (Vec<&Type>, &Type) {
+        let mut params = Vec::new();
+        let mut ty = self;
+
+        while let Type::Function(arg, ret) = ty {
+            params.push(&**arg);
+            ty = ret;
+        }
+
+        (params, ty)
+    }
+}
+
+impl From<&TypeRef> for Type {
+    fn from(type_ref: &TypeRef) -> Self {
+        match type_ref {
+            TypeRef::Named(_, name, args) => Type::Named(
+                name.text().to_string(),
+                args.iter().map(Type::from).collect(),
+            ),
+            TypeRef::Function(_, arg, ret) => {
+                Type::Function(Box::new(Type::from(&**arg)), Box::new(Type::from(&**ret)))
+            }
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Named(name, args) => {
+                write!(f, "{name}")?;
+
+                for arg in args {
+                    match arg {
+                        Type::Named(_, args) if args.is_empty() => write!(f, " {arg}")?,
+                        Type::Unknown => write!(f, " {arg}")?,
+                        _ => write!(f, " ({arg})")?,
+                    }
+                }
+
+                Ok(())
+            }
+            Type::Function(arg, ret) => match **arg {
+                Type::Function(..) => write!(f, "({arg}) -> {ret}"),
+                _ => write!(f, "{arg} -> {ret}"),
+            },
+            Type::Unknown => write!(f, "_"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Resolver<'a> {
+    analysis: Analysis,
+    values: Vec<(String, usize)>,
+    types: Vec<(String, usize)>,
+    generics: HashMap<String, Vec<usize>>,
+    bodies: HashMap<usize, &'a Expr>,
+}
+
+impl<'a> Resolver<'a> {
+    fn add(
+        &mut self,
+        name: &Name,
+        kind: SymbolKind,
+        text: &Substr,
+        scope: Option<Range<usize>>,
+    ) -> usize {
+        self.analysis.symbols.push(Symbol {
+            name: name.clone(),
+            kind,
+            text: text.clone(),
+            ty: None,
+            bounds: Vec::new(),
+            children: Vec::new(),
+            scope: scope.into_iter().collect(),
+        });
+
+        self.analysis.symbols.len() - 1
+    }
+
+    fn declare_value(&mut self, def: &'a ValueDef, scope: Range<usize>) -> usize {
+        let kind = match def.body {
+            Expr::Func(..) => SymbolKind::Function,
+            _ => SymbolKind::Value,
+        };
+        let symbol = self.add(&def.name, kind, &def.text, Some(scope));
+        self.values.push((def.name.text().to_string(), symbol));
+        self.bodies.insert(symbol, &def.body);
+
+        symbol
+    }
+
+    fn declare_type(&mut self, def: &TypeDef) -> usize {
+        let symbol = self.add(&def.name, SymbolKind::Type, &def.text, Some(0..usize::MAX));
+        self.types.push((def.name.text().to_string(), symbol));
+
+        symbol
+    }
+
+    // `map $ a, b;` makes `a` and `b` usable as types in whatever else is called `map`
+    fn declare_generic(&mut self, def: &GenericDef) -> usize {
+        let symbol = self.add(&def.name, SymbolKind::Generic, &def.text, None);
+
+        for (name, bounds) in &def.args {
+            let arg = self.add(
+                name,
+                SymbolKind::TypeParameter,
+                &def.text,
+                Some(def.text.range()),
+            );
+            self.analysis.symbols[arg].bounds = bounds.iter().map(Type::from).collect();
+            self.analysis.symbols[symbol].children.push(arg);
+            self.generics
+                .entry(def.name.text().to_string())
+                .or_default()
+                .push(arg);
+        }
+
+        symbol
+    }
+
+    fn with_generics(&mut self, name: &Name, text: &Substr, f: impl FnOnce(&mut Self)) {
+        let scope = self.types.len();
+
+        for arg in self
+            .generics
+            .get(name.text().as_str())
+            .cloned()
+            .unwrap_or_default()
+        {
+            self.analysis.symbols[arg].scope.push(text.range());
+            self.types
+                .push((self.analysis.symbols[arg].name.text().to_string(), arg));
+        }
+
+        f(self);
+        self.types.truncate(scope);
+    }
+
+    fn type_def(&mut self, def: &TypeDef, symbol: usize) {
+        for (name, type_ref) in &def.fields {
+            self.type_ref(type_ref);
+            let field = self.add(name, SymbolKind::Field, &def.text, None);
+            self.analysis.symbols[field].ty = Some(Type::from(type_ref));
+            self.analysis.symbols[symbol].children.push(field);
+        }
+    }
+
+    fn generic_def(&mut self, def: &GenericDef) {
+        let scope = self.types.len();
+
+        for arg in self
+            .generics
+            .get(def.name.text().as_str())
+            .cloned()
+            .unwrap_or_default()
+        {
+            self.types
+                .push((self.analysis.symbols[arg].name.text().to_string(), arg));
+        }
+
+        for (_, bounds) in &def.args {
+            for bound in bounds {
+                self.type_ref(bound);
+            }
+        }
+
+        self.types.truncate(scope);
+    }
+
+    // a type's name is also its constructor
+    fn value_ref(&mut self, name: &Name) {
+        let symbol = self
+            .values
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name.text().as_str())
+            .or_else(|| {
+                self.types.iter().rev().find(|(n, symbol)| {
+                    n == name.text().as_str()
+                        && self.analysis.symbols[*symbol].kind == SymbolKind::Type
+                })
+            });
+
+        match symbol {
+            Some((_, symbol)) => self.analysis.references.push((name.clone(), *symbol)),
+            None if is_builtin_value(name.text()) => (),
+            None => self.error(
+                Code::UnboundName,
+                format!("`{}` isn't defined", name.text()),
+                name.text(),
+                "nothing with this name is in scope",
+            ),
+        }
+    }
+
+    fn type_ref(&mut self, type_ref: &TypeRef) {
+        match type_ref {
+            TypeRef::Named(text, name, args) => {
+                let symbol = self
+                    .types
+                    .iter()
+                    .rev()
+                    .find(|(n, _)| n == name.text().as_str())
+                    .map(|(_, symbol)| *symbol);
+                let params = match symbol {
+                    Some(symbol) => {
+                        self.analysis.references.push((name.clone(), symbol));
+                        self.type_params(symbol)
+                    }
+                    None if BUILTIN_TYPES.contains(&name.text().as_str()) => 0,
+                    None => {
+                        self.error(
+                            Code::UnknownType,
+                            format!("there's no type called `{}`", name.text()),
+                            name.text(),
+                            "nothing with this name is a type",
+                        );
+                        args.len()
+                    }
+                };
+
+                if args.len() != params {
+                    self.error(
+                        Code::WrongTypeArguments,
+                        format!(
+                            "`{}` takes {params} type argument{}, not {}",
+                            name.text(),
+                            if params == 1 { "" } else { "s" },
+                            args.len()
+                        ),
+                        text,
+                        "this has the wrong number of type arguments",
+                    );
+                }
+
+                for arg in args {
+                    self.type_ref(arg);
+                }
+            }
+            TypeRef::Function(_, arg, ret) => {
+                self.type_ref(arg);
+                self.type_ref(ret);
+            }
+        }
+    }
+
+    // type parameters take no arguments of their own
+    fn type_params(&self, symbol: usize) -> usize {
+        match self.analysis.symbols[symbol].kind {
+            SymbolKind::Type => self
+                .generics
+                .get(self.analysis.symbols[symbol].name.text().as_str())
+                .map_or(0, Vec::len),
+            _ => 0,
+        }
+    }
+
+    fn error(&mut self, code: Code, message: String, text: &Substr, label: &str) {
+        self.analysis.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            code,
+            message,
+            range: text.range(),
+            label: label.to_string(),
+            secondary: Vec::new(),
+            suggestions: Vec::new(),
+        });
+    }
+
+    fn expr(&mut self, expr: &'a Expr) {
+        match expr {
+            // the field's name isn't in scope anywhere, so there's nothing to resolve it to
+            Expr::Call(..) if let Some((record, _)) = expr.field_access() => self.expr(record),
+            Expr::SymbolRef(_, name) => self.value_ref(name),
+            Expr::Func(text, name, arg_type, ret_type, body) => {
+                for type_ref in [arg_type, ret_type].into_iter().flatten() {
+                    self.type_ref(type_ref);
+                }
+
+                let param = self.add(name, SymbolKind::Parameter, text, Some(body.text().range()));
+                self.analysis.symbols[param].ty = arg_type.as_ref().map(Type::from);
+                self.values.push((name.text().to_string(), param));
+                self.expr(body);
+                self.values.pop();
+            }
+            Expr::Call(_, func, arg) => {
+                self.expr(func);
+                self.expr(arg);
+            }
+            Expr::IfThenElse(_, condition, then_expr, else_expr) => {
+                self.expr(condition);
+                self.expr(then_expr);
+                self.expr(else_expr);
+            }
+            // like the top level, everything in a `let` can see everything else in it
+            Expr::LetIn(text, defs, body) => {
+                let scope = self.values.len();
+
+                for def in defs {
+                    self.declare_value(def, text.range());
+                }
+
+                for def in defs {
+                    self.expr(&def.body);
+                }
+
+                self.expr(body);
+                self.values.truncate(scope);
+            }
+            Expr::Float(..) | Expr::Int(..) | Expr::Error(_) => (),
+        }
+    }
+
+    // there's no type checker yet, so this only works types out bottom-up from annotations and
+    // literals. anything it can't see is `_`
+    fn infer_all(&mut self) {
+        let uses: HashMap<usize, usize> = self
+            .analysis
+            .references
+            .iter()
+            .map(|(name, symbol)| (name.text().range().start, *symbol))
+            .collect();
+        let mut in_progress = HashSet::new();
+        let mut symbols: Vec<usize> = self.bodies.keys().copied().collect();
+        symbols.sort();
+
+        for symbol in symbols {
+            self.infer_symbol(symbol, &uses, &mut in_progress);
+        }
+    }
+
+    fn infer_symbol(
+        &mut self,
+        symbol: usize,
+        uses: &HashMap<usize, usize>,
+        in_progress: &mut HashSet<usize>,
+    ) -> Type {
+        if let Some(ty) = &self.analysis.symbols[symbol].ty {
+            return ty.clone();
+        }
+
+        let Some(body) = self.bodies.get(&symbol).copied() else {
+            return Type::Unknown;
+        };
+
+        // recursive definitions only get whatever their annotations say
+        if !in_progress.insert(symbol) {
+            return Type::Unknown;
+        }
+
+        let ty = self.infer(body, uses, in_progress);
+        self.analysis.symbols[symbol].ty = Some(ty.clone());
+
+        ty
+    }
+
+    fn infer(
+        &mut self,
+        expr: &Expr,
+        uses: &HashMap<usize, usize>,
+        in_progress: &mut HashSet<usize>,
+    ) -> Type {
+        match expr {
+            Expr::SymbolRef(_, name) => match uses.get(&name.text().range().start) {
+                Some(symbol) if self.analysis.symbols[*symbol].kind == SymbolKind::Type => {
+                    self.constructor_type(*symbol)
+                }
+                Some(symbol) => self.infer_symbol(*symbol, uses, in_progress),
+                None if matches!(name.text().as_str(), "true" | "false") => bool_type(),
+                None => Type::Unknown,
+            },
+            Expr::Func(_, _, arg_type, ret_type, body) => Type::Function(
+                Box::new(arg_type.as_ref().map_or(Type::Unknown, Type::from)),
+                Box::new(match ret_type {
+                    Some(ret_type) => Type::from(ret_type),
+                    None => self.infer(body, uses, in_progress),
+                }),
+            ),
+            Expr::Call(..) if let Some((record, field)) = expr.field_access() => {
+                match self.infer(record, uses, in_progress) {
+                    Type::Named(ty, _) => self
+                        .types
+                        .iter()
+                        .find(|(name, _)| *name == ty)
+                        .and_then(|(_, symbol)| {
+                            self.analysis.symbols[*symbol]
+                                .children
+                                .iter()
+                                .map(|child| &self.analysis.symbols[*child])
+                                .find(|child| child.name.text() == field.text())
+                        })
+                        .and_then(|field| field.ty.clone())
+                        .unwrap_or(Type::Unknown),
+                    _ => Type::Unknown,
+                }
+            }
+            Expr::Call(_, func, arg) => {
+                if let Some(ty) = self.infer_builtin(func, arg, uses, in_progress) {
+                    return ty;
+                }
+
+                match self.infer(func, uses, in_progress) {
+                    Type::Function(_, ret) => *ret,
+                    _ => Type::Unknown,
+                }
+            }
+            Expr::IfThenElse(_, _, then_expr, else_expr) => {
+                match self.infer(then_expr, uses, in_progress) {
+                    Type::Unknown => self.infer(else_expr, uses, in_progress),
+                    ty => ty,
+                }
+            }
+            Expr::LetIn(_, _, body) => self.infer(body, uses, in_progress),
+            Expr::Float(..) => Type::Named("Float".to_string(), Vec::new()),
+            Expr::Int(..) => Type::Named("Int".to_string(), Vec::new()),
+            Expr::Error(_) => Type::Unknown,
+        }
+    }
+
+    // `Point | x :Int, y :Int;` is built with `Point : Int -> Int -> Point`
+    fn constructor_type(&self, symbol: usize) -> Type {
+        let ty = &self.analysis.symbols[symbol];
+        let params = self
+            .generics
+            .get(ty.name.text().as_str())
+            .into_iter()
+            .flatten()
+            .map(|param| {
+                Type::Named(
+                    self.analysis.symbols[*param].name.text().to_string(),
+                    Vec::new(),
+                )
+            })
+            .collect();
+
+        ty.children.iter().rev().fold(
+            Type::Named(ty.name.text().to_string(), params),
+            |ret, field| {
+                let field = self.analysis.symbols[*field]
+                    .ty
+                    .clone()
+                    .unwrap_or(Type::Unknown);
+                Type::Function(Box::new(field), Box::new(ret))
+            },
+        )
+    }
+
+    // built-ins aren't symbols, so they only get recognised when nothing in the file shadows them.
+    // arithmetic gives back whatever it was given and everything else gives a `Bool`
+    fn infer_builtin(
+        &mut self,
+        func: &Expr,
+        arg: &Expr,
+        uses: &HashMap<usize, usize>,
+        in_progress: &mut HashSet<usize>,
+    ) -> Option<Type> {
+        let builtin = |expr: &Expr| match expr {
+            Expr::SymbolRef(_, name) if !uses.contains_key(&name.text().range().start) => {
+                Some(name.text().to_string())
+            }
+            _ => None,
+        };
+
+        match func {
+            Expr::SymbolRef(..) if builtin(func)? == "not" => Some(bool_type()),
+            Expr::Call(_, op, lhs) => match builtin(op)?.as_str() {
+                "+" | "-" | "*" | "/" | "%" => Some(match self.infer(lhs, uses, in_progress) {
+                    Type::Unknown => self.infer(arg, uses, in_progress),
+                    ty => ty,
+                }),
+                "==" | "!=" | "<" | "<=" | ">" | ">=" | "&&" | "||" => Some(bool_type()),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+fn is_builtin_value(name: &str) -> bool {
+    matches!(name, "true" | "false") || BUILTINS.iter().any(|(builtin, _)| *builtin == name)
+}
+
+fn bool_type() -> Type {
+    Type::Named("Bool".to_string(), Vec::new())
+}
@@ -2,6 +2,12 @@ use std::fmt::Debug;
 use std::hash::Hash;
 
 use arcstr::Substr;
+use serde::{
+    ser::{SerializeStruct, SerializeStructVariant},
+    Serialize, Serializer,
+};
+
+use crate::diagnostic::SourceFile;
 
 pub trait Ast: Debug + Clone {
     fn text(&self) -> &Substr;
@@ -25,6 +31,8 @@ pub enum Def {
     Generic(GenericDef),
     Value(ValueDef),
     Type(TypeDef),
+    Fixity(FixityDef),
+    Test(TestDef),
 }
 
 impl Ast for Def {
@@ -33,6 +41,8 @@ impl Ast for Def {
             Def::Generic(generic_def) => &generic_def.text,
             Def::Value(value_def) => &value_def.text,
             Def::Type(type_def) => &type_def.text,
+            Def::Fixity(fixity_def) => &fixity_def.text,
+            Def::Test(test_def) => &test_def.text,
         }
     }
 }
@@ -58,6 +68,30 @@ pub struct TypeDef {
     pub fields: Vec<(Name, TypeRef)>,
 }
 
+#[derive(Debug, Clone)]
+pub struct FixityDef {
+    pub text: Substr,
+    pub associativity: Associativity,
+    pub precedence: u16,
+    pub operators: Vec<Name>,
+}
+
+// only `caelis test` runs these, they're left out of everything else
+#[derive(Debug, Clone)]
+pub struct TestDef {
+    pub text: Substr,
+    // the string literal, quotes and all, and what it says
+    pub literal: Substr,
+    pub name: String,
+    pub body: Expr,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+pub enum Associativity {
+    Left,
+    Right,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Name(pub Substr);
 
@@ -85,12 +119,14 @@ impl Ast for TypeRef {
 #[derive(Debug, Clone)]
 pub enum Expr {
     SymbolRef(Substr, Name),
-    Func(Substr, Name, TypeRef, Option<TypeRef>, Box<Expr>),
+    // only functions `lower` makes up, like the one `f >> g` stands for, have no parameter type
+    Func(Substr, Name, Option<TypeRef>, Option<TypeRef>, Box<Expr>),
     Call(Substr, Box<Expr>, Box<Expr>),
     IfThenElse(Substr, Box<Expr>, Box<Expr>, Box<Expr>),
     LetIn(Substr, Vec<ValueDef>, Box<Expr>),
     Float(Substr, f64),
-    Int(Substr, f64),
+    Int(Substr, i64),
+    Error(Substr),
 }
 
 impl Ast for Expr {
@@ -103,6 +139,275 @@ impl Ast for Expr {
             Expr::LetIn(substr, _, _) => substr,
             Expr::Float(substr, _) => substr,
             Expr::Int(substr, _) => substr,
+            Expr::Error(substr) => substr,
+        }
+    }
+}
+
+impl Expr {
+    // `p.x` comes out of the parser as `(.) p x`, and nothing else can be called `.`
+    pub fn field_access(&self) -> Option<(&Expr, &Name)> {
+        match self {
+            Expr::Call(_, func, field) => match (&**func, &**field) {
+                (Expr::Call(_, op, record), Expr::SymbolRef(_, field))
+                    if op.text().as_str() == "." =>
+                {
+                    Some((record, field))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
+
+// an ast node as JSON. every variant is an object with named fields, and spans are byte offsets as well
+// as 1-based lines and columns in `file`, the file the node was parsed from
+pub struct Json<'a, T: ?Sized> {
+    pub node: &'a T,
+    pub file: &'a SourceFile,
+}
+
+impl<'a, T: ?Sized> Json<'a, T> {
+    pub fn new(node: &'a T, file: &'a SourceFile) -> Self {
+        Self { node, file }
+    }
+
+    fn with<U: ?Sized>(&self, node: &'a U) -> Json<'a, U> {
+        Json::new(node, self.file)
+    }
+
+    fn span(&self, text: &'a Substr) -> JsonSpan<'a> {
+        JsonSpan {
+            text,
+            file: self.file,
+        }
+    }
+}
+
+struct JsonSpan<'a> {
+    text: &'a Substr,
+    file: &'a SourceFile,
+}
+
+impl Serialize for JsonSpan<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let range = self.text.range();
+        let (line, column) = self.file.line_column(range.start);
+        let (end_line, end_column) = self.file.line_column(range.end);
+
+        let mut span = serializer.serialize_struct("Span", 6)?;
+        span.serialize_field("start", &range.start)?;
+        span.serialize_field("end", &range.end)?;
+        span.serialize_field("line", &line)?;
+        span.serialize_field("column", &column)?;
+        span.serialize_field("end_line", &end_line)?;
+        span.serialize_field("end_column", &end_column)?;
+        span.end()
+    }
+}
+
+impl<'a, T> Serialize for Json<'a, [T]>
+where
+    Json<'a, T>: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.node.iter().map(|node| self.with(node)))
+    }
+}
+
+impl Serialize for Json<'_, Root> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut root = serializer.serialize_struct("Root", 2)?;
+        root.serialize_field("span", &self.span(&self.node.text))?;
+        root.serialize_field("defs", &self.with(&self.node.defs[..]))?;
+        root.end()
+    }
+}
+
+impl Serialize for Json<'_, Def> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.node {
+            Def::Generic(def) => {
+                serializer.serialize_newtype_variant("Def", 0, "Generic", &self.with(def))
+            }
+            Def::Value(def) => {
+                serializer.serialize_newtype_variant("Def", 1, "Value", &self.with(def))
+            }
+            Def::Type(def) => {
+                serializer.serialize_newtype_variant("Def", 2, "Type", &self.with(def))
+            }
+            Def::Fixity(def) => {
+                serializer.serialize_newtype_variant("Def", 3, "Fixity", &self.with(def))
+            }
+            Def::Test(def) => {
+                serializer.serialize_newtype_variant("Def", 4, "Test", &self.with(def))
+            }
+        }
+    }
+}
+
+impl Serialize for Json<'_, GenericDef> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut def = serializer.serialize_struct("GenericDef", 3)?;
+        def.serialize_field("span", &self.span(&self.node.text))?;
+        def.serialize_field("name", &self.with(&self.node.name))?;
+        def.serialize_field("args", &self.with(&self.node.args[..]))?;
+        def.end()
+    }
+}
+
+impl Serialize for Json<'_, (Name, Vec<TypeRef>)> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (name, bounds) = self.node;
+
+        let mut arg = serializer.serialize_struct("GenericArg", 2)?;
+        arg.serialize_field("name", &self.with(name))?;
+        arg.serialize_field("bounds", &self.with(&bounds[..]))?;
+        arg.end()
+    }
+}
+
+impl Serialize for Json<'_, ValueDef> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut def = serializer.serialize_struct("ValueDef", 3)?;
+        def.serialize_field("span", &self.span(&self.node.text))?;
+        def.serialize_field("name", &self.with(&self.node.name))?;
+        def.serialize_field("body", &self.with(&self.node.body))?;
+        def.end()
+    }
+}
+
+impl Serialize for Json<'_, TypeDef> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut def = serializer.serialize_struct("TypeDef", 3)?;
+        def.serialize_field("span", &self.span(&self.node.text))?;
+        def.serialize_field("name", &self.with(&self.node.name))?;
+        def.serialize_field("fields", &self.with(&self.node.fields[..]))?;
+        def.end()
+    }
+}
+
+impl Serialize for Json<'_, (Name, TypeRef)> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let (name, type_ref) = self.node;
+
+        let mut field = serializer.serialize_struct("Field", 2)?;
+        field.serialize_field("name", &self.with(name))?;
+        field.serialize_field("type", &self.with(type_ref))?;
+        field.end()
+    }
+}
+
+impl Serialize for Json<'_, FixityDef> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut def = serializer.serialize_struct("FixityDef", 4)?;
+        def.serialize_field("span", &self.span(&self.node.text))?;
+        def.serialize_field("associativity", &self.node.associativity)?;
+        def.serialize_field("precedence", &self.node.precedence)?;
+        def.serialize_field("operators", &self.with(&self.node.operators[..]))?;
+        def.end()
+    }
+}
+
+impl Serialize for Json<'_, TestDef> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut def = serializer.serialize_struct("TestDef", 4)?;
+        def.serialize_field("span", &self.span(&self.node.text))?;
+        def.serialize_field("literal", &self.span(&self.node.literal))?;
+        def.serialize_field("name", &self.node.name)?;
+        def.serialize_field("body", &self.with(&self.node.body))?;
+        def.end()
+    }
+}
+
+impl Serialize for Json<'_, Name> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut name = serializer.serialize_struct("Name", 2)?;
+        name.serialize_field("text", self.node.0.as_str())?;
+        name.serialize_field("span", &self.span(&self.node.0))?;
+        name.end()
+    }
+}
+
+impl Serialize for Json<'_, TypeRef> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.node {
+            TypeRef::Named(text, name, args) => {
+                let mut ty = serializer.serialize_struct_variant("TypeRef", 0, "Named", 3)?;
+                ty.serialize_field("span", &self.span(text))?;
+                ty.serialize_field("name", &self.with(name))?;
+                ty.serialize_field("args", &self.with(&args[..]))?;
+                ty.end()
+            }
+            TypeRef::Function(text, arg, ret) => {
+                let mut ty = serializer.serialize_struct_variant("TypeRef", 1, "Function", 3)?;
+                ty.serialize_field("span", &self.span(text))?;
+                ty.serialize_field("arg", &self.with(&**arg))?;
+                ty.serialize_field("ret", &self.with(&**ret))?;
+                ty.end()
+            }
+        }
+    }
+}
+
+impl Serialize for Json<'_, Expr> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.node {
+            Expr::SymbolRef(text, name) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 0, "SymbolRef", 2)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("name", &self.with(name))?;
+                expr.end()
+            }
+            Expr::Func(text, param, param_type, ret_type, body) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 1, "Func", 5)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("param", &self.with(param))?;
+                expr.serialize_field("param_type", &param_type.as_ref().map(|ty| self.with(ty)))?;
+                expr.serialize_field("ret_type", &ret_type.as_ref().map(|ty| self.with(ty)))?;
+                expr.serialize_field("body", &self.with(&**body))?;
+                expr.end()
+            }
+            Expr::Call(text, func, arg) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 2, "Call", 3)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("func", &self.with(&**func))?;
+                expr.serialize_field("arg", &self.with(&**arg))?;
+                expr.end()
+            }
+            Expr::IfThenElse(text, condition, then_expr, else_expr) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 3, "IfThenElse", 4)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("condition", &self.with(&**condition))?;
+                expr.serialize_field("then", &self.with(&**then_expr))?;
+                expr.serialize_field("else", &self.with(&**else_expr))?;
+                expr.end()
+            }
+            Expr::LetIn(text, defs, body) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 4, "LetIn", 3)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("defs", &self.with(&defs[..]))?;
+                expr.serialize_field("body", &self.with(&**body))?;
+                expr.end()
+            }
+            Expr::Float(text, value) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 5, "Float", 2)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("value", value)?;
+                expr.end()
+            }
+            Expr::Int(text, value) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 6, "Int", 2)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.serialize_field("value", value)?;
+                expr.end()
+            }
+            Expr::Error(text) => {
+                let mut expr = serializer.serialize_struct_variant("Expr", 7, "Error", 1)?;
+                expr.serialize_field("span", &self.span(text))?;
+                expr.end()
+            }
         }
     }
 }
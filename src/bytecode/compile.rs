@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use arcstr::Substr;
+
+use super::{Capture, Function, Global, GlobalKind, Instr, Program, Span};
+use crate::{
+    ast::{Ast, Def, Expr, Root},
+    value::{Builtin, RuntimeError, BUILTINS},
+};
+
+struct Compiler {
+    globals: HashMap<String, u32>,
+    functions: Vec<Function>,
+    // the functions being compiled, innermost last
+    scopes: Vec<Scope>,
+}
+
+#[derive(Default)]
+struct Scope {
+    // names are looked up from the end so inner ones shadow outer ones
+    locals: Vec<(String, u32)>,
+    slots: u32,
+    captures: Vec<Capture>,
+    code: Vec<Instr>,
+    spans: Vec<Span>,
+}
+
+// like the interpreter, later definitions replace earlier ones with the same name. tests are left out
+pub fn compile(file: &str, root: &Root) -> Result<Program, RuntimeError> {
+    compile_hosted(file, root, &[])
+}
+
+// the same, with `hosts` naming functions the program embedding caelis provides once it runs. like the
+// built-ins, a definition with the same name shadows them
+pub fn compile_hosted(file: &str, root: &Root, hosts: &[&str]) -> Result<Program, RuntimeError> {
+    let (compiler, globals) = definitions(root, hosts)?;
+
+    Ok(compiler.program(file, root, globals))
+}
+
+// the program along with a function for each of its tests, in order. a test that doesn't compile
+// only fails itself
+pub fn compile_tests(
+    file: &str,
+    root: &Root,
+) -> Result<(Program, Vec<Result<u32, RuntimeError>>), RuntimeError> {
+    let (mut compiler, globals) = definitions(root, &[])?;
+    let tests = root
+        .defs
+        .iter()
+        .filter_map(|def| match def {
+            Def::Test(test_def) => Some(compiler.thunk(&test_def.body)),
+            _ => None,
+        })
+        .collect();
+
+    Ok((compiler.program(file, root, globals), tests))
+}
+
+fn definitions(root: &Root, hosts: &[&str]) -> Result<(Compiler, Vec<Global>), RuntimeError> {
+    let mut globals: Vec<Global> = hosts
+        .iter()
+        .map(|name| Global {
+            name: name.to_string(),
+            kind: GlobalKind::Host,
+        })
+        .collect();
+    let mut indices: HashMap<String, u32> = hosts
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (name.to_string(), i as u32))
+        .collect();
+
+    for def in &root.defs {
+        let (name, kind) = match def {
+            Def::Value(value_def) => (value_def.name.text(), GlobalKind::Value(0)),
+            Def::Type(type_def) => (
+                type_def.name.text(),
+                GlobalKind::Constructor(
+                    type_def
+                        .fields
+                        .iter()
+                        .map(|(name, _)| name.text().to_string())
+                        .collect(),
+                ),
+            ),
+            Def::Generic(_) | Def::Fixity(_) | Def::Test(_) => continue,
+        };
+        let index = *indices.entry(name.to_string()).or_insert_with(|| {
+            globals.push(Global {
+                name: name.to_string(),
+                kind: GlobalKind::Value(0),
+            });
+            globals.len() as u32 - 1
+        });
+
+        globals[index as usize].kind = kind;
+    }
+
+    let mut compiler = Compiler {
+        globals: indices,
+        functions: Vec::new(),
+        scopes: Vec::new(),
+    };
+
+    for def in &root.defs {
+        if let Def::Value(value_def) = def {
+            let function = compiler.thunk(&value_def.body)?;
+            globals[compiler.globals[value_def.name.text().as_str()] as usize].kind =
+                GlobalKind::Value(function);
+        }
+    }
+
+    Ok((compiler, globals))
+}
+
+impl Compiler {
+    fn program(self, file: &str, root: &Root, globals: Vec<Global>) -> Program {
+        Program {
+            file: file.to_string(),
+            source: root.text.parent().clone(),
+            globals,
+            functions: self.functions,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+        match expr {
+            Expr::Call(..) if let Some((record, field)) = expr.field_access() => {
+                self.expr(record)?;
+                self.emit(Instr::Field(field.text().into()), record.text());
+            }
+            Expr::SymbolRef(_, name) => {
+                let instr = match self.resolve(self.scopes.len() - 1, name.text()) {
+                    Some(Capture::Local(slot)) => Instr::Local(slot),
+                    Some(Capture::Capture(index)) => Instr::Capture(index),
+                    None => self
+                        .global(name.text())
+                        .ok_or_else(|| RuntimeError::unbound(name))?,
+                };
+
+                self.emit(instr, name.text());
+            }
+            Expr::Func(text, param, _, _, body) => {
+                self.scopes.push(Scope {
+                    locals: vec![(param.text().to_string(), 0)],
+                    slots: 1,
+                    ..Scope::default()
+                });
+                let function = self.function(1, body)?;
+
+                self.emit(Instr::Closure(function), text);
+            }
+            Expr::Call(text, ..) if let Some((builtin, args)) = self.saturated(expr) => {
+                for arg in args {
+                    self.expr(arg)?;
+                }
+
+                self.emit(Instr::Op(builtin), text);
+            }
+            Expr::Call(text, func, arg) => {
+                self.expr(func)?;
+                self.expr(arg)?;
+                self.emit(Instr::Call(func.text().into()), text);
+            }
+            Expr::IfThenElse(_, condition, then_expr, else_expr) => {
+                self.expr(condition)?;
+                let jump_unless = self.emit(Instr::JumpUnless(0), condition.text());
+                self.expr(then_expr)?;
+                let jump = self.emit(Instr::Jump(0), then_expr.text());
+                let else_start = self.scope().code.len() as u32;
+                self.expr(else_expr)?;
+                let end = self.scope().code.len() as u32;
+
+                self.scope().code[jump_unless] = Instr::JumpUnless(else_start);
+                self.scope().code[jump] = Instr::Jump(end);
+            }
+            Expr::LetIn(_, defs, body) => {
+                let scope = self.scope().locals.len();
+                let mut slots = Vec::new();
+
+                for def in defs {
+                    let slot = self.scope().slots;
+                    self.scope().slots += 1;
+                    self.scope()
+                        .locals
+                        .push((def.name.text().to_string(), slot));
+                    self.emit(Instr::Cell(slot), def.name.text());
+                    slots.push(slot);
+                }
+
+                for (def, slot) in defs.iter().zip(slots) {
+                    let function = self.thunk(&def.body)?;
+                    self.emit(Instr::Thunk(slot, function), &def.text);
+                }
+
+                self.expr(body)?;
+                self.scope().locals.truncate(scope);
+            }
+            Expr::Float(text, value) => {
+                self.emit(Instr::Float(*value), text);
+            }
+            Expr::Int(text, value) => {
+                self.emit(Instr::Int(*value), text);
+            }
+            Expr::Error(text) => return Err(RuntimeError::unparsed(text)),
+        }
+
+        Ok(())
+    }
+
+    // a definition becomes a function of nothing that gets called the first time it's needed
+    fn thunk(&mut self, body: &Expr) -> Result<u32, RuntimeError> {
+        self.scopes.push(Scope::default());
+
+        self.function(0, body)
+    }
+
+    // finishes the innermost scope as a function with `body`
+    fn function(&mut self, arity: u8, body: &Expr) -> Result<u32, RuntimeError> {
+        let result = self.expr(body);
+        self.emit(Instr::Return, body.text());
+        let scope = self
+            .scopes
+            .pop()
+            .expect("functions are compiled in a scope");
+        result?;
+
+        self.functions.push(Function {
+            arity,
+            slots: scope.slots,
+            captures: scope.captures,
+            code: scope.code,
+            spans: scope.spans,
+        });
+
+        Ok(self.functions.len() as u32 - 1)
+    }
+
+    // finds a local in the function at `depth`, capturing it from the functions around it if that's
+    // where it's defined
+    fn resolve(&mut self, depth: usize, name: &str) -> Option<Capture> {
+        let local = self.scopes[depth]
+            .locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name);
+
+        if let Some((_, slot)) = local {
+            return Some(Capture::Local(*slot));
+        }
+
+        let outer = self.resolve(depth.checked_sub(1)?, name)?;
+        let captures = &mut self.scopes[depth].captures;
+        let index = match captures.iter().position(|capture| *capture == outer) {
+            Some(index) => index,
+            None => {
+                captures.push(outer);
+                captures.len() - 1
+            }
+        };
+
+        Some(Capture::Capture(index as u32))
+    }
+
+    fn global(&self, name: &str) -> Option<Instr> {
+        if let Some(index) = self.globals.get(name) {
+            return Some(Instr::Global(*index));
+        }
+
+        match name {
+            "true" => Some(Instr::Bool(true)),
+            "false" => Some(Instr::Bool(false)),
+            _ => BUILTINS
+                .iter()
+                .find(|(builtin, _)| *builtin == name)
+                .map(|(_, builtin)| Instr::Builtin(*builtin)),
+        }
+    }
+
+    // a built-in that's given everything it takes runs straight away, rather than being built up one
+    // argument at a time
+    fn saturated<'e>(&mut self, expr: &'e Expr) -> Option<(Builtin, Vec<&'e Expr>)> {
+        let Expr::Call(_, func, last) = expr else {
+            return None;
+        };
+        let (name, args) = match &**func {
+            Expr::SymbolRef(_, name) => (name, vec![&**last]),
+            Expr::Call(_, func, first) => match &**func {
+                Expr::SymbolRef(_, name) => (name, vec![&**first, &**last]),
+                _ => return None,
+            },
+            _ => return None,
+        };
+
+        if self.resolve(self.scopes.len() - 1, name.text()).is_some() {
+            return None;
+        }
+
+        match self.global(name.text())? {
+            Instr::Builtin(builtin) if builtin.arity() == args.len() => Some((builtin, args)),
+            _ => None,
+        }
+    }
+
+    fn scope(&mut self) -> &mut Scope {
+        self.scopes.last_mut().expect("code is compiled in a scope")
+    }
+
+    // returns where the instruction went, for jumps that get filled in later
+    fn emit(&mut self, instr: Instr, text: &Substr) -> usize {
+        let scope = self.scope();
+        scope.code.push(instr);
+        scope.spans.push(text.into());
+
+        scope.code.len() - 1
+    }
+}
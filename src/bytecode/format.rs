@@ -0,0 +1,508 @@
+use anyhow::{bail, ensure, Context, Result};
+use arcstr::ArcStr;
+
+use super::{Capture, Function, Global, GlobalKind, Instr, Program, Span};
+use crate::value::{Builtin, BUILTINS};
+
+// `.caeb` files start with this, then a version that goes up whenever the layout changes. everything
+// after is little-endian, and strings and lists are prefixed with their length as a u32
+const MAGIC: &[u8; 4] = b"CAEB";
+const VERSION: u16 = 1;
+
+pub fn encode(program: &Program) -> Vec<u8> {
+    let mut writer = Writer(MAGIC.to_vec());
+    writer.u16(VERSION);
+    writer.str(&program.file);
+    writer.str(&program.source);
+
+    writer.u32(program.globals.len() as u32);
+    for global in &program.globals {
+        writer.str(&global.name);
+
+        match &global.kind {
+            GlobalKind::Value(function) => {
+                writer.u8(0);
+                writer.u32(*function);
+            }
+            GlobalKind::Constructor(fields) => {
+                writer.u8(1);
+                writer.u32(fields.len() as u32);
+                for field in fields {
+                    writer.str(field);
+                }
+            }
+            GlobalKind::Host => writer.u8(2),
+        }
+    }
+
+    writer.u32(program.functions.len() as u32);
+    for function in &program.functions {
+        writer.u8(function.arity);
+        writer.u32(function.slots);
+
+        writer.u32(function.captures.len() as u32);
+        for capture in &function.captures {
+            match capture {
+                Capture::Local(slot) => {
+                    writer.u8(0);
+                    writer.u32(*slot);
+                }
+                Capture::Capture(index) => {
+                    writer.u8(1);
+                    writer.u32(*index);
+                }
+            }
+        }
+
+        writer.u32(function.code.len() as u32);
+        for (instr, span) in function.code.iter().zip(&function.spans) {
+            writer.instr(*instr);
+            writer.span(*span);
+        }
+    }
+
+    writer.0
+}
+
+// reads a `.caeb` file, checking it can run without the VM ever going wrong
+pub fn decode(bytes: &[u8]) -> Result<Program> {
+    ensure!(
+        bytes.starts_with(MAGIC),
+        "this isn't a caelis bytecode file"
+    );
+    let mut reader = Reader {
+        bytes,
+        pos: MAGIC.len(),
+    };
+
+    let version = reader.u16()?;
+    ensure!(
+        version == VERSION,
+        "this was compiled for version {version} of the bytecode, but only version {VERSION} can be run"
+    );
+
+    let file = reader.str()?;
+    let source = ArcStr::from(reader.str()?);
+
+    let mut globals = Vec::new();
+    for _ in 0..reader.u32()? {
+        let name = reader.str()?;
+        let kind = match reader.u8()? {
+            0 => GlobalKind::Value(reader.u32()?),
+            1 => {
+                let mut fields = Vec::new();
+                for _ in 0..reader.u32()? {
+                    fields.push(reader.str()?);
+                }
+                GlobalKind::Constructor(fields)
+            }
+            2 => GlobalKind::Host,
+            tag => bail!("unknown kind of definition {tag}"),
+        };
+
+        globals.push(Global { name, kind });
+    }
+
+    let mut functions = Vec::new();
+    for _ in 0..reader.u32()? {
+        let arity = reader.u8()?;
+        let slots = reader.u32()?;
+
+        let mut captures = Vec::new();
+        for _ in 0..reader.u32()? {
+            captures.push(match reader.u8()? {
+                0 => Capture::Local(reader.u32()?),
+                1 => Capture::Capture(reader.u32()?),
+                tag => bail!("unknown kind of capture {tag}"),
+            });
+        }
+
+        let (mut code, mut spans) = (Vec::new(), Vec::new());
+        for _ in 0..reader.u32()? {
+            code.push(reader.instr()?);
+            spans.push(reader.span()?);
+        }
+
+        functions.push(Function {
+            arity,
+            slots,
+            captures,
+            code,
+            spans,
+        });
+    }
+
+    ensure!(
+        reader.pos == bytes.len(),
+        "there's something after the end of the program"
+    );
+
+    let program = Program {
+        file,
+        source,
+        globals,
+        functions,
+    };
+    verify(&program).context("the bytecode is invalid")?;
+
+    Ok(program)
+}
+
+fn verify(program: &Program) -> Result<()> {
+    for global in &program.globals {
+        if let GlobalKind::Value(function) = global.kind {
+            let function = program
+                .functions
+                .get(function as usize)
+                .with_context(|| format!("`{}` refers to a missing function", global.name))?;
+            ensure!(
+                function.arity == 0 && function.captures.is_empty(),
+                "`{}` has to be worked out by a function of nothing",
+                global.name
+            );
+        }
+    }
+
+    for (index, function) in program.functions.iter().enumerate() {
+        verify_function(program, function).with_context(|| format!("in function {index}"))?;
+    }
+
+    Ok(())
+}
+
+// works out how many values are on the stack before each instruction, which has to be the same
+// however it's reached. jumps only go forwards, so one pass does it
+fn verify_function(program: &Program, function: &Function) -> Result<()> {
+    ensure!(function.arity <= 1, "functions take at most one argument");
+    ensure!(
+        function.slots >= function.arity as u32,
+        "there's no slot for the argument"
+    );
+
+    let len = function.code.len();
+    let mut depths: Vec<Option<usize>> = vec![None; len + 1];
+    depths[0] = Some(0);
+
+    for (pc, (instr, span)) in function.code.iter().zip(&function.spans).enumerate() {
+        verify_span(program, *span)?;
+
+        // code nothing jumps to can't run
+        let Some(depth) = depths[pc] else {
+            continue;
+        };
+        let (pops, pushes) = instr.stack_effect();
+        ensure!(
+            depth >= pops,
+            "instruction {pc} needs more values than there are"
+        );
+
+        let after = depth - pops + pushes;
+        let mut arrive = |target: u32| -> Result<()> {
+            let target = target as usize;
+            ensure!(
+                target > pc && target <= len,
+                "instruction {pc} jumps somewhere it can't"
+            );
+            match depths[target] {
+                Some(existing) => ensure!(
+                    existing == after,
+                    "instruction {target} can be reached with different amounts on the stack"
+                ),
+                None => depths[target] = Some(after),
+            }
+            Ok(())
+        };
+        let next = match *instr {
+            Instr::JumpUnless(target) => {
+                arrive(target)?;
+                Some(after)
+            }
+            Instr::Jump(target) => {
+                arrive(target)?;
+                None
+            }
+            Instr::Return => {
+                ensure!(
+                    depth == 1,
+                    "instruction {pc} returns with {depth} values on the stack"
+                );
+                None
+            }
+            Instr::Call(span) | Instr::Field(span) => {
+                verify_span(program, span)?;
+                Some(after)
+            }
+            _ => Some(after),
+        };
+
+        if let Some(next) = next {
+            match depths[pc + 1] {
+                Some(existing) => ensure!(
+                    existing == next,
+                    "instruction {} can be reached with different amounts on the stack",
+                    pc + 1
+                ),
+                None => depths[pc + 1] = Some(next),
+            }
+        }
+
+        match *instr {
+            Instr::Global(index) => ensure!(
+                (index as usize) < program.globals.len(),
+                "instruction {pc} refers to a missing definition"
+            ),
+            Instr::Local(slot) | Instr::Cell(slot) => ensure!(
+                slot < function.slots,
+                "instruction {pc} refers to a missing slot"
+            ),
+            Instr::Capture(index) => ensure!(
+                (index as usize) < function.captures.len(),
+                "instruction {pc} refers to a missing capture"
+            ),
+            Instr::Closure(inner) => verify_closure(program, function, inner, 1)?,
+            Instr::Thunk(slot, inner) => {
+                ensure!(
+                    slot < function.slots,
+                    "instruction {pc} refers to a missing slot"
+                );
+                verify_closure(program, function, inner, 0)?;
+            }
+            _ => (),
+        }
+    }
+
+    ensure!(depths[len].is_none(), "the code runs off the end");
+
+    // the VM makes room for every slot before the function starts, so there can't be any the code
+    // doesn't use
+    let used = function
+        .code
+        .iter()
+        .filter_map(|instr| match *instr {
+            Instr::Local(slot) | Instr::Cell(slot) | Instr::Thunk(slot, _) => Some(slot + 1),
+            _ => None,
+        })
+        .fold(function.arity as u32, u32::max);
+    ensure!(
+        function.slots <= used,
+        "there are {} slots but only {used} get used",
+        function.slots
+    );
+
+    Ok(())
+}
+
+// `inner` gets made into a closure inside `outer`, so it can only capture what `outer` has
+fn verify_closure(program: &Program, outer: &Function, inner: u32, arity: u8) -> Result<()> {
+    let inner = program
+        .functions
+        .get(inner as usize)
+        .context("a closure is made of a missing function")?;
+    ensure!(
+        inner.arity == arity,
+        "a closure is made of a function with the wrong arity"
+    );
+
+    for capture in &inner.captures {
+        match *capture {
+            Capture::Local(slot) => {
+                ensure!(slot < outer.slots, "a closure captures a missing slot")
+            }
+            Capture::Capture(index) => ensure!(
+                (index as usize) < outer.captures.len(),
+                "a closure captures a missing capture"
+            ),
+        }
+    }
+
+    Ok(())
+}
+
+fn verify_span(program: &Program, span: Span) -> Result<()> {
+    let range = span.range();
+
+    ensure!(
+        range.start <= range.end
+            && program.source.is_char_boundary(range.start)
+            && program.source.is_char_boundary(range.end),
+        "a span is outside the source"
+    );
+
+    Ok(())
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend(value.to_le_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.0.extend(value.as_bytes());
+    }
+
+    fn span(&mut self, span: Span) {
+        self.u32(span.start);
+        self.u32(span.end);
+    }
+
+    fn instr(&mut self, instr: Instr) {
+        match instr {
+            Instr::Int(value) => {
+                self.u8(0);
+                self.0.extend(value.to_le_bytes());
+            }
+            Instr::Float(value) => {
+                self.u8(1);
+                self.0.extend(value.to_le_bytes());
+            }
+            Instr::Bool(value) => {
+                self.u8(2);
+                self.u8(value as u8);
+            }
+            Instr::Builtin(builtin) => {
+                self.u8(3);
+                self.builtin(builtin);
+            }
+            Instr::Global(index) => {
+                self.u8(4);
+                self.u32(index);
+            }
+            Instr::Local(slot) => {
+                self.u8(5);
+                self.u32(slot);
+            }
+            Instr::Capture(index) => {
+                self.u8(6);
+                self.u32(index);
+            }
+            Instr::Closure(function) => {
+                self.u8(7);
+                self.u32(function);
+            }
+            Instr::Cell(slot) => {
+                self.u8(8);
+                self.u32(slot);
+            }
+            Instr::Thunk(slot, function) => {
+                self.u8(9);
+                self.u32(slot);
+                self.u32(function);
+            }
+            Instr::Call(func) => {
+                self.u8(10);
+                self.span(func);
+            }
+            Instr::Field(field) => {
+                self.u8(11);
+                self.span(field);
+            }
+            Instr::JumpUnless(target) => {
+                self.u8(12);
+                self.u32(target);
+            }
+            Instr::Jump(target) => {
+                self.u8(13);
+                self.u32(target);
+            }
+            Instr::Return => self.u8(14),
+            Instr::Op(builtin) => {
+                self.u8(15);
+                self.builtin(builtin);
+            }
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin) {
+        let index = BUILTINS.iter().position(|(_, b)| *b == builtin);
+        self.u8(index.expect("every built-in is in the table") as u8);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8]> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(len))
+            .context("the file ends too soon")?;
+        self.pos += len;
+
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self
+            .take(N)?
+            .try_into()
+            .expect("`take` gives back as much as it's asked for"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn str(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+
+        String::from_utf8(self.take(len)?.to_vec()).context("a string isn't valid UTF-8")
+    }
+
+    fn span(&mut self) -> Result<Span> {
+        Ok(Span {
+            start: self.u32()?,
+            end: self.u32()?,
+        })
+    }
+
+    fn builtin(&mut self) -> Result<Builtin> {
+        let index = self.u8()? as usize;
+        let (_, builtin) = BUILTINS.get(index).context("unknown built-in")?;
+
+        Ok(*builtin)
+    }
+
+    fn instr(&mut self) -> Result<Instr> {
+        Ok(match self.u8()? {
+            0 => Instr::Int(i64::from_le_bytes(self.array()?)),
+            1 => Instr::Float(f64::from_le_bytes(self.array()?)),
+            2 => Instr::Bool(self.u8()? != 0),
+            3 => Instr::Builtin(self.builtin()?),
+            4 => Instr::Global(self.u32()?),
+            5 => Instr::Local(self.u32()?),
+            6 => Instr::Capture(self.u32()?),
+            7 => Instr::Closure(self.u32()?),
+            8 => Instr::Cell(self.u32()?),
+            9 => Instr::Thunk(self.u32()?, self.u32()?),
+            10 => Instr::Call(self.span()?),
+            11 => Instr::Field(self.span()?),
+            12 => Instr::JumpUnless(self.u32()?),
+            13 => Instr::Jump(self.u32()?),
+            14 => Instr::Return,
+            15 => Instr::Op(self.builtin()?),
+            opcode => bail!("unknown instruction {opcode}"),
+        })
+    }
+}
@@ -0,0 +1,132 @@
+use std::ops::Range;
+
+use arcstr::{ArcStr, Substr};
+
+use crate::value::Builtin;
+
+mod compile;
+mod format;
+mod vm;
+
+pub use compile::{compile, compile_hosted, compile_tests};
+pub use format::{decode, encode};
+pub use vm::{Value, Vm};
+
+// a compiled program, which is what a `.caeb` file holds. it runs the same as the interpreter would,
+// definitions included: they're still only worked out the first time they're needed
+pub struct Program {
+    // the file this was compiled from and its text, so errors can point at the code that caused them
+    pub file: String,
+    pub source: ArcStr,
+    pub globals: Vec<Global>,
+    pub functions: Vec<Function>,
+}
+
+pub struct Global {
+    pub name: String,
+    pub kind: GlobalKind,
+}
+
+pub enum GlobalKind {
+    // the function that works the value out, which takes no argument
+    Value(u32),
+    // a type's name, with its fields
+    Constructor(Vec<String>),
+    // a function the program embedding caelis gives the VM before it runs
+    Host,
+}
+
+pub struct Function {
+    // 1 for a function, 0 for a definition that's waiting to be worked out
+    pub arity: u8,
+    // the parameter is slot 0, the rest are for `let` bindings
+    pub slots: u32,
+    // what a closure copies from the function that creates it
+    pub captures: Vec<Capture>,
+    pub code: Vec<Instr>,
+    // where in the source each instruction came from
+    pub spans: Vec<Span>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capture {
+    Local(u32),
+    Capture(u32),
+}
+
+// instructions work on a stack of values. a function leaves exactly its result behind when it returns
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instr {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Builtin(Builtin),
+    Global(u32),
+    Local(u32),
+    Capture(u32),
+    // makes a closure over a function
+    Closure(u32),
+    // puts an empty cell for a `let` binding in a slot, then fills it with a function that works it out.
+    // they're separate so bindings can refer to each other
+    Cell(u32),
+    Thunk(u32, u32),
+    // calls a function with an argument, which is on top of it. the span is the function's
+    Call(Span),
+    // the span is the field's name
+    Field(Span),
+    // a built-in given everything it takes, which are on the stack in order
+    Op(Builtin),
+    JumpUnless(u32),
+    Jump(u32),
+    Return,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct Span {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Program {
+    pub fn text(&self, span: Span) -> Substr {
+        self.source.substr(span.range())
+    }
+}
+
+impl Instr {
+    // how many values it takes off the stack and how many it puts back
+    pub fn stack_effect(self) -> (usize, usize) {
+        match self {
+            Instr::Call(_) => (2, 1),
+            Instr::Field(_) => (1, 1),
+            Instr::Op(builtin) => (builtin.arity(), 1),
+            Instr::JumpUnless(_) | Instr::Return => (1, 0),
+            Instr::Cell(_) | Instr::Thunk(..) | Instr::Jump(_) => (0, 0),
+            Instr::Int(_)
+            | Instr::Float(_)
+            | Instr::Bool(_)
+            | Instr::Builtin(_)
+            | Instr::Global(_)
+            | Instr::Local(_)
+            | Instr::Capture(_)
+            | Instr::Closure(_) => (0, 1),
+        }
+    }
+}
+
+impl Span {
+    pub fn range(self) -> Range<usize> {
+        self.start as usize..self.end as usize
+    }
+}
+
+impl From<&Substr> for Span {
+    fn from(text: &Substr) -> Self {
+        let range = text.range();
+
+        Span {
+            start: range.start as u32,
+            end: range.end as u32,
+        }
+    }
+}
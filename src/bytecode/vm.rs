@@ -0,0 +1,379 @@
+use std::{cell::RefCell, mem::size_of, rc::Rc};
+
+use super::{Capture, GlobalKind, Instr, Program, Span};
+use crate::{
+    diagnostic::Code,
+    limits::{Limits, Meter},
+    value::{self, Constructor, RuntimeError},
+};
+
+pub type Value = value::Value<Rc<Closure>>;
+
+pub struct Closure {
+    function: u32,
+    captures: Vec<Slot>,
+}
+
+#[derive(Clone)]
+enum Slot {
+    Empty,
+    Value(Value),
+    // a definition, which is shared with every closure that captured it
+    Cell(Rc<RefCell<Lazy>>),
+}
+
+enum Lazy {
+    Thunk(Rc<Closure>),
+    Evaluating,
+    Value(Value),
+    // a host function that hasn't been provided
+    Host,
+}
+
+struct Frame {
+    closure: Rc<Closure>,
+    pc: usize,
+    slots: Vec<Slot>,
+    // the definition this is working out, which gets the result when it returns
+    fill: Option<Rc<RefCell<Lazy>>>,
+}
+
+// calls are kept on the heap rather than the stack, so deep recursion doesn't need a bigger one
+pub struct Vm<'a> {
+    program: &'a Program,
+    globals: Vec<Rc<RefCell<Lazy>>>,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    // for everything the VM ever runs, so a VM that's been stopped stays stopped
+    meter: Meter,
+}
+
+impl<'a> Vm<'a> {
+    // the program has to have been verified, which `decode` does for files
+    pub fn new(program: &'a Program) -> Self {
+        Self::with_limits(program, Limits::default())
+    }
+
+    pub fn with_limits(program: &'a Program, limits: Limits) -> Self {
+        let globals = program
+            .globals
+            .iter()
+            .map(|global| {
+                let lazy = match &global.kind {
+                    GlobalKind::Value(function) => Lazy::Thunk(Rc::new(Closure {
+                        function: *function,
+                        captures: Vec::new(),
+                    })),
+                    GlobalKind::Constructor(fields) => {
+                        Lazy::Value(Constructor::value(global.name.clone(), fields.clone()))
+                    }
+                    GlobalKind::Host => Lazy::Host,
+                };
+
+                Rc::new(RefCell::new(lazy))
+            })
+            .collect();
+
+        Self {
+            program,
+            globals,
+            stack: Vec::new(),
+            frames: Vec::new(),
+            meter: Meter::new(limits),
+        }
+    }
+
+    // works out a top-level definition, if there is one with that name
+    pub fn get(&mut self, name: &str) -> Option<Result<Value, RuntimeError>> {
+        let index = self
+            .program
+            .globals
+            .iter()
+            .rposition(|global| global.name == name)?;
+        let cell = self.globals[index].clone();
+
+        Some(self.force(cell))
+    }
+
+    // works out a function of nothing that isn't one of the program's definitions, like a test
+    pub fn eval(&mut self, function: u32) -> Result<Value, RuntimeError> {
+        let closure = Rc::new(Closure {
+            function,
+            captures: Vec::new(),
+        });
+
+        self.force(Rc::new(RefCell::new(Lazy::Thunk(closure))))
+    }
+
+    // gives the program a host function it was compiled to expect. a definition with the same name
+    // shadows it, and keeps its place
+    pub fn provide(&mut self, name: &str, value: Value) {
+        let index = self
+            .program
+            .globals
+            .iter()
+            .rposition(|global| global.name == name && matches!(global.kind, GlobalKind::Host));
+
+        if let Some(index) = index {
+            *self.globals[index].borrow_mut() = Lazy::Value(value);
+        }
+    }
+
+    // calls a function from outside the program, which has nowhere in it to point errors at
+    pub fn call(&mut self, func: Value, arg: Value) -> Result<Value, RuntimeError> {
+        match func {
+            Value::Function(closure) => {
+                let (base, stack) = (self.frames.len(), self.stack.len());
+                let result = self
+                    .enter(closure, Some(arg), None, Span::default())
+                    .and_then(|()| self.run(base));
+
+                self.settle(base, stack, result)
+            }
+            func => {
+                let text = self.program.text(Span::default());
+                func.apply(arg, &text, &text)
+            }
+        }
+    }
+
+    fn force(&mut self, cell: Rc<RefCell<Lazy>>) -> Result<Value, RuntimeError> {
+        let (base, stack) = (self.frames.len(), self.stack.len());
+        let result = self
+            .load(Slot::Cell(cell), Span::default())
+            .and_then(|()| self.run(base));
+
+        self.settle(base, stack, result)
+    }
+
+    // anything half done when something goes wrong is thrown away, the definitions it was working out
+    // included, once the error knows what they were
+    fn settle(
+        &mut self,
+        base: usize,
+        stack: usize,
+        result: Result<Value, RuntimeError>,
+    ) -> Result<Value, RuntimeError> {
+        result.map_err(|mut e| {
+            // every frame but the innermost is waiting on the instruction before the one it'll go on
+            // with, which is the call or the definition that made the next
+            for frame in self.frames[base..].iter().rev().skip(1) {
+                let function = &self.program.functions[frame.closure.function as usize];
+                e = e.called_from(&self.program.text(function.spans[frame.pc - 1]));
+            }
+
+            self.frames.truncate(base);
+            self.stack.truncate(stack);
+
+            e
+        })
+    }
+
+    // runs until every frame above `base` has returned
+    fn run(&mut self, base: usize) -> Result<Value, RuntimeError> {
+        let program = self.program;
+
+        while self.frames.len() > base {
+            let frame = self.frames.last_mut().expect("there's a frame to run");
+            let function = &program.functions[frame.closure.function as usize];
+            let instr = function.code[frame.pc];
+            let span = function.spans[frame.pc];
+            frame.pc += 1;
+
+            self.meter.step().map_err(|e| e.at(&program.text(span)))?;
+
+            match instr {
+                Instr::Int(value) => self.stack.push(Value::Int(value)),
+                Instr::Float(value) => self.stack.push(Value::Float(value)),
+                Instr::Bool(value) => self.stack.push(Value::Bool(value)),
+                Instr::Builtin(builtin) => self.stack.push(Value::Builtin(builtin, Vec::new())),
+                Instr::Global(index) => {
+                    let cell = self.globals[index as usize].clone();
+                    self.load(Slot::Cell(cell), span)?;
+                }
+                Instr::Local(slot) => {
+                    let slot = frame.slots[slot as usize].clone();
+                    self.load(slot, span)?;
+                }
+                Instr::Capture(index) => {
+                    let slot = frame.closure.captures[index as usize].clone();
+                    self.load(slot, span)?;
+                }
+                Instr::Closure(function) => {
+                    let closure = self.closure(function, span)?;
+                    self.stack.push(Value::Function(closure));
+                }
+                Instr::Cell(slot) => {
+                    self.meter
+                        .allocate(size_of::<RefCell<Lazy>>())
+                        .map_err(|e| e.at(&program.text(span)))?;
+                    let frame = self.frames.last_mut().expect("there's a frame to run");
+                    frame.slots[slot as usize] =
+                        Slot::Cell(Rc::new(RefCell::new(Lazy::Evaluating)));
+                }
+                Instr::Thunk(slot, function) => {
+                    let closure = self.closure(function, span)?;
+                    let frame = self.frames.last_mut().expect("there's a frame to run");
+
+                    if let Slot::Cell(cell) = &frame.slots[slot as usize] {
+                        *cell.borrow_mut() = Lazy::Thunk(closure);
+                    }
+                }
+                Instr::Call(func) => {
+                    // nothing's left to do in a frame that returns what it calls, so the call can take
+                    // its place
+                    let tail = function.code[frame.pc] == Instr::Return && frame.fill.is_none();
+                    let arg = self.pop();
+
+                    match self.pop() {
+                        Value::Function(closure) => {
+                            if tail {
+                                self.frames.pop();
+                            }
+
+                            self.enter(closure, Some(arg), None, span)?;
+                        }
+                        callee => {
+                            let value =
+                                callee.apply(arg, &program.text(span), &program.text(func))?;
+                            self.meter
+                                .allocate(value.footprint())
+                                .map_err(|e| e.at(&program.text(span)))?;
+                            self.stack.push(value);
+                        }
+                    }
+                }
+                Instr::Field(field) => {
+                    let value = self
+                        .pop()
+                        .field(&program.text(field), &program.text(span))?;
+                    self.stack.push(value);
+                }
+                Instr::Op(builtin) => {
+                    let start = self.stack.len() - builtin.arity();
+                    let value = builtin.run(&self.stack[start..], &program.text(span))?;
+                    self.stack.truncate(start);
+                    self.stack.push(value);
+                }
+                Instr::JumpUnless(target) => {
+                    if !self.pop().condition(&program.text(span))? {
+                        self.frames.last_mut().expect("there's a frame to run").pc =
+                            target as usize;
+                    }
+                }
+                Instr::Jump(target) => frame.pc = target as usize,
+                Instr::Return => {
+                    let frame = self.frames.pop().expect("there's a frame to run");
+
+                    if let Some(cell) = frame.fill {
+                        let value = self.stack.last().expect("a function returns a value");
+                        *cell.borrow_mut() = Lazy::Value(value.clone());
+                    }
+                }
+            }
+        }
+
+        Ok(self.pop())
+    }
+
+    // pushes what a slot holds, or starts working it out if it hasn't been yet
+    fn load(&mut self, slot: Slot, span: Span) -> Result<(), RuntimeError> {
+        let cell = match slot {
+            Slot::Value(value) => {
+                self.stack.push(value);
+                return Ok(());
+            }
+            Slot::Cell(cell) => cell,
+            Slot::Empty => return Err(self.recursive(span)),
+        };
+        let lazy = std::mem::replace(&mut *cell.borrow_mut(), Lazy::Evaluating);
+
+        match lazy {
+            Lazy::Value(value) => {
+                self.stack.push(value.clone());
+                *cell.borrow_mut() = Lazy::Value(value);
+            }
+            Lazy::Thunk(closure) => self.enter(closure, None, Some(cell), span)?,
+            Lazy::Evaluating => return Err(self.recursive(span)),
+            Lazy::Host => {
+                *cell.borrow_mut() = Lazy::Host;
+                let name = self.program.text(span);
+
+                return Err(RuntimeError::new(
+                    Code::UnboundName,
+                    format!("`{name}` is meant to come from the program running this, which didn't provide it"),
+                    &name,
+                    "this isn't defined in caelis",
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    // `span` is what's being called, or the definition being worked out
+    fn enter(
+        &mut self,
+        closure: Rc<Closure>,
+        arg: Option<Value>,
+        fill: Option<Rc<RefCell<Lazy>>>,
+        span: Span,
+    ) -> Result<(), RuntimeError> {
+        let function = &self.program.functions[closure.function as usize];
+        self.meter
+            .call(self.frames.len() + 1)
+            .and_then(|()| {
+                self.meter
+                    .allocate(size_of::<Frame>() + function.slots as usize * size_of::<Slot>())
+            })
+            .map_err(|e| e.at(&self.program.text(span)))?;
+        let mut slots = vec![Slot::Empty; function.slots as usize];
+
+        if let Some(arg) = arg {
+            slots[0] = Slot::Value(arg);
+        }
+
+        self.frames.push(Frame {
+            closure,
+            pc: 0,
+            slots,
+            fill,
+        });
+
+        Ok(())
+    }
+
+    fn closure(&mut self, function: u32, span: Span) -> Result<Rc<Closure>, RuntimeError> {
+        let captures = &self.program.functions[function as usize].captures;
+        self.meter
+            .allocate(size_of::<Closure>() + captures.len() * size_of::<Slot>())
+            .map_err(|e| e.at(&self.program.text(span)))?;
+
+        let frame = self.frames.last().expect("there's a frame to run");
+        let captures = captures
+            .iter()
+            .map(|capture| match capture {
+                Capture::Local(slot) => frame.slots[*slot as usize].clone(),
+                Capture::Capture(index) => frame.closure.captures[*index as usize].clone(),
+            })
+            .collect();
+
+        Ok(Rc::new(Closure { function, captures }))
+    }
+
+    // verifying the program makes sure nothing takes more off the stack than it put there
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("the stack has a value")
+    }
+
+    fn recursive(&self, span: Span) -> RuntimeError {
+        let name = self.program.text(span);
+
+        RuntimeError::new(
+            Code::RecursiveValue,
+            format!("`{name}` is defined in terms of itself"),
+            &name,
+            "this is needed to work out itself",
+        )
+    }
+}
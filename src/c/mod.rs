@@ -0,0 +1,482 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Write,
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    bytecode::{Capture, Function, GlobalKind, Instr, Program, Span},
+    diagnostic::SourceFile,
+    value::Builtin,
+};
+
+const RUNTIME: &str = include_str!("runtime.c");
+
+struct Emitter<'a> {
+    program: &'a Program,
+    source: SourceFile,
+    // the C names of the globals and functions
+    globals: Vec<String>,
+    functions: Vec<String>,
+    // every span an error can point at, which go in a table
+    spans: Vec<Span>,
+    span_indices: HashMap<Span, usize>,
+}
+
+// writes the program out as C99 that only needs a C compiler and libm. every bytecode function becomes
+// a C function, with a local for each place on its stack and each `let` binding, so it reads much like
+// the bytecode does
+pub fn emit(program: &Program) -> Result<String> {
+    let Some(main) = program.globals.iter().rposition(|g| g.name == "main") else {
+        bail!("`{}` has no `main` to run", program.file);
+    };
+    if let Some(host) = program
+        .globals
+        .iter()
+        .find(|g| matches!(g.kind, GlobalKind::Host))
+    {
+        bail!(
+            "`{}` needs `{}` from the program embedding it, which C can't call",
+            program.file,
+            host.name
+        );
+    }
+
+    let mut emitter = Emitter {
+        program,
+        source: SourceFile::new(&program.file, program.source.clone()),
+        globals: program
+            .globals
+            .iter()
+            .enumerate()
+            .map(|(i, global)| identifier(&format!("g{i}"), &global.name))
+            .collect(),
+        functions: function_names(program),
+        spans: Vec::new(),
+        span_indices: HashMap::new(),
+    };
+
+    let mut functions = String::new();
+    for index in 0..program.functions.len() {
+        emitter.function(&mut functions, index);
+    }
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* {}, compiled to C by caelis. build it with `cc -std=c99 {} -lm` */\n",
+        comment(&program.file),
+        comment(&program.file).replace(".cae", ".c")
+    )
+    .unwrap();
+    out.push_str(RUNTIME);
+
+    out.push_str("\nstatic const struct span spans[] = {\n");
+    if emitter.spans.is_empty() {
+        out.push_str("    {0, 0, \"\"},\n");
+    }
+    for span in &emitter.spans {
+        let (line, column) = emitter.source.line_column(span.start as usize);
+        let text = c_string(&program.text(*span));
+        writeln!(out, "    {{{line}, {column}, {text}}},").unwrap();
+    }
+    out.push_str("};\n\n");
+
+    for (i, global) in program.globals.iter().enumerate() {
+        if let GlobalKind::Constructor(fields) = &global.kind {
+            let fields_name = match fields.is_empty() {
+                true => "NULL".to_string(),
+                false => {
+                    let names: Vec<String> = fields.iter().map(|f| c_string(f)).collect();
+                    writeln!(
+                        out,
+                        "static const char *const fields{i}[] = {{{}}};",
+                        names.join(", ")
+                    )
+                    .unwrap();
+                    format!("fields{i}")
+                }
+            };
+
+            writeln!(
+                out,
+                "static const struct type type{i} = {{{}, {}, {fields_name}}};",
+                c_string(&global.name),
+                fields.len()
+            )
+            .unwrap();
+        }
+
+        writeln!(
+            out,
+            "static struct cell {}; /* {} */",
+            emitter.globals[i],
+            comment(&global.name)
+        )
+        .unwrap();
+    }
+
+    out.push('\n');
+    for name in &emitter.functions {
+        writeln!(out, "static value {name}(struct closure *self, value arg);").unwrap();
+    }
+
+    out.push_str(&functions);
+
+    out.push_str("\nint main(void) {\n");
+    writeln!(out, "    file_name = {};", c_string(&program.file)).unwrap();
+    for (i, global) in program.globals.iter().enumerate() {
+        let init = match &global.kind {
+            GlobalKind::Value(function) => format!(
+                "{{THUNK, closure({}, 0, NULL), {{0}}}}",
+                emitter.functions[*function as usize]
+            ),
+            GlobalKind::Constructor(_) => format!("{{DONE, NULL, constructor(&type{i})}}"),
+            GlobalKind::Host => unreachable!("programs with host functions aren't emitted"),
+        };
+        writeln!(out, "    {} = (struct cell){init};", emitter.globals[i]).unwrap();
+    }
+    writeln!(
+        out,
+        "\n    struct buf out = {{0}};\n    show(&out, force(&{}, NULL));\n    puts(out.data);\n    return 0;\n}}",
+        emitter.globals[main]
+    )
+    .unwrap();
+
+    Ok(out)
+}
+
+impl Emitter<'_> {
+    fn function(&mut self, out: &mut String, index: usize) {
+        let program = self.program;
+        let function = &program.functions[index];
+        let depths = depths(function);
+        let targets: HashSet<u32> = function
+            .code
+            .iter()
+            .filter_map(|instr| match instr {
+                Instr::Jump(target) | Instr::JumpUnless(target) => Some(*target),
+                _ => None,
+            })
+            .collect();
+        let cells = cell_names(program, function);
+
+        let body = function.spans.last().copied().unwrap_or_default();
+        let (line, _) = self.source.line_column(body.start as usize);
+        let text = program.text(body);
+        let excerpt: String = text
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(60)
+            .collect();
+        let more = if excerpt.len() < text.len() {
+            " ..."
+        } else {
+            ""
+        };
+
+        writeln!(out, "\n/* line {line}: {}{more} */", comment(&excerpt)).unwrap();
+        writeln!(
+            out,
+            "static value {}(struct closure *self, value arg) {{",
+            self.functions[index]
+        )
+        .unwrap();
+
+        if !cells.is_empty() {
+            let mut slots: Vec<(&u32, &String)> = cells.iter().collect();
+            slots.sort();
+            let decls: Vec<String> = slots.iter().map(|(_, n)| format!("*{n} = NULL")).collect();
+            writeln!(out, "    struct cell {};", decls.join(", ")).unwrap();
+        }
+        let max_depth = depths.iter().flatten().max().copied().unwrap_or(0);
+        if max_depth > 0 {
+            let stack: Vec<String> = (0..max_depth).map(|i| format!("s{i}")).collect();
+            writeln!(out, "    value {};", stack.join(", ")).unwrap();
+        }
+
+        // every function has the same signature, whether it has an argument and captures or not
+        let captures = |capture: Capture| {
+            function.code.iter().any(|instr| match *instr {
+                Instr::Closure(inner) | Instr::Thunk(_, inner) => program.functions[inner as usize]
+                    .captures
+                    .contains(&capture),
+                _ => false,
+            })
+        };
+        let uses_self = !function.captures.is_empty()
+            && (function.code.iter().any(|i| matches!(i, Instr::Capture(_)))
+                || (0..function.captures.len() as u32).any(|i| captures(Capture::Capture(i))));
+        let uses_arg = function.arity == 1
+            && (function.code.contains(&Instr::Local(0)) || captures(Capture::Local(0)));
+        if !uses_self {
+            out.push_str("    (void)self;\n");
+        }
+        if !uses_arg {
+            out.push_str("    (void)arg;\n");
+        }
+        out.push('\n');
+
+        for (pc, (instr, span)) in function.code.iter().zip(&function.spans).enumerate() {
+            if targets.contains(&(pc as u32)) {
+                writeln!(out, "pc{pc}:").unwrap();
+            }
+
+            let Some(depth) = depths[pc] else {
+                continue;
+            };
+            let slot = |slot: u32| match (function.arity, slot) {
+                (1, 0) => "arg".to_string(),
+                _ => cells[&slot].clone(),
+            };
+            let top = depth.saturating_sub(1);
+
+            let statement = match *instr {
+                Instr::Int(value) => format!("s{depth} = integer({});", int(value)),
+                Instr::Float(value) => format!("s{depth} = floating({});", float(value)),
+                Instr::Bool(value) => format!("s{depth} = boolean({value});"),
+                Instr::Builtin(builtin) => format!("s{depth} = builtin({});", name(builtin)),
+                Instr::Global(index) => {
+                    let span = self.span(*span);
+                    format!(
+                        "s{depth} = force(&{}, {span});",
+                        self.globals[index as usize]
+                    )
+                }
+                Instr::Local(0) if function.arity == 1 => format!("s{depth} = arg;"),
+                Instr::Local(index) => {
+                    format!("s{depth} = force({}, {});", slot(index), self.span(*span))
+                }
+                Instr::Capture(index) => format!(
+                    "s{depth} = force(self->captures[{index}], {});",
+                    self.span(*span)
+                ),
+                Instr::Closure(inner) => format!(
+                    "s{depth} = function({});",
+                    self.closure(function, inner, slot)
+                ),
+                Instr::Cell(index) => format!("{} = cell();", slot(index)),
+                Instr::Thunk(index, inner) => format!(
+                    "thunk({}, {});",
+                    slot(index),
+                    self.closure(function, inner, slot)
+                ),
+                Instr::Call(func) => format!(
+                    "s{} = call(s{}, s{top}, {}, {});",
+                    depth - 2,
+                    depth - 2,
+                    self.span(*span),
+                    self.span(func)
+                ),
+                Instr::Field(field) => format!(
+                    "s{top} = field(s{top}, {}, {});",
+                    self.span(field),
+                    self.span(*span)
+                ),
+                Instr::Op(builtin) => {
+                    let first = depth - builtin.arity();
+                    let args: Vec<String> = (first..depth).map(|i| format!("s{i}")).collect();
+                    format!(
+                        "s{first} = run({}, (value[]){{{}}}, {});",
+                        name(builtin),
+                        args.join(", "),
+                        self.span(*span)
+                    )
+                }
+                Instr::JumpUnless(target) => format!(
+                    "if (!condition(s{top}, {})) goto pc{target};",
+                    self.span(*span)
+                ),
+                Instr::Jump(target) => format!("goto pc{target};"),
+                Instr::Return => format!("return s{top};"),
+            };
+
+            writeln!(out, "    {statement}").unwrap();
+        }
+
+        out.push_str("}\n");
+    }
+
+    // the arguments `function` and `thunk` need to make a closure over `inner`
+    fn closure(&self, outer: &Function, inner: u32, slot: impl Fn(u32) -> String) -> String {
+        let captures: Vec<String> = self.program.functions[inner as usize]
+            .captures
+            .iter()
+            .map(|capture| match *capture {
+                Capture::Local(0) if outer.arity == 1 => "filled(arg)".to_string(),
+                Capture::Local(index) => slot(index),
+                Capture::Capture(index) => format!("self->captures[{index}]"),
+            })
+            .collect();
+        let name = &self.functions[inner as usize];
+
+        match captures.is_empty() {
+            true => format!("{name}, 0, NULL"),
+            false => format!(
+                "{name}, {}, (struct cell *[]){{{}}}",
+                captures.len(),
+                captures.join(", ")
+            ),
+        }
+    }
+
+    fn span(&mut self, span: Span) -> String {
+        let index = *self.span_indices.entry(span).or_insert_with(|| {
+            self.spans.push(span);
+            self.spans.len() - 1
+        });
+
+        format!("&spans[{index}]")
+    }
+}
+
+// how deep the stack is before each instruction, or `None` if nothing reaches it
+fn depths(function: &Function) -> Vec<Option<usize>> {
+    let mut depths = vec![None; function.code.len() + 1];
+    depths[0] = Some(0);
+
+    for (pc, instr) in function.code.iter().enumerate() {
+        let Some(depth) = depths[pc] else {
+            continue;
+        };
+        let (pops, pushes) = instr.stack_effect();
+        let after = depth - pops + pushes;
+
+        match instr {
+            Instr::JumpUnless(target) => depths[*target as usize] = Some(after),
+            Instr::Jump(target) => depths[*target as usize] = Some(after),
+            _ => (),
+        }
+
+        if !matches!(instr, Instr::Jump(_) | Instr::Return) {
+            depths[pc + 1] = Some(after);
+        }
+    }
+
+    depths
+}
+
+// names functions after the definition they're part of, which is easier to follow than a number
+fn function_names(program: &Program) -> Vec<String> {
+    let mut names = vec![String::new(); program.functions.len()];
+
+    for global in &program.globals {
+        if let GlobalKind::Value(function) = global.kind {
+            names[function as usize] = global.name.clone();
+        }
+    }
+
+    // a function is always compiled before the one that makes a closure over it, so going backwards
+    // names the outer one first
+    for index in (0..program.functions.len()).rev() {
+        let function = &program.functions[index];
+        let mut bindings = HashMap::new();
+
+        for (instr, span) in function.code.iter().zip(&function.spans) {
+            match *instr {
+                Instr::Cell(slot) => {
+                    bindings.insert(slot, program.text(*span).to_string());
+                }
+                Instr::Closure(inner) => names[inner as usize] = names[index].clone(),
+                Instr::Thunk(slot, inner) => names[inner as usize] = bindings[&slot].clone(),
+                _ => (),
+            }
+        }
+    }
+
+    names
+        .iter()
+        .enumerate()
+        .map(|(i, name)| identifier(&format!("f{i}"), name))
+        .collect()
+}
+
+// the C names of the cells for a function's `let` bindings
+fn cell_names(program: &Program, function: &Function) -> HashMap<u32, String> {
+    function
+        .code
+        .iter()
+        .zip(&function.spans)
+        .filter_map(|(instr, span)| match instr {
+            Instr::Cell(slot) => {
+                Some((*slot, identifier(&format!("c{slot}"), &program.text(*span))))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// `prefix` keeps it unique, the rest is whatever of `name` C allows
+fn identifier(prefix: &str, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .collect();
+
+    match name.is_empty() {
+        true => prefix.to_string(),
+        false => format!("{prefix}_{name}"),
+    }
+}
+
+fn int(value: i64) -> String {
+    match value {
+        i64::MIN => "INT64_MIN".to_string(),
+        _ => format!("INT64_C({value})"),
+    }
+}
+
+fn float(value: f64) -> String {
+    match value {
+        f64::INFINITY => "INFINITY".to_string(),
+        f64::NEG_INFINITY => "-INFINITY".to_string(),
+        _ if value.is_nan() => "NAN".to_string(),
+        _ => format!("{value:?}"),
+    }
+}
+
+fn name(builtin: Builtin) -> &'static str {
+    match builtin {
+        Builtin::Add => "ADD",
+        Builtin::Sub => "SUB",
+        Builtin::Mul => "MUL",
+        Builtin::Div => "DIV",
+        Builtin::Rem => "REM",
+        Builtin::Eq => "EQ",
+        Builtin::Ne => "NE",
+        Builtin::Lt => "LT",
+        Builtin::Le => "LE",
+        Builtin::Gt => "GT",
+        Builtin::Ge => "GE",
+        Builtin::And => "AND",
+        Builtin::Or => "OR",
+        Builtin::Not => "NOT",
+        Builtin::Assert => "ASSERT",
+    }
+}
+
+fn c_string(text: &str) -> String {
+    let mut out = String::from("\"");
+
+    for byte in text.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b'\n' => out.push_str("\\n"),
+            // `??` can start a trigraph
+            b'?' => out.push_str("\\?"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => write!(out, "\\{byte:03o}").unwrap(),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+fn comment(text: &str) -> String {
+    text.replace("*/", "* /")
+}
@@ -0,0 +1,441 @@
+/* the runtime for programs compiled to C, which goes at the top of every file caelis writes. it does
+   what src/value.rs does for the interpreter and the VM, and has to agree with it. memory is never
+   freed, a program runs once and then exits. calls use the C stack, so recursion only goes as deep as
+   that lets it */
+
+#include <inttypes.h>
+#include <math.h>
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+enum tag { INT, FLOAT, BOOL, RECORD, FUNCTION, BUILTIN, CONSTRUCTOR };
+
+typedef struct value {
+    enum tag tag;
+    union {
+        int64_t i;
+        double f;
+        bool b;
+        struct record *record;
+        struct closure *closure;
+        struct partial *partial;
+    } as;
+} value;
+
+struct type {
+    const char *name;
+    size_t nfields;
+    const char *const *fields;
+};
+
+struct record {
+    const struct type *type;
+    value fields[];
+};
+
+/* a definition, which is worked out the first time it's needed */
+enum state { THUNK, EVALUATING, DONE };
+
+struct cell {
+    enum state state;
+    struct closure *thunk;
+    value value;
+};
+
+struct closure {
+    value (*code)(struct closure *self, value arg);
+    struct cell *captures[];
+};
+
+enum builtin { ADD, SUB, MUL, DIV, REM, EQ, NE, LT, LE, GT, GE, AND, OR, NOT, ASSERT };
+
+static const char *const builtin_names[] = {
+    "+", "-", "*", "/", "%", "==", "!=", "<", "<=", ">", ">=", "&&", "||", "not", "assert",
+};
+
+/* a built-in or a type's name, and whatever it's been given so far */
+struct partial {
+    enum builtin builtin;
+    const struct type *type;
+    size_t nargs;
+    value args[];
+};
+
+/* where something came from, for errors */
+struct span {
+    int line, column;
+    const char *text;
+};
+
+static const char *file_name;
+
+struct buf {
+    char *data;
+    size_t len, cap;
+};
+
+static void *alloc(size_t size) {
+    void *p = malloc(size);
+    if (!p) {
+        fputs("out of memory\n", stderr);
+        exit(1);
+    }
+    return p;
+}
+
+static void put(struct buf *b, const char *format, ...) {
+    va_list args;
+    va_start(args, format);
+    int len = vsnprintf(NULL, 0, format, args);
+    va_end(args);
+
+    if (b->len + len + 1 > b->cap) {
+        b->cap = (b->len + len + 1) * 2;
+        char *data = alloc(b->cap);
+        if (b->data) memcpy(data, b->data, b->len + 1);
+        free(b->data);
+        b->data = data;
+    }
+
+    va_start(args, format);
+    vsnprintf(b->data + b->len, len + 1, format, args);
+    va_end(args);
+    b->len += len;
+}
+
+static const char *format(const char *pattern, ...) {
+    va_list args;
+    va_start(args, pattern);
+    int len = vsnprintf(NULL, 0, pattern, args);
+    va_end(args);
+
+    char *text = alloc(len + 1);
+    va_start(args, pattern);
+    vsnprintf(text, len + 1, pattern, args);
+    va_end(args);
+    return text;
+}
+
+static void fail(const char *code, const struct span *span, const char *message, const char *label) {
+    fprintf(stderr, "[%s] Error: %s\n  at %s:%d:%d, %s\n", code, message, file_name, span->line, span->column,
+            label);
+    exit(1);
+}
+
+static value integer(int64_t i) { return (value){INT, {.i = i}}; }
+static value floating(double f) { return (value){FLOAT, {.f = f}}; }
+static value boolean(bool b) { return (value){BOOL, {.b = b}}; }
+
+/* the same as rust's `{:?}`: the shortest digits that read back the same, with a `.0` on whole numbers
+   and an exponent on very big and very small ones */
+static void show_float(struct buf *b, double f) {
+    if (isnan(f)) {
+        put(b, "NaN");
+        return;
+    }
+    if (signbit(f)) {
+        put(b, "-");
+        f = -f;
+    }
+    if (isinf(f)) {
+        put(b, "inf");
+        return;
+    }
+    if (f == 0) {
+        put(b, "0.0");
+        return;
+    }
+
+    char text[32];
+    for (int precision = 0; precision < 17; precision++) {
+        snprintf(text, sizeof text, "%.*e", precision, f);
+        if (strtod(text, NULL) == f) break;
+    }
+
+    char digits[32];
+    size_t ndigits = 0;
+    char *e = strchr(text, 'e');
+    for (char *c = text; c < e; c++)
+        if (*c != '.') digits[ndigits++] = *c;
+    while (ndigits > 1 && digits[ndigits - 1] == '0') ndigits--;
+    digits[ndigits] = 0;
+    int exponent = atoi(e + 1);
+
+    if (exponent < -4 || exponent >= 16) {
+        put(b, "%c", digits[0]);
+        if (ndigits > 1) put(b, ".%s", digits + 1);
+        put(b, "e%d", exponent);
+    } else if (exponent < 0) {
+        put(b, "0.");
+        for (int i = -1; i > exponent; i--) put(b, "0");
+        put(b, "%s", digits);
+    } else {
+        for (int i = 0; i <= exponent; i++) put(b, "%c", (size_t)i < ndigits ? digits[i] : '0');
+        put(b, ".%s", (size_t)exponent + 1 < ndigits ? digits + exponent + 1 : "0");
+    }
+}
+
+static void show(struct buf *b, value v) {
+    switch (v.tag) {
+    case INT:
+        put(b, "%" PRId64, v.as.i);
+        return;
+    case FLOAT:
+        show_float(b, v.as.f);
+        return;
+    case BOOL:
+        put(b, v.as.b ? "true" : "false");
+        return;
+    case RECORD: {
+        const struct type *type = v.as.record->type;
+        put(b, "%s", type->name);
+        for (size_t i = 0; i < type->nfields; i++) {
+            put(b, i ? ", %s = " : " { %s = ", type->fields[i]);
+            show(b, v.as.record->fields[i]);
+        }
+        if (type->nfields) put(b, " }");
+        return;
+    }
+    case FUNCTION:
+        put(b, "<function>");
+        return;
+    case BUILTIN:
+        put(b, "<built-in (%s)>", builtin_names[v.as.partial->builtin]);
+        return;
+    case CONSTRUCTOR:
+        put(b, "<constructor of %s>", v.as.partial->type->name);
+        return;
+    }
+}
+
+static const char *shown(value v) {
+    struct buf b = {0};
+    show(&b, v);
+    return b.data;
+}
+
+/* functions don't have a type until there's a type checker to give them one */
+static const char *kind(value v) {
+    switch (v.tag) {
+    case INT: return "Int";
+    case FLOAT: return "Float";
+    case BOOL: return "Bool";
+    case RECORD: return v.as.record->type->name;
+    default: return "function";
+    }
+}
+
+/* records are equal when every field is, which can't be said of records holding functions. -1 is
+   for those */
+static int equals(const struct record *a, const struct record *b) {
+    if (a->type != b->type) return 0;
+
+    int equal = 1;
+    for (size_t i = 0; i < a->type->nfields; i++) {
+        value x = a->fields[i], y = b->fields[i];
+        int field;
+        if (x.tag != y.tag) return -1;
+        switch (x.tag) {
+        case INT: field = x.as.i == y.as.i; break;
+        case FLOAT: field = x.as.f == y.as.f; break;
+        case BOOL: field = x.as.b == y.as.b; break;
+        case RECORD:
+            field = equals(x.as.record, y.as.record);
+            if (field < 0) return -1;
+            break;
+        default: return -1;
+        }
+        equal = equal && field;
+    }
+    return equal;
+}
+
+static size_t arity(enum builtin builtin) { return builtin == NOT || builtin == ASSERT ? 1 : 2; }
+
+/* ints wrap around like the machine ones. `&&` and `||` get both sides already worked out, there's
+   nothing to short-circuit in a language without side effects */
+static value run(enum builtin builtin, const value *args, const struct span *span) {
+    value a = args[0], b = args[arity(builtin) - 1];
+
+    if (builtin == NOT) {
+        if (a.tag == BOOL) return boolean(!a.as.b);
+    } else if (builtin == ASSERT) {
+        if (a.tag == BOOL && !a.as.b) fail("E0407", span, "assertion failed", "this is false");
+        if (a.tag == BOOL) return a;
+    } else if (a.tag == BOOL && b.tag == BOOL) {
+        switch (builtin) {
+        case AND: return boolean(a.as.b && b.as.b);
+        case OR: return boolean(a.as.b || b.as.b);
+        case EQ: return boolean(a.as.b == b.as.b);
+        case NE: return boolean(a.as.b != b.as.b);
+        default: break;
+        }
+    } else if (a.tag == RECORD && b.tag == RECORD && (builtin == EQ || builtin == NE)) {
+        int equal = equals(a.as.record, b.as.record);
+        if (equal >= 0) return boolean(builtin == EQ ? equal : !equal);
+    } else if (a.tag == INT && b.tag == INT) {
+        uint64_t x = (uint64_t)a.as.i, y = (uint64_t)b.as.i;
+        if ((builtin == DIV || builtin == REM) && b.as.i == 0)
+            fail("E0404", span, "division by zero", "the right-hand side of this is 0");
+        switch (builtin) {
+        case ADD: return integer((int64_t)(x + y));
+        case SUB: return integer((int64_t)(x - y));
+        case MUL: return integer((int64_t)(x * y));
+        case DIV: return integer(b.as.i == -1 ? (int64_t)(0 - x) : a.as.i / b.as.i);
+        case REM: return integer(b.as.i == -1 ? 0 : a.as.i % b.as.i);
+        case EQ: return boolean(a.as.i == b.as.i);
+        case NE: return boolean(a.as.i != b.as.i);
+        case LT: return boolean(a.as.i < b.as.i);
+        case LE: return boolean(a.as.i <= b.as.i);
+        case GT: return boolean(a.as.i > b.as.i);
+        case GE: return boolean(a.as.i >= b.as.i);
+        default: break;
+        }
+    } else if (a.tag == FLOAT && b.tag == FLOAT) {
+        switch (builtin) {
+        case ADD: return floating(a.as.f + b.as.f);
+        case SUB: return floating(a.as.f - b.as.f);
+        case MUL: return floating(a.as.f * b.as.f);
+        case DIV: return floating(a.as.f / b.as.f);
+        case REM: return floating(fmod(a.as.f, b.as.f));
+        case EQ: return boolean(a.as.f == b.as.f);
+        case NE: return boolean(a.as.f != b.as.f);
+        case LT: return boolean(a.as.f < b.as.f);
+        case LE: return boolean(a.as.f <= b.as.f);
+        case GT: return boolean(a.as.f > b.as.f);
+        case GE: return boolean(a.as.f >= b.as.f);
+        default: break;
+        }
+    }
+
+    const char *message = arity(builtin) == 1
+                              ? format("`%s` can't be used on `%s`", builtin_names[builtin], kind(a))
+                              : format("`%s` can't be used on `%s` and `%s`", builtin_names[builtin], kind(a), kind(b));
+    fail("E0403", span, message, "these don't go together");
+    return a;
+}
+
+static inline value builtin(enum builtin builtin) {
+    struct partial *partial = alloc(sizeof *partial);
+    *partial = (struct partial){builtin, NULL, 0};
+    return (value){BUILTIN, {.partial = partial}};
+}
+
+/* a type with no fields is just its one value */
+static inline value constructor(const struct type *type) {
+    if (!type->nfields) {
+        struct record *record = alloc(sizeof *record);
+        record->type = type;
+        return (value){RECORD, {.record = record}};
+    }
+
+    struct partial *partial = alloc(sizeof *partial);
+    *partial = (struct partial){0, type, 0};
+    return (value){CONSTRUCTOR, {.partial = partial}};
+}
+
+static struct closure *closure(value (*code)(struct closure *, value), size_t ncaptures,
+                               struct cell *const *captures) {
+    struct closure *closure = alloc(sizeof *closure + ncaptures * sizeof *captures);
+    closure->code = code;
+    if (ncaptures) memcpy(closure->captures, captures, ncaptures * sizeof *captures);
+    return closure;
+}
+
+static inline value function(value (*code)(struct closure *, value), size_t ncaptures,
+                             struct cell *const *captures) {
+    return (value){FUNCTION, {.closure = closure(code, ncaptures, captures)}};
+}
+
+/* an empty cell for a `let` binding, which gets its thunk once every binding has a cell */
+static inline struct cell *cell(void) {
+    struct cell *cell = alloc(sizeof *cell);
+    *cell = (struct cell){EVALUATING, NULL, {0}};
+    return cell;
+}
+
+/* a parameter that a closure captures */
+static inline struct cell *filled(value v) {
+    struct cell *cell = alloc(sizeof *cell);
+    *cell = (struct cell){DONE, NULL, v};
+    return cell;
+}
+
+static inline void thunk(struct cell *cell, value (*code)(struct closure *, value), size_t ncaptures,
+                         struct cell *const *captures) {
+    if (!cell) return;
+    cell->state = THUNK;
+    cell->thunk = closure(code, ncaptures, captures);
+}
+
+static value force(struct cell *cell, const struct span *span) {
+    if (!cell || cell->state == EVALUATING)
+        fail("E0405", span, format("`%s` is defined in terms of itself", span->text), "this is needed to work out itself");
+
+    if (cell->state == THUNK) {
+        cell->state = EVALUATING;
+        cell->value = cell->thunk->code(cell->thunk, (value){0});
+        cell->state = DONE;
+    }
+
+    return cell->value;
+}
+
+/* `call` is the whole call and `func` the part that's being called */
+static inline value call(value f, value arg, const struct span *call, const struct span *func) {
+    if (f.tag == FUNCTION) return f.as.closure->code(f.as.closure, arg);
+
+    if (f.tag == BUILTIN || f.tag == CONSTRUCTOR) {
+        struct partial *old = f.as.partial;
+        size_t needs = f.tag == BUILTIN ? arity(old->builtin) : old->type->nfields;
+        size_t nargs = old->nargs + 1;
+
+        if (nargs == needs && f.tag == BUILTIN) {
+            value args[2];
+            memcpy(args, old->args, old->nargs * sizeof *args);
+            args[old->nargs] = arg;
+            return run(old->builtin, args, call);
+        }
+
+        if (nargs == needs) {
+            struct record *record = alloc(sizeof *record + nargs * sizeof(value));
+            record->type = old->type;
+            memcpy(record->fields, old->args, old->nargs * sizeof(value));
+            record->fields[old->nargs] = arg;
+            return (value){RECORD, {.record = record}};
+        }
+
+        struct partial *partial = alloc(sizeof *partial + nargs * sizeof(value));
+        *partial = (struct partial){old->builtin, old->type, nargs};
+        memcpy(partial->args, old->args, old->nargs * sizeof(value));
+        partial->args[old->nargs] = arg;
+        return (value){f.tag, {.partial = partial}};
+    }
+
+    fail("E0402", func, format("`%s` isn't a function", shown(f)), "this is called like a function");
+    return f;
+}
+
+/* `record` is where the value came from and `field` the name after the `.` */
+static inline value field(value v, const struct span *field, const struct span *record) {
+    if (v.tag != RECORD)
+        fail("E0406", record, format("`%s` has no fields", shown(v)), format("this is `%s`, not a record", kind(v)));
+
+    const struct type *type = v.as.record->type;
+    for (size_t i = 0; i < type->nfields; i++)
+        if (!strcmp(type->fields[i], field->text)) return v.as.record->fields[i];
+
+    fail("E0406", field, format("`%s` has no field `%s`", type->name, field->text),
+         format("not a field of `%s`", type->name));
+    return v;
+}
+
+static inline bool condition(value v, const struct span *span) {
+    if (v.tag != BOOL)
+        fail("E0403", span, format("`if` needs a `Bool`, not `%s`", kind(v)), "this decides which branch is taken");
+    return v.as.b;
+}
@@ -0,0 +1,268 @@
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use arcstr::{ArcStr, Substr};
+use inkwell::{
+    basic_block::BasicBlock,
+    builder::Builder,
+    context::Context,
+    debug_info::{
+        debug_metadata_version, AsDIScope, DICompileUnit, DIFlags, DIFlagsConstants,
+        DILocalVariable, DILocation, DIScope, DISubprogram, DIType, DWARFEmissionKind,
+        DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Module},
+    values::PointerValue,
+};
+
+use crate::diagnostic::SourceFile;
+
+// DW_ATE_*, what a basic type's bits mean
+const BOOLEAN: u32 = 0x02;
+const FLOAT: u32 = 0x04;
+const SIGNED: u32 = 0x05;
+
+// the DWARF that lets gdb and lldb step through a caelis program. there's a compile unit for the file,
+// a subprogram for every definition and a lexical block for every `let`, and types and variables
+// are called what caelis calls them
+pub struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    source: SourceFile,
+    types: HashMap<String, DIType<'ctx>>,
+    // the text of each definition and `let`, in bytes, with the scope made for it. scopes come after
+    // the ones they're in
+    scopes: Vec<(Range<usize>, DIScope<'ctx>)>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    pub fn new(context: &'ctx Context, module: &Module<'ctx>, path: &str, src: &ArcStr) -> Self {
+        let path = Path::new(path);
+        let filename = path
+            .file_name()
+            .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+        let directory = path
+            .parent()
+            .map_or(String::new(), |dir| dir.to_string_lossy().into_owned());
+
+        module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+
+        // DWARF has no code for caelis, and C is what debuggers know best
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &filename,
+            &directory,
+            "caelis",
+            false,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+
+        let mut debug_info = Self {
+            builder,
+            unit,
+            source: SourceFile::new(path.display(), src.clone()),
+            types: HashMap::new(),
+            scopes: Vec::new(),
+        };
+
+        for (name, bits, encoding) in [
+            ("Int", 64, SIGNED),
+            ("Float", 64, FLOAT),
+            ("Bool", 8, BOOLEAN),
+        ] {
+            let ty = debug_info
+                .builder
+                .create_basic_type(name, bits, encoding, DIFlags::ZERO)
+                .expect("built-in types have names");
+            debug_info.types.insert(name.to_string(), ty.as_type());
+        }
+
+        debug_info
+    }
+
+    // DWARF's lines and columns start at 1, the same as diagnostics
+    pub fn line_column(&self, text: &Substr) -> (u32, u32) {
+        let (line, column) = self.source.line_column(text.range().start);
+
+        (line as u32, column as u32)
+    }
+
+    // a record and its fields, as `(name, type, size, offset)` with sizes and offsets in bits. the
+    // types of the fields have to have been described already
+    pub fn record(&mut self, name: &Substr, bits: u64, fields: &[(Substr, String, u64, u64)]) {
+        let (line, _) = self.line_column(name);
+        let file = self.unit.get_file();
+
+        let members: Vec<DIType> = fields
+            .iter()
+            .map(|(field, ty, bits, offset)| {
+                let (line, _) = self.line_column(field);
+
+                self.builder
+                    .create_member_type(
+                        self.unit.as_debug_info_scope(),
+                        field,
+                        file,
+                        line,
+                        *bits,
+                        0,
+                        *offset,
+                        DIFlags::PUBLIC,
+                        self.ty(ty),
+                    )
+                    .as_type()
+            })
+            .collect();
+
+        let record = self.builder.create_struct_type(
+            self.unit.as_debug_info_scope(),
+            name,
+            file,
+            line,
+            bits,
+            0,
+            DIFlags::PUBLIC,
+            None,
+            &members,
+            0,
+            None,
+            name,
+        );
+
+        self.types.insert(name.to_string(), record.as_type());
+    }
+
+    // a definition called `name` in `text`, which is `linkage_name` to the linker
+    pub fn subprogram(
+        &mut self,
+        text: &Substr,
+        name: &str,
+        linkage_name: &str,
+        params: &[String],
+        ret: &str,
+    ) -> DISubprogram<'ctx> {
+        let params: Vec<DIType> = params.iter().map(|param| self.ty(param)).collect();
+        let (line, _) = self.line_column(text);
+        let file = self.unit.get_file();
+
+        let subroutine =
+            self.builder
+                .create_subroutine_type(file, Some(self.ty(ret)), &params, DIFlags::ZERO);
+        let subprogram = self.builder.create_function(
+            self.unit.as_debug_info_scope(),
+            name,
+            Some(linkage_name),
+            file,
+            line,
+            subroutine,
+            false,
+            true,
+            line,
+            DIFlags::PUBLIC,
+            false,
+        );
+
+        self.scopes
+            .push((text.range(), subprogram.as_debug_info_scope()));
+        subprogram
+    }
+
+    // the block a `let` in `text` makes
+    pub fn lexical_block(&mut self, text: &Substr) -> DIScope<'ctx> {
+        let (line, column) = self.line_column(text);
+        let block = self
+            .builder
+            .create_lexical_block(self.scope(text), self.unit.get_file(), line, column)
+            .as_debug_info_scope();
+
+        self.scopes.push((text.range(), block));
+        block
+    }
+
+    // a parameter, counting from 1, or a `let` binding
+    pub fn variable(&self, name: &Substr, ty: &str, arg_no: Option<u32>) -> DILocalVariable<'ctx> {
+        let (line, _) = self.line_column(name);
+        let scope = self.scope(name);
+        let file = self.unit.get_file();
+
+        match arg_no {
+            Some(arg_no) => self.builder.create_parameter_variable(
+                scope,
+                name,
+                arg_no,
+                file,
+                line,
+                self.ty(ty),
+                true,
+                DIFlags::ZERO,
+            ),
+            None => self.builder.create_auto_variable(
+                scope,
+                name,
+                file,
+                line,
+                self.ty(ty),
+                true,
+                DIFlags::ZERO,
+                0,
+            ),
+        }
+    }
+
+    // `variable` lives at `storage` from the end of `block` on
+    pub fn declare(
+        &self,
+        storage: PointerValue<'ctx>,
+        variable: DILocalVariable<'ctx>,
+        location: DILocation<'ctx>,
+        block: BasicBlock<'ctx>,
+    ) {
+        self.builder
+            .insert_declare_at_end(storage, Some(variable), None, location, block);
+    }
+
+    pub fn location(&self, context: &'ctx Context, text: &Substr) -> DILocation<'ctx> {
+        let (line, column) = self.line_column(text);
+
+        self.builder
+            .create_debug_location(context, line, column, self.scope(text), None)
+    }
+
+    // everything built from here on comes from `text`
+    pub fn set_location(&self, context: &'ctx Context, builder: &Builder<'ctx>, text: &Substr) {
+        builder.set_current_debug_location(self.location(context, text));
+    }
+
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+
+    // the innermost scope `text` is in
+    fn scope(&self, text: &Substr) -> DIScope<'ctx> {
+        let offset = text.range().start;
+
+        self.scopes
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&offset))
+            .map_or(self.unit.as_debug_info_scope(), |(_, scope)| *scope)
+    }
+
+    fn ty(&self, name: &str) -> DIType<'ctx> {
+        self.types[name]
+    }
+}
@@ -0,0 +1,592 @@
+use anyhow::Result;
+use arcstr::Substr;
+use inkwell::{
+    module::Linkage,
+    values::{BasicMetadataValueEnum, FunctionValue, IntValue, PointerValue},
+    FloatPredicate, IntPredicate,
+};
+
+use super::{error, types::TypeLink, unsupported, values::Value, CodeGen, Typed};
+use crate::{
+    ast::{Ast, Expr, Name, ValueDef},
+    diagnostic::Code,
+    value::{Builtin, BUILTINS},
+};
+
+impl<'ctx> CodeGen<'ctx> {
+    pub fn expr(&mut self, expr: &Expr) -> Result<Typed<'ctx>> {
+        let context = self.decl_info.context;
+
+        if let Some((record, field)) = expr.field_access() {
+            return self.field(expr.text(), record, field);
+        }
+
+        match expr {
+            Expr::Int(_, i) => Ok((
+                context.i64_type().const_int(*i as u64, true).into(),
+                TypeLink::INT,
+            )),
+            Expr::Float(_, f) => Ok((context.f64_type().const_float(*f).into(), TypeLink::FLOAT)),
+            Expr::SymbolRef(text, name) => self.symbol(text, name),
+            Expr::Call(text, _, _) => self.call(text, expr),
+            Expr::IfThenElse(text, condition, then_expr, else_expr) => {
+                self.if_then_else(text, condition, then_expr, else_expr)
+            }
+            Expr::LetIn(text, defs, body) => self.let_in(text, defs, body),
+            Expr::Func(text, _, _, _, _) => {
+                Err(unsupported(text, "a function inside a definition"))
+            }
+            Expr::Error(text) => Err(error(text, "this didn't parse")),
+        }
+    }
+
+    fn symbol(&mut self, text: &Substr, name: &Name) -> Result<Typed<'ctx>> {
+        if let Some(local) = self.local(name)? {
+            return Ok(local);
+        }
+
+        match self.decl_info.values.get(name).cloned() {
+            Some(Value::Function {
+                function,
+                params,
+                ret,
+            }) if params.is_empty() => self.call_function(text, function, ret, &[]),
+            Some(Value::Constructor(ty)) if ty.get(self).fields().is_empty() => {
+                self.construct(text, ty, &[])
+            }
+            Some(_) => Err(unsupported(
+                text,
+                format!("`{}` used without calling it", name.text()),
+            )),
+            None => match name.text().as_str() {
+                "true" => Ok((self.bool(true).into(), TypeLink::BOOL)),
+                "false" => Ok((self.bool(false).into(), TypeLink::BOOL)),
+                _ => Err(unsupported(
+                    text,
+                    format!("`{}` used without calling it", name.text()),
+                )),
+            },
+        }
+    }
+
+    // a parameter or a `let` binding, if `name` is one
+    fn local(&self, name: &Name) -> Result<Option<Typed<'ctx>>> {
+        match self
+            .locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name.text().as_str())
+        {
+            Some((_, Some(value))) => Ok(Some(*value)),
+            Some((_, None)) => Err(too_early(name)),
+            None => Ok(None),
+        }
+    }
+
+    // only calls that give a definition, a constructor or a built-in everything it takes can be
+    // compiled, since there are no closures to hold on to the rest
+    fn call(&mut self, text: &Substr, expr: &Expr) -> Result<Typed<'ctx>> {
+        let mut args = Vec::new();
+        let mut func = expr;
+        while let Expr::Call(_, inner, arg) = func {
+            args.push(&**arg);
+            func = inner;
+        }
+        args.reverse();
+
+        let Expr::SymbolRef(_, name) = func else {
+            return Err(unsupported(
+                func.text(),
+                "calling something other than a name",
+            ));
+        };
+        if self.local(name)?.is_some() {
+            return Err(unsupported(
+                func.text(),
+                "calling a parameter or a `let` binding",
+            ));
+        }
+
+        match self.decl_info.values.get(name).cloned() {
+            Some(Value::Function {
+                function,
+                params,
+                ret,
+            }) if params.len() == args.len() => {
+                let args = self.args(&args, &params)?;
+                self.call_function(text, function, ret, &args)
+            }
+            Some(Value::Constructor(ty)) if ty.get(self).fields().len() == args.len() => {
+                let fields: Vec<_> = ty.get(self).fields().iter().map(|(_, ty)| *ty).collect();
+                let args = self.args(&args, &fields)?;
+                self.construct(text, ty, &args)
+            }
+            Some(_) => Err(unsupported(
+                text,
+                format!(
+                    "calling `{}` with a different number of arguments than it takes",
+                    name.text()
+                ),
+            )),
+            None => {
+                let Some((_, builtin)) = BUILTINS.iter().find(|(b, _)| *b == name.text()) else {
+                    return Err(error(
+                        func.text(),
+                        format!("`{}` isn't defined", name.text()),
+                    ));
+                };
+                if builtin.arity() != args.len() {
+                    return Err(unsupported(
+                        text,
+                        format!(
+                            "calling `{}` with a different number of arguments than it takes",
+                            name.text()
+                        ),
+                    ));
+                }
+
+                let args = args
+                    .iter()
+                    .map(|arg| self.expr(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                self.set_location(text);
+                self.builtin(text, *builtin, &args)
+            }
+        }
+    }
+
+    // the arguments, each of the type it's supposed to be
+    fn args(&mut self, args: &[&Expr], types: &[TypeLink]) -> Result<Vec<Typed<'ctx>>> {
+        args.iter()
+            .zip(types)
+            .map(|(arg, expected)| {
+                let (value, ty) = self.expr(arg)?;
+                self.expect(arg.text(), ty, *expected)?;
+
+                Ok((value, ty))
+            })
+            .collect()
+    }
+
+    fn call_function(
+        &mut self,
+        text: &Substr,
+        function: FunctionValue<'ctx>,
+        ret: TypeLink,
+        args: &[Typed<'ctx>],
+    ) -> Result<Typed<'ctx>> {
+        let args: Vec<BasicMetadataValueEnum> = args.iter().map(|(arg, _)| (*arg).into()).collect();
+
+        self.set_location(text);
+        let value = self
+            .builder
+            .build_call(function, &args, "")?
+            .try_as_basic_value()
+            .left()
+            .expect("every function gives back a value");
+
+        Ok((value, ret))
+    }
+
+    fn construct(
+        &mut self,
+        text: &Substr,
+        ty: TypeLink,
+        fields: &[Typed<'ctx>],
+    ) -> Result<Typed<'ctx>> {
+        let llvm_struct = ty.get(self).llvm_type(self).into_struct_type();
+
+        self.set_location(text);
+        let mut record = llvm_struct.const_zero();
+        for (i, (field, _)) in fields.iter().enumerate() {
+            record = self
+                .builder
+                .build_insert_value(record, *field, i as u32, "")?
+                .into_struct_value();
+        }
+
+        Ok((record.into(), ty))
+    }
+
+    fn field(&mut self, text: &Substr, record: &Expr, field: &Name) -> Result<Typed<'ctx>> {
+        let (value, ty) = self.expr(record)?;
+
+        let Some(i) = ty
+            .get(self)
+            .fields()
+            .iter()
+            .position(|(name, _)| name == field)
+        else {
+            return Err(error(
+                field.text(),
+                format!("`{}` has no field `{}`", ty.get(self).name(), field.text()),
+            ));
+        };
+        let field_ty = ty.get(self).fields()[i].1;
+
+        self.set_location(text);
+        let value =
+            self.builder
+                .build_extract_value(value.into_struct_value(), i as u32, field.text())?;
+
+        Ok((value, field_ty))
+    }
+
+    fn if_then_else(
+        &mut self,
+        text: &Substr,
+        condition: &Expr,
+        then_expr: &Expr,
+        else_expr: &Expr,
+    ) -> Result<Typed<'ctx>> {
+        let context = self.decl_info.context;
+        let function = self.function();
+
+        let (condition_value, ty) = self.expr(condition)?;
+        self.expect(condition.text(), ty, TypeLink::BOOL)?;
+
+        let then_block = context.append_basic_block(function, "then");
+        let else_block = context.append_basic_block(function, "else");
+        let end = context.append_basic_block(function, "end");
+        self.set_location(text);
+        self.builder.build_conditional_branch(
+            condition_value.into_int_value(),
+            then_block,
+            else_block,
+        )?;
+
+        self.builder.position_at_end(then_block);
+        let (then_value, ty) = self.expr(then_expr)?;
+        let then_block = self.block();
+        self.set_location(text);
+        self.builder.build_unconditional_branch(end)?;
+
+        self.builder.position_at_end(else_block);
+        let (else_value, else_ty) = self.expr(else_expr)?;
+        self.expect(else_expr.text(), else_ty, ty)?;
+        let else_block = self.block();
+        self.set_location(text);
+        self.builder.build_unconditional_branch(end)?;
+
+        self.builder.position_at_end(end);
+        let phi = self.builder.build_phi(then_value.get_type(), "")?;
+        phi.add_incoming(&[(&then_value, then_block), (&else_value, else_block)]);
+
+        Ok((phi.as_basic_value(), ty))
+    }
+
+    fn let_in(&mut self, text: &Substr, defs: &[ValueDef], body: &Expr) -> Result<Typed<'ctx>> {
+        self.debug_info.lexical_block(text);
+
+        // every binding is in scope from the start, so using one too early can be caught
+        let outer = self.locals.len();
+        for def in defs {
+            self.locals.push((def.name.text().to_string(), None));
+        }
+
+        let value = self.bindings(outer, defs).and_then(|()| self.expr(body));
+        self.locals.truncate(outer);
+
+        value
+    }
+
+    fn bindings(&mut self, outer: usize, defs: &[ValueDef]) -> Result<()> {
+        for (i, def) in defs.iter().enumerate() {
+            if let Expr::Func(text, _, _, _, _) = &def.body {
+                return Err(unsupported(text, "a function defined in a `let`"));
+            }
+
+            let value = self.expr(&def.body)?;
+            let variable =
+                self.debug_info
+                    .variable(def.name.text(), value.1.get(self).name(), None);
+
+            self.set_location(&def.text);
+            self.keep(&def.name, value, variable)?;
+            self.locals[outer + i].1 = Some(value);
+        }
+
+        Ok(())
+    }
+
+    fn builtin(
+        &mut self,
+        text: &Substr,
+        builtin: Builtin,
+        args: &[Typed<'ctx>],
+    ) -> Result<Typed<'ctx>> {
+        let builder = &self.builder;
+
+        let value = match (builtin, args) {
+            (Builtin::Not, [(a, TypeLink::BOOL)]) => {
+                builder.build_not(a.into_int_value(), "")?.into()
+            }
+            (Builtin::Assert, [(a, TypeLink::BOOL)]) => {
+                let a = a.into_int_value();
+                self.check(
+                    text,
+                    a,
+                    Code::AssertionFailed,
+                    "assertion failed",
+                    "this is false",
+                )?;
+                a.into()
+            }
+            (_, [(a, TypeLink::INT), (b, TypeLink::INT)]) => {
+                let (a, b) = (a.into_int_value(), b.into_int_value());
+
+                match builtin {
+                    Builtin::Add => builder.build_int_add(a, b, "")?.into(),
+                    Builtin::Sub => builder.build_int_sub(a, b, "")?.into(),
+                    Builtin::Mul => builder.build_int_mul(a, b, "")?.into(),
+                    Builtin::Div | Builtin::Rem => self.divide(text, builtin, a, b)?.into(),
+                    _ => match int_predicate(builtin) {
+                        Some(predicate) => builder.build_int_compare(predicate, a, b, "")?.into(),
+                        None => return Err(self.invalid_operands(text, builtin, args)),
+                    },
+                }
+            }
+            (_, [(a, TypeLink::FLOAT), (b, TypeLink::FLOAT)]) => {
+                let (a, b) = (a.into_float_value(), b.into_float_value());
+
+                match builtin {
+                    Builtin::Add => builder.build_float_add(a, b, "")?.into(),
+                    Builtin::Sub => builder.build_float_sub(a, b, "")?.into(),
+                    Builtin::Mul => builder.build_float_mul(a, b, "")?.into(),
+                    Builtin::Div => builder.build_float_div(a, b, "")?.into(),
+                    Builtin::Rem => builder.build_float_rem(a, b, "")?.into(),
+                    _ => match float_predicate(builtin) {
+                        Some(predicate) => builder.build_float_compare(predicate, a, b, "")?.into(),
+                        None => return Err(self.invalid_operands(text, builtin, args)),
+                    },
+                }
+            }
+            (_, [(a, TypeLink::BOOL), (b, TypeLink::BOOL)]) => {
+                let (a, b) = (a.into_int_value(), b.into_int_value());
+
+                match builtin {
+                    Builtin::And => builder.build_and(a, b, "")?.into(),
+                    Builtin::Or => builder.build_or(a, b, "")?.into(),
+                    Builtin::Eq => builder
+                        .build_int_compare(IntPredicate::EQ, a, b, "")?
+                        .into(),
+                    Builtin::Ne => builder
+                        .build_int_compare(IntPredicate::NE, a, b, "")?
+                        .into(),
+                    _ => return Err(self.invalid_operands(text, builtin, args)),
+                }
+            }
+            _ => return Err(self.invalid_operands(text, builtin, args)),
+        };
+
+        let ty = match builtin {
+            Builtin::Add | Builtin::Sub | Builtin::Mul | Builtin::Div | Builtin::Rem => args[0].1,
+            _ => TypeLink::BOOL,
+        };
+        Ok((value, ty))
+    }
+
+    // ints wrap around, so the smallest one divided by -1 is itself. LLVM leaves that undefined, so
+    // -1 is handled by itself
+    fn divide(
+        &mut self,
+        text: &Substr,
+        builtin: Builtin,
+        a: IntValue<'ctx>,
+        b: IntValue<'ctx>,
+    ) -> Result<IntValue<'ctx>> {
+        let i64_type = self.decl_info.context.i64_type();
+
+        let nonzero =
+            self.builder
+                .build_int_compare(IntPredicate::NE, b, i64_type.const_zero(), "")?;
+        self.check(
+            text,
+            nonzero,
+            Code::DivisionByZero,
+            "division by zero",
+            "the right-hand side of this is 0",
+        )?;
+
+        let minus_one =
+            self.builder
+                .build_int_compare(IntPredicate::EQ, b, i64_type.const_all_ones(), "")?;
+        let b = self
+            .builder
+            .build_select(minus_one, i64_type.const_int(1, false), b, "")?
+            .into_int_value();
+
+        let (value, by_minus_one) = match builtin {
+            Builtin::Div => (
+                self.builder.build_int_signed_div(a, b, "")?,
+                self.builder.build_int_neg(a, "")?,
+            ),
+            _ => (
+                self.builder.build_int_signed_rem(a, b, "")?,
+                i64_type.const_zero(),
+            ),
+        };
+
+        Ok(self
+            .builder
+            .build_select(minus_one, by_minus_one, value, "")?
+            .into_int_value())
+    }
+
+    // carries on if `condition` holds, and otherwise fails the way the interpreter does
+    fn check(
+        &mut self,
+        text: &Substr,
+        condition: IntValue<'ctx>,
+        code: Code,
+        message: &str,
+        label: &str,
+    ) -> Result<()> {
+        let context = self.decl_info.context;
+        let function = self.function();
+
+        let fail = context.append_basic_block(function, "fail");
+        let ok = context.append_basic_block(function, "ok");
+        self.builder.build_conditional_branch(condition, ok, fail)?;
+
+        self.builder.position_at_end(fail);
+        let (line, column) = self.debug_info.line_column(text);
+        let message = format!(
+            "[{code}] Error: {message}\n  at {}:{line}:{column}, {label}\n",
+            self.module.get_name().to_string_lossy()
+        );
+        let format = self.string("%s")?;
+        let message = self.string(&message)?;
+        let dprintf = self.printf("dprintf", &[context.i32_type().into()])?;
+        self.builder.build_call(
+            dprintf,
+            &[
+                context.i32_type().const_int(2, false).into(),
+                format.into(),
+                message.into(),
+            ],
+            "",
+        )?;
+
+        let exit = self.module.get_function("exit").unwrap_or_else(|| {
+            let exit_type = context
+                .void_type()
+                .fn_type(&[context.i32_type().into()], false);
+            self.module
+                .add_function("exit", exit_type, Some(Linkage::External))
+        });
+        self.builder
+            .build_call(exit, &[context.i32_type().const_int(1, false).into()], "")?;
+        self.builder.build_unreachable()?;
+
+        self.builder.position_at_end(ok);
+        Ok(())
+    }
+
+    // `printf` and `dprintf` from libc, declared the first time they're used. they take the type of
+    // the strings given to them, which is all there is to go on for a pointer type
+    pub fn printf(
+        &self,
+        name: &str,
+        before: &[inkwell::types::BasicMetadataTypeEnum<'ctx>],
+    ) -> Result<FunctionValue<'ctx>> {
+        if let Some(function) = self.module.get_function(name) {
+            return Ok(function);
+        }
+
+        let context = self.decl_info.context;
+        let mut params = before.to_vec();
+        params.push(self.string("")?.get_type().into());
+
+        Ok(self.module.add_function(
+            name,
+            context.i32_type().fn_type(&params, true),
+            Some(Linkage::External),
+        ))
+    }
+
+    pub fn string(&self, s: &str) -> Result<PointerValue<'ctx>> {
+        Ok(self
+            .builder
+            .build_global_string_ptr(s, "")?
+            .as_pointer_value())
+    }
+
+    fn bool(&self, b: bool) -> IntValue<'ctx> {
+        self.decl_info
+            .context
+            .bool_type()
+            .const_int(b as u64, false)
+    }
+
+    fn invalid_operands(
+        &self,
+        text: &Substr,
+        builtin: Builtin,
+        args: &[Typed<'ctx>],
+    ) -> anyhow::Error {
+        let types: Vec<String> = args
+            .iter()
+            .map(|(_, ty)| format!("`{}`", ty.get(self).name()))
+            .collect();
+
+        error(
+            text,
+            format!(
+                "`{}` can't be used on {}",
+                builtin.name(),
+                types.join(" and ")
+            ),
+        )
+    }
+
+    // the function being built, which new blocks go in
+    fn function(&self) -> FunctionValue<'ctx> {
+        self.block()
+            .get_parent()
+            .expect("blocks are always in a function")
+    }
+
+    fn block(&self) -> inkwell::basic_block::BasicBlock<'ctx> {
+        self.builder
+            .get_insert_block()
+            .expect("the builder is always in a block")
+    }
+}
+
+// a `let` binding used by one before it
+pub fn too_early(name: &Name) -> anyhow::Error {
+    error(
+        name.text(),
+        format!(
+            "`{}` is used before it's worked out. compiled with LLVM, `let` bindings are worked \
+             out in the order they're written",
+            name.text()
+        ),
+    )
+}
+
+fn int_predicate(builtin: Builtin) -> Option<IntPredicate> {
+    Some(match builtin {
+        Builtin::Eq => IntPredicate::EQ,
+        Builtin::Ne => IntPredicate::NE,
+        Builtin::Lt => IntPredicate::SLT,
+        Builtin::Le => IntPredicate::SLE,
+        Builtin::Gt => IntPredicate::SGT,
+        Builtin::Ge => IntPredicate::SGE,
+        _ => return None,
+    })
+}
+
+// ordered, so comparisons with NaN are false, except for `!=` which is true like it is in Rust
+fn float_predicate(builtin: Builtin) -> Option<FloatPredicate> {
+    Some(match builtin {
+        Builtin::Eq => FloatPredicate::OEQ,
+        Builtin::Ne => FloatPredicate::UNE,
+        Builtin::Lt => FloatPredicate::OLT,
+        Builtin::Le => FloatPredicate::OLE,
+        Builtin::Gt => FloatPredicate::OGT,
+        Builtin::Ge => FloatPredicate::OGE,
+        _ => return None,
+    })
+}
@@ -0,0 +1,150 @@
+use anyhow::Result;
+
+use super::{error, expr::too_early, types::TypeLink, unsupported, values::Value, CodeGen};
+use crate::{
+    ast::{Ast, Expr, Name},
+    value::{Builtin, BUILTINS},
+};
+
+impl<'ctx> CodeGen<'ctx> {
+    // the type `expr` works out to, found the same way `expr` builds the code for it, with the types of
+    // the locals in scope. a definition that isn't declared yet gets declared first, so definitions
+    // can use each other whatever order they're in
+    pub fn type_of(
+        &mut self,
+        expr: &Expr,
+        locals: &mut Vec<(Name, Option<TypeLink>)>,
+    ) -> Result<TypeLink> {
+        if let Some((record, field)) = expr.field_access() {
+            let ty = self.type_of(record, locals)?;
+
+            return ty
+                .get(self)
+                .fields()
+                .iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, ty)| *ty)
+                .ok_or_else(|| {
+                    error(
+                        field.text(),
+                        format!("`{}` has no field `{}`", ty.get(self).name(), field.text()),
+                    )
+                });
+        }
+
+        match expr {
+            Expr::Int(_, _) => Ok(TypeLink::INT),
+            Expr::Float(_, _) => Ok(TypeLink::FLOAT),
+            Expr::SymbolRef(text, name) => {
+                match locals.iter().rev().find(|(local, _)| local == name) {
+                    Some((_, Some(ty))) => return Ok(*ty),
+                    Some((_, None)) => return Err(too_early(name)),
+                    None => (),
+                }
+
+                match self.declared(name)? {
+                    Some(Value::Function { ret, .. }) => Ok(ret),
+                    Some(Value::Constructor(ty)) => Ok(ty),
+                    None if matches!(name.text().as_str(), "true" | "false") => Ok(TypeLink::BOOL),
+                    None => Err(unsupported(
+                        text,
+                        format!("`{}` used without calling it", name.text()),
+                    )),
+                }
+            }
+            Expr::Call(_, _, _) => {
+                let mut args = Vec::new();
+                let mut func = expr;
+                while let Expr::Call(_, inner, arg) = func {
+                    args.push(&**arg);
+                    func = inner;
+                }
+
+                let Expr::SymbolRef(_, name) = func else {
+                    return Err(unsupported(
+                        func.text(),
+                        "calling something other than a name",
+                    ));
+                };
+                if locals.iter().any(|(local, _)| local == name) {
+                    return Err(unsupported(
+                        func.text(),
+                        "calling a parameter or a `let` binding",
+                    ));
+                }
+
+                match self.declared(name)? {
+                    Some(Value::Function { ret, .. }) => Ok(ret),
+                    Some(Value::Constructor(ty)) => Ok(ty),
+                    None => match BUILTINS.iter().find(|(b, _)| *b == name.text()) {
+                        // what they're given is last in `args`, since it was collected from the outside
+                        Some((
+                            _,
+                            Builtin::Add
+                            | Builtin::Sub
+                            | Builtin::Mul
+                            | Builtin::Div
+                            | Builtin::Rem,
+                        )) => match args.last() {
+                            Some(lhs) => self.type_of(lhs, locals),
+                            None => Err(unsupported(func.text(), "a built-in without arguments")),
+                        },
+                        Some(_) => Ok(TypeLink::BOOL),
+                        None => Err(error(
+                            func.text(),
+                            format!("`{}` isn't defined", name.text()),
+                        )),
+                    },
+                }
+            }
+            // a recursive definition can only give back what its other branch does
+            Expr::IfThenElse(_, _, then_expr, else_expr) => self
+                .type_of(then_expr, locals)
+                .or_else(|_| self.type_of(else_expr, locals)),
+            Expr::LetIn(_, defs, body) => {
+                let outer = locals.len();
+                locals.extend(defs.iter().map(|def| (def.name.clone(), None)));
+
+                let ty = defs
+                    .iter()
+                    .enumerate()
+                    .try_for_each(|(i, def)| {
+                        locals[outer + i].1 = Some(self.type_of(&def.body, locals)?);
+
+                        Ok(())
+                    })
+                    .and_then(|()| self.type_of(body, locals));
+                locals.truncate(outer);
+
+                ty
+            }
+            Expr::Func(text, _, _, _, _) => {
+                Err(unsupported(text, "a function inside a definition"))
+            }
+            Expr::Error(text) => Err(error(text, "this didn't parse")),
+        }
+    }
+
+    // what `name` stands for at the top level, declaring it if it hasn't been yet
+    fn declared(&mut self, name: &Name) -> Result<Option<Value<'ctx>>> {
+        if self.declaring.contains(name) {
+            return Err(error(
+                name.text(),
+                format!(
+                    "can't tell what `{}` gives back. say with an annotation, like \
+                     `x :Int -> :Int ...`",
+                    name.text()
+                ),
+            ));
+        }
+        if let Some(def) = self.undeclared.remove(name) {
+            // left for later if it can't be declared yet, where the error gets reported anyway
+            if let Err(e) = self.declare(&def) {
+                self.undeclared.insert(name.clone(), def);
+                return Err(e);
+            }
+        }
+
+        Ok(self.decl_info.values.get(name).cloned())
+    }
+}
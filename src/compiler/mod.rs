@@ -1,32 +1,407 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt::Display};
 
-use anyhow::Result;
-use inkwell::{builder::Builder, context::Context, module::Module};
-use types::{DynType, Struct, TypeLink};
+use anyhow::{anyhow, bail, Result};
+use arcstr::{ArcStr, Substr};
+use debug::DebugInfo;
+use inkwell::{
+    builder::Builder,
+    context::Context,
+    debug_info::DILocalVariable,
+    module::Module,
+    targets::{CodeModel, InitializationConfig, RelocMode, Target, TargetData, TargetMachine},
+    types::BasicType,
+    values::{BasicMetadataValueEnum, BasicValueEnum},
+    OptimizationLevel,
+};
+use types::{DynType, PrimitiveType, Struct, TypeLink};
 use values::Value;
 
-use crate::ast::{Ast, Def, Name, TypeRef};
+use crate::{
+    ast::{Ast, Def, Expr, Name, Root, TypeRef, ValueDef},
+    diagnostic::SourceFile,
+};
 
+mod debug;
+mod expr;
+mod infer;
 mod types;
 mod values;
 
+// a value along with its type
+type Typed<'ctx> = (BasicValueEnum<'ctx>, TypeLink);
+
 struct CodeGen<'ctx> {
     module: Module<'ctx>,
     builder: Builder<'ctx>,
+    debug_info: DebugInfo<'ctx>,
+    // how big records are and where their fields go, for the debug info
+    target_data: TargetData,
     decl_info: DeclInfo<'ctx>,
+    // the parameters and `let` bindings in scope, innermost last. bindings that haven't been worked
+    // out yet have no value
+    locals: Vec<(String, Option<Typed<'ctx>>)>,
+    // the definitions without functions yet, and the ones being given them. one can need another's
+    // type before it gets declared in turn
+    undeclared: HashMap<Name, ValueDef>,
+    declaring: Vec<Name>,
 }
 
 impl<'ctx> CodeGen<'ctx> {
-    pub fn new(module_name: &str, decl_info: DeclInfo<'ctx>) -> Self {
-        Self {
-            module: decl_info.context.create_module(module_name),
+    pub fn new(module_name: &str, src: &ArcStr, decl_info: DeclInfo<'ctx>) -> Result<Self> {
+        let module = decl_info.context.create_module(module_name);
+
+        // code for the machine it's compiled on, like a C compiler does by default
+        Target::initialize_native(&InitializationConfig::default()).map_err(|e| anyhow!(e))?;
+        let triple = TargetMachine::get_default_triple();
+        let machine = Target::from_triple(&triple)
+            .map_err(|e| anyhow!(e.to_string()))?
+            .create_target_machine(
+                &triple,
+                "generic",
+                "",
+                OptimizationLevel::None,
+                RelocMode::PIC,
+                CodeModel::Default,
+            )
+            .ok_or_else(|| anyhow!("LLVM can't generate code for `{triple}`"))?;
+        let target_data = machine.get_target_data();
+        module.set_triple(&triple);
+        module.set_data_layout(&target_data.get_data_layout());
+
+        Ok(Self {
+            debug_info: DebugInfo::new(decl_info.context, &module, module_name, src),
+            module,
             builder: decl_info.context.create_builder(),
+            target_data,
             decl_info,
-        }
+            locals: Vec::new(),
+            undeclared: HashMap::new(),
+            declaring: Vec::new(),
+        })
+    }
+
+    // call before building the code for `text`, so debuggers can find it
+    pub fn set_location(&self, text: &Substr) {
+        self.debug_info
+            .set_location(self.decl_info.context, &self.builder, text);
     }
 
     pub fn resolve_type_ref(&self, type_ref: &TypeRef) -> Result<TypeLink> {
-        todo!()
+        self.decl_info.resolve_type_ref(type_ref)
+    }
+
+    // records hold their fields by value, so each one's body is set after the bodies of the records
+    // in it, and they're described to debuggers in the same order
+    fn define_records(&mut self) -> Result<()> {
+        let mut order = Vec::new();
+        for i in 0..self.decl_info.types.len() {
+            self.record_order(TypeLink(i), &mut Vec::new(), &mut order)?;
+        }
+
+        for ty in order {
+            let Some(text) = ty.get(self).text().cloned() else {
+                continue;
+            };
+            let llvm_struct = ty.get(self).llvm_type(self).into_struct_type();
+            let fields: Vec<_> = ty
+                .get(self)
+                .fields()
+                .iter()
+                .map(|(_, field)| field.get(self).llvm_type(self))
+                .collect();
+            llvm_struct.set_body(&fields, false);
+
+            let members: Vec<_> = ty
+                .get(self)
+                .fields()
+                .iter()
+                .zip(&fields)
+                .enumerate()
+                .map(|(i, ((name, field), llvm_type))| {
+                    let offset = self
+                        .target_data
+                        .offset_of_element(&llvm_struct, i as u32)
+                        .unwrap_or_default();
+
+                    (
+                        name.text().clone(),
+                        field.get(self).name().to_string(),
+                        self.target_data.get_bit_size(llvm_type),
+                        offset * 8,
+                    )
+                })
+                .collect();
+            let bits = self.target_data.get_bit_size(&llvm_struct);
+
+            self.debug_info.record(&text, bits, &members);
+        }
+
+        Ok(())
+    }
+
+    fn record_order(
+        &self,
+        ty: TypeLink,
+        within: &mut Vec<TypeLink>,
+        order: &mut Vec<TypeLink>,
+    ) -> Result<()> {
+        if order.contains(&ty) {
+            return Ok(());
+        }
+        if within.contains(&ty) {
+            let text = ty.get(self).text().expect("only records hold anything");
+            return Err(unsupported(
+                text,
+                format!("`{}`, which holds itself,", ty.get(self).name()),
+            ));
+        }
+
+        within.push(ty);
+        for (_, field) in ty.get(self).fields() {
+            self.record_order(*field, within, order)?;
+        }
+        within.pop();
+
+        order.push(ty);
+        Ok(())
+    }
+
+    // a function for `def`, with its parameters from the annotations and what it gives back from its
+    // annotation, or worked out from its body where there isn't one
+    fn declare(&mut self, def: &ValueDef) -> Result<()> {
+        let (names, ret, body) = signature(&def.body);
+
+        let params = names
+            .iter()
+            .map(|(name, type_ref)| match type_ref {
+                Some(type_ref) => self.resolve_type_ref(type_ref),
+                None => Err(unsupported(name.text(), "a parameter without a type")),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let ret = match ret {
+            Some(type_ref) => self.resolve_type_ref(type_ref)?,
+            None => {
+                let mut locals = names
+                    .iter()
+                    .zip(&params)
+                    .map(|((name, _), ty)| ((*name).clone(), Some(*ty)))
+                    .collect();
+
+                self.declaring.push(def.name.clone());
+                let ret = self.type_of(body, &mut locals);
+                self.declaring.pop();
+
+                ret?
+            }
+        };
+
+        let param_types: Vec<_> = params
+            .iter()
+            .map(|param| param.get(self).llvm_type(self).into())
+            .collect();
+        let linkage_name = format!("caelis.{}", def.name.text());
+        let function = self.module.add_function(
+            &linkage_name,
+            ret.get(self).llvm_type(self).fn_type(&param_types, false),
+            None,
+        );
+
+        let param_names: Vec<_> = params
+            .iter()
+            .map(|param| param.get(self).name().to_string())
+            .collect();
+        let ret_name = ret.get(self).name().to_string();
+        let subprogram = self.debug_info.subprogram(
+            &def.text,
+            def.name.text(),
+            &linkage_name,
+            &param_names,
+            &ret_name,
+        );
+        function.set_subprogram(subprogram);
+
+        self.decl_info.values.insert(
+            def.name.clone(),
+            Value::Function {
+                function,
+                params,
+                ret,
+            },
+        );
+        Ok(())
+    }
+
+    fn define(&mut self, def: &ValueDef) -> Result<()> {
+        let Some(Value::Function {
+            function,
+            params,
+            ret,
+        }) = self.decl_info.values.get(&def.name).cloned()
+        else {
+            unreachable!("every definition is declared first");
+        };
+        let (names, _, body) = signature(&def.body);
+
+        let entry = self.decl_info.context.append_basic_block(function, "entry");
+        self.builder.position_at_end(entry);
+        self.set_location(&def.text);
+
+        for (i, ((name, _), ty)) in names.iter().zip(&params).enumerate() {
+            let value = function
+                .get_nth_param(i as u32)
+                .expect("there's an LLVM parameter for each one");
+            let variable =
+                self.debug_info
+                    .variable(name.text(), ty.get(self).name(), Some(i as u32 + 1));
+
+            self.keep(name, (value, *ty), variable)?;
+            self.locals
+                .push((name.text().to_string(), Some((value, *ty))));
+        }
+
+        let value = self.expr(body);
+        self.locals.clear();
+        let (value, ty) = value?;
+        self.expect(body.text(), ty, ret)?;
+
+        self.set_location(body.text());
+        self.builder.build_return(Some(&value))?;
+        Ok(())
+    }
+
+    // the C entry point, which prints what `main` works out to like `caelis run` does
+    fn entry_point(&mut self, main: &ValueDef) -> Result<()> {
+        let Some(Value::Function {
+            function,
+            params,
+            ret,
+        }) = self.decl_info.values.get(&main.name).cloned()
+        else {
+            unreachable!("every definition is declared first");
+        };
+        if !params.is_empty() {
+            return Err(error(main.name.text(), "`main` can't take parameters"));
+        }
+
+        let context = self.decl_info.context;
+        let entry_point =
+            self.module
+                .add_function("main", context.i32_type().fn_type(&[], false), None);
+        self.builder
+            .position_at_end(context.append_basic_block(entry_point, "entry"));
+        self.builder.unset_current_debug_location();
+
+        let value = self
+            .builder
+            .build_call(function, &[], "main")?
+            .try_as_basic_value()
+            .left()
+            .expect("`main` gives back a value");
+
+        let mut format = String::new();
+        let mut args = Vec::new();
+        self.show(main, value, ret, &mut format, &mut args)?;
+        format.push('\n');
+
+        let format = self.string(&format)?;
+        args.insert(0, format.into());
+        let printf = self.printf("printf", &[])?;
+        self.builder.build_call(printf, &args, "")?;
+        self.builder
+            .build_return(Some(&context.i32_type().const_zero()))?;
+
+        Ok(())
+    }
+
+    // a format for `printf` that prints `value` the way `caelis run` does, and what goes in it
+    fn show(
+        &self,
+        main: &ValueDef,
+        value: BasicValueEnum<'ctx>,
+        ty: TypeLink,
+        format: &mut String,
+        args: &mut Vec<BasicMetadataValueEnum<'ctx>>,
+    ) -> Result<()> {
+        match ty {
+            TypeLink::INT => {
+                format.push_str("%lld");
+                args.push(value.into());
+            }
+            TypeLink::BOOL => {
+                format.push_str("%s");
+                let shown = self.builder.build_select(
+                    value.into_int_value(),
+                    self.string("true")?,
+                    self.string("false")?,
+                    "",
+                )?;
+                args.push(shown.into());
+            }
+            TypeLink::FLOAT => {
+                return Err(unsupported(
+                    main.name.text(),
+                    "printing a `Float` from `main`",
+                ))
+            }
+            _ => {
+                format.push_str(ty.get(self).name());
+
+                for (i, (name, field)) in ty.get(self).fields().iter().enumerate() {
+                    format.push_str(if i == 0 { " { " } else { ", " });
+                    format.push_str(name.text());
+                    format.push_str(" = ");
+
+                    let value = self.builder.build_extract_value(
+                        value.into_struct_value(),
+                        i as u32,
+                        name.text(),
+                    )?;
+                    self.show(main, value, *field, format, args)?;
+                }
+                if !ty.get(self).fields().is_empty() {
+                    format.push_str(" }");
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // puts `value` where a debugger can find it, as `variable`
+    fn keep(
+        &self,
+        name: &Name,
+        (value, _): Typed<'ctx>,
+        variable: DILocalVariable<'ctx>,
+    ) -> Result<()> {
+        let slot = self.builder.build_alloca(value.get_type(), name.text())?;
+        self.builder.build_store(slot, value)?;
+
+        let block = self
+            .builder
+            .get_insert_block()
+            .expect("the builder is always in a block");
+        let location = self
+            .debug_info
+            .location(self.decl_info.context, name.text());
+        self.debug_info.declare(slot, variable, location, block);
+
+        Ok(())
+    }
+
+    fn expect(&self, text: &Substr, ty: TypeLink, expected: TypeLink) -> Result<()> {
+        if ty == expected {
+            return Ok(());
+        }
+
+        Err(error(
+            text,
+            format!(
+                "this is `{}`, but `{}` is needed",
+                ty.get(self).name(),
+                expected.get(self).name()
+            ),
+        ))
     }
 }
 
@@ -40,20 +415,26 @@ impl<'ctx> DeclInfo<'ctx> {
     pub fn new(context: &'ctx Context) -> Self {
         Self {
             context,
-            types: Self::init_types(context),
+            types: Self::init_types(),
             values: Self::init_values(),
         }
     }
 
-    fn init_types(context: &'ctx Context) -> Vec<DynType<'ctx>> {
-        todo!() //TODO: PAIN
+    // in the order `TypeLink` expects
+    fn init_types() -> Vec<DynType<'ctx>> {
+        vec![
+            Box::new(PrimitiveType::I64),
+            Box::new(PrimitiveType::F64),
+            Box::new(PrimitiveType::Bool),
+        ]
     }
 
+    // built-ins are compiled where they're called, so none of them are declared up front
     fn init_values() -> HashMap<Name, Value<'ctx>> {
-        todo!() //TODO: PAIN
+        HashMap::new()
     }
 
-    pub fn populate<'src>(&mut self, ast: &'src Vec<Def>) {
+    pub fn populate(&mut self, ast: &[Def]) -> Result<()> {
         let mut generic_defs = HashMap::new();
 
         for def in ast.iter().filter_map(|def| match def {
@@ -64,35 +445,153 @@ impl<'ctx> DeclInfo<'ctx> {
             generic_defs.insert(def.name, def.args);
         }
 
+        let mut structs = Vec::new();
         for def in ast.iter().filter_map(|def| match def {
             Def::Type(type_def) => Some(type_def),
             _ => None,
         }) {
+            if generic_defs.contains_key(&def.name) {
+                return Err(unsupported(
+                    def.name.text(),
+                    format!("`{}`, which takes type parameters,", def.name.text()),
+                ));
+            }
+
             let def = def.clone();
             //TODO: no guarantee of unique names
-            self.types.push(Box::new(Struct::new(
-                self.context,
-                def.name.clone(),
-                def.fields,
-                generic_defs.get(&def.name),
-            )) as DynType<'ctx>)
+            structs.push(Struct::new(self.context, def.name.clone(), def.fields));
+        }
+
+        // records can hold each other, so they all need names before any fields are looked up
+        let mut names: Vec<String> = self.types.iter().map(|ty| ty.name().to_string()).collect();
+        names.extend(structs.iter().map(|s| s.name.text().to_string()));
+        for mut s in structs {
+            s.resolve(|type_ref| link(&names, type_ref))?;
+            self.types.push(Box::new(s) as DynType<'ctx>);
         }
 
         for def in ast.iter().filter_map(|def| match def {
-            Def::Value(value_def) => Some(value_def),
+            Def::Type(type_def) => Some(type_def),
             _ => None,
         }) {
-            //TODO: calculate symbols
+            let ty = self.find(def.name.text(), def.name.text())?;
+            self.values.insert(def.name.clone(), Value::Constructor(ty));
         }
+
+        Ok(())
+    }
+
+    pub fn resolve_type_ref(&self, type_ref: &TypeRef) -> Result<TypeLink> {
+        let names: Vec<String> = self.types.iter().map(|ty| ty.name().to_string()).collect();
+
+        link(&names, type_ref)
     }
+
+    // the type called `name`, which `text` refers to
+    pub fn find(&self, text: &Substr, name: &str) -> Result<TypeLink> {
+        self.types
+            .iter()
+            .position(|ty| ty.name() == name)
+            .map(TypeLink)
+            .ok_or_else(|| error(text, format!("there's no type `{name}`")))
+    }
+}
+
+// `type_ref` as one of the types in `names`, which are in the order `DeclInfo::types` has them
+fn link(names: &[String], type_ref: &TypeRef) -> Result<TypeLink> {
+    match type_ref {
+        TypeRef::Named(text, name, args) if args.is_empty() => names
+            .iter()
+            .position(|ty| ty == name.text().as_str())
+            .map(TypeLink)
+            .ok_or_else(|| error(text, format!("there's no type `{}`", name.text()))),
+        TypeRef::Named(text, _, _) => Err(unsupported(text, "a type with type arguments")),
+        TypeRef::Function(text, _, _) => Err(unsupported(text, "a function passed as a value")),
+    }
+}
+
+// each parameter's name and the type it's annotated with
+type Params<'a> = Vec<(&'a Name, &'a Option<TypeRef>)>;
+
+// `x :Int -> y :Int -> :Int body` takes `x` and `y` and gives back what `body` does, an `Int`
+fn signature(expr: &Expr) -> (Params<'_>, Option<&TypeRef>, &Expr) {
+    let mut params = Vec::new();
+    let mut ret = None;
+    let mut body = expr;
+
+    while let Expr::Func(_, name, ty, ret_ty, inner) = body {
+        params.push((name, ty));
+        ret = ret_ty.as_ref();
+        body = inner;
+    }
+
+    (params, ret, body)
 }
 
-pub fn compile<'src>(module_name: String, ast: &'src Vec<Def>) -> Result<()> {
+// errors say where in the file they are, and `compile`'s caller says which file
+fn error(text: &Substr, message: impl Display) -> anyhow::Error {
+    let (line, column) = SourceFile::new("", text.parent().clone()).line_column(text.range().start);
+
+    anyhow!("{line}:{column}: {message}")
+}
+
+fn unsupported(text: &Substr, what: impl Display) -> anyhow::Error {
+    error(text, format!("{what} can't be compiled with LLVM yet"))
+}
+
+// the program as LLVM IR for the machine it's compiled on, with the debug info to go with it. it
+// keeps to what the interpreter does, except that `let` bindings are worked out where they're
+// written instead of when they're first needed, so one can only use those before it
+pub fn compile(file: &str, src: &ArcStr, root: &Root) -> Result<String> {
+    let Some(main) = root.defs.iter().rev().find_map(|def| match def {
+        Def::Value(value_def) if value_def.name.text() == "main" => Some(value_def),
+        _ => None,
+    }) else {
+        bail!("there's no `main` to run");
+    };
+
     let context = Context::create();
     let mut decl_info = DeclInfo::new(&context);
-    decl_info.populate(ast);
+    decl_info.populate(&root.defs)?;
+
+    let mut codegen = CodeGen::new(file, src, decl_info)?;
+    codegen.define_records()?;
+
+    // a name defined twice means what it was defined as last, like it does to the interpreter
+    let defs: Vec<&ValueDef> = root
+        .defs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, def)| match def {
+            Def::Value(value_def)
+                if !root.defs[i + 1..].iter().any(
+                    |later| matches!(later, Def::Value(later) if later.name == value_def.name),
+                ) =>
+            {
+                Some(value_def)
+            }
+            _ => None,
+        })
+        .collect();
+    codegen.undeclared = defs
+        .iter()
+        .map(|def| (def.name.clone(), (*def).clone()))
+        .collect();
+    for def in &defs {
+        if let Some(def) = codegen.undeclared.remove(&def.name) {
+            codegen.declare(&def)?;
+        }
+    }
+    for def in &defs {
+        codegen.define(def)?;
+    }
+    codegen.entry_point(main)?;
 
-    let codegen = CodeGen::new(&module_name, decl_info);
+    codegen.debug_info.finalize();
+    codegen
+        .module
+        .verify()
+        .map_err(|e| anyhow!("LLVM rejected the code generated: {e}"))?;
 
-    Ok(())
+    Ok(codegen.module.print_to_string().to_string())
 }
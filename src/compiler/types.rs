@@ -1,3 +1,5 @@
+use anyhow::Result;
+use arcstr::Substr;
 use inkwell::{
     context::Context,
     types::{BasicType, BasicTypeEnum, StructType},
@@ -12,21 +14,38 @@ pub type DynType<'ctx> = Box<dyn Type<'ctx> + 'ctx>;
 
 pub trait Type<'ctx>: Debug {
     fn llvm_type(&self, codegen: &CodeGen<'ctx>) -> BasicTypeEnum<'ctx>;
+
+    // what caelis calls it, which is also what debuggers show
+    fn name(&self) -> &str;
+
+    // what's in it, for records
+    fn fields(&self) -> &[(Name, TypeLink)] {
+        &[]
+    }
+
+    // where the program defines it, if it does
+    fn text(&self) -> Option<&Substr> {
+        None
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct TypeLink(usize);
+// a type by where it is in `DeclInfo::types`. the built-in types always come first, in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TypeLink(pub usize);
 
-// this is a "shadow" type which pretends to be a real type but actually just proxies for the type
-// at the index it has. magic!
-impl<'ctx> Type<'ctx> for TypeLink {
-    fn llvm_type(&self, codegen: &CodeGen<'ctx>) -> BasicTypeEnum<'ctx> {
+impl TypeLink {
+    pub const INT: TypeLink = TypeLink(0);
+    pub const FLOAT: TypeLink = TypeLink(1);
+    pub const BOOL: TypeLink = TypeLink(2);
+
+    // the type this stands in for
+    pub fn get<'a, 'ctx>(self, codegen: &'a CodeGen<'ctx>) -> &'a dyn Type<'ctx> {
         codegen
             .decl_info
             .types
             .get(self.0)
             .unwrap() // if this fails we're screwed
-            .llvm_type(codegen)
+            .as_ref()
     }
 }
 
@@ -44,38 +63,71 @@ pub enum StructFields {
 }
 
 impl<'ctx> Struct<'ctx> {
-    pub fn new(
-        context: &'ctx Context,
-        name: Name,
-        fields: Vec<(Name, TypeRef)>,
-        generic_args: Option<&Vec<(Name, Vec<TypeRef>)>>,
-    ) -> Self {
-        //TODO: support generics
+    pub fn new(context: &'ctx Context, name: Name, fields: Vec<(Name, TypeRef)>) -> Self {
         Self {
             name: name.clone(),
             llvm_struct: context.opaque_struct_type(&name.text().to_string()),
             fields: StructFields::Unresolved(fields),
         }
     }
+
+    // the fields' types, once every type's been given a place
+    pub fn resolve(&mut self, link: impl Fn(&TypeRef) -> Result<TypeLink>) -> Result<()> {
+        if let StructFields::Unresolved(fields) = &self.fields {
+            let fields = fields
+                .iter()
+                .map(|(name, type_ref)| Ok((name.clone(), link(type_ref)?)))
+                .collect::<Result<_>>()?;
+            self.fields = StructFields::Resolved(fields);
+        }
+
+        Ok(())
+    }
 }
 
 impl<'ctx> Type<'ctx> for Struct<'ctx> {
-    fn llvm_type(&self, codegen: &CodeGen<'ctx>) -> BasicTypeEnum<'ctx> {
+    fn llvm_type(&self, _codegen: &CodeGen<'ctx>) -> BasicTypeEnum<'ctx> {
         self.llvm_struct.as_basic_type_enum()
     }
+
+    fn name(&self) -> &str {
+        self.name.text()
+    }
+
+    fn text(&self) -> Option<&Substr> {
+        Some(self.name.text())
+    }
+
+    // records are resolved before they go in `DeclInfo::types`, so nothing sees them without fields
+    fn fields(&self) -> &[(Name, TypeLink)] {
+        match &self.fields {
+            StructFields::Unresolved(_) => &[],
+            StructFields::Resolved(fields) => fields,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum PrimitiveType {
-    F64,
     I64,
+    F64,
+    Bool,
 }
 
 impl<'ctx> Type<'ctx> for PrimitiveType {
     fn llvm_type(&self, codegen: &CodeGen<'ctx>) -> BasicTypeEnum<'ctx> {
         match self {
-            PrimitiveType::F64 => codegen.decl_info.context.f64_type().as_basic_type_enum(),
             PrimitiveType::I64 => codegen.decl_info.context.i64_type().as_basic_type_enum(),
+            PrimitiveType::F64 => codegen.decl_info.context.f64_type().as_basic_type_enum(),
+            PrimitiveType::Bool => codegen.decl_info.context.bool_type().as_basic_type_enum(),
+        }
+    }
+
+    fn name(&self) -> &str {
+        match self {
+            PrimitiveType::I64 => "Int",
+            PrimitiveType::F64 => "Float",
+            PrimitiveType::Bool => "Bool",
         }
     }
 }
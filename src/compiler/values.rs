@@ -1,6 +1,17 @@
-//TODO: big pain (type inference go brrr)
-pub struct Value<'ctx> {
-    todo: &'ctx str,
-}
+use inkwell::values::FunctionValue;
+
+use super::types::TypeLink;
 
-impl<'ctx> Value<'ctx> {}
+// what a top-level name stands for
+#[derive(Debug, Clone)]
+pub enum Value<'ctx> {
+    // a definition, taking all its parameters at once. values are functions that take none, and work
+    // themselves out again each time they're used
+    Function {
+        function: FunctionValue<'ctx>,
+        params: Vec<TypeLink>,
+        ret: TypeLink,
+    },
+    // a record's constructor, which takes its fields
+    Constructor(TypeLink),
+}
@@ -0,0 +1,255 @@
+use std::{
+    fmt::{Display, Write},
+    ops::Range,
+};
+
+use arcstr::{ArcStr, Substr};
+
+use crate::lexer::{self, Token, TokenKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyntaxKind {
+    Root,
+    GenericDef,
+    GenericArg,
+    ValueDef,
+    TypeDef,
+    Field,
+    FixityDef,
+    TestDef,
+    // a name being bound or referred to: `x`, `(<>)`, or a keyword that shouldn't be there
+    Name,
+    // `:Int` or `:(Int -> Int)`, the type of a parameter or a field
+    TypeAnnotation,
+    NamedType,
+    FunctionType,
+    ParenType,
+    SymbolRef,
+    Func,
+    Call,
+    // operators, composition and pipes, which all become calls
+    Binary,
+    FieldAccess,
+    Paren,
+    IfThenElse,
+    LetIn,
+    Literal,
+    // whatever the parser had to skip to carry on
+    Error,
+}
+
+// what the parser makes: which tokens, by index, each node covers. the tokens themselves, trivia and
+// all, get filled in by `SyntaxNode::new`
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub kind: SyntaxKind,
+    pub span: Range<usize>,
+    pub children: Vec<Node>,
+}
+
+impl Node {
+    pub fn new(kind: SyntaxKind, span: impl Into<Range<usize>>, children: Vec<Node>) -> Self {
+        Self {
+            kind,
+            span: span.into(),
+            children,
+        }
+    }
+
+    // a node covering exactly its children, like `a + b`
+    pub fn around(kind: SyntaxKind, children: Vec<Node>) -> Self {
+        let start = children.first().map_or(0, |child| child.span.start);
+        let end = children.last().map_or(start, |child| child.span.end);
+
+        Self::new(kind, start..end, children)
+    }
+}
+
+// a long chain of operators nests as deep as it's long, too deep to drop each node from inside the one
+// around it
+impl Drop for Node {
+    fn drop(&mut self) {
+        let mut nodes = std::mem::take(&mut self.children);
+
+        while let Some(mut node) = nodes.pop() {
+            nodes.append(&mut node.children);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(Token),
+}
+
+// every token in the file sits somewhere in here, trivia and all, so printing the tree gives back the
+// exact source, broken parts included. the ast is read off this tree
+#[derive(Debug, Clone)]
+pub struct SyntaxNode {
+    pub kind: SyntaxKind,
+    // without the trivia of the first and last token. the root has the whole file
+    pub text: Substr,
+    pub children: Vec<SyntaxElement>,
+}
+
+impl SyntaxNode {
+    // the root gets whatever comes after the last token as well
+    pub fn root(src: &ArcStr, tokens: &[Token], node: Node) -> Self {
+        let mut root = Self::new(src, tokens, node);
+        root.text = src.substr(..);
+        root.children
+            .push(SyntaxElement::Token(lexer::end_of_input(src, tokens)));
+
+        root
+    }
+
+    pub fn new(src: &ArcStr, tokens: &[Token], node: Node) -> Self {
+        Builder {
+            src,
+            tokens,
+            next: node.span.start,
+        }
+        .node(node)
+    }
+
+    pub fn tokens(&self) -> Vec<&Token> {
+        self.children
+            .iter()
+            .flat_map(|child| match child {
+                SyntaxElement::Node(node) => node.tokens(),
+                SyntaxElement::Token(token) => vec![token],
+            })
+            .collect()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &SyntaxNode> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Node(node) => Some(node),
+            SyntaxElement::Token(_) => None,
+        })
+    }
+
+    // the node's own tokens, not its children's
+    pub fn own_tokens(&self) -> impl Iterator<Item = &Token> {
+        self.children.iter().filter_map(|child| match child {
+            SyntaxElement::Token(token) => Some(token),
+            SyntaxElement::Node(_) => None,
+        })
+    }
+
+    pub fn token(&self, kind: TokenKind) -> Option<&Token> {
+        self.own_tokens().find(|token| token.kind == kind)
+    }
+
+    // the outermost node covering exactly `range`
+    pub fn find(&self, range: Range<usize>) -> Option<&SyntaxNode> {
+        if self.text.range() == range {
+            return Some(self);
+        }
+
+        self.nodes().find_map(|node| {
+            let text = node.text.range();
+
+            (text.start <= range.start && range.end <= text.end)
+                .then(|| node.find(range.clone()))
+                .flatten()
+        })
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for token in self.tokens() {
+            write!(f, "{}{}{}", token.leading, token.span, token.trailing)?;
+        }
+
+        Ok(())
+    }
+}
+
+// one line per node and token, with the trivia spelled out
+pub fn dump(node: &SyntaxNode) -> String {
+    let mut out = String::new();
+    dump_node(&mut out, node, 0);
+
+    out
+}
+
+fn dump_node(out: &mut String, node: &SyntaxNode, depth: usize) {
+    let indent = "  ".repeat(depth);
+    writeln!(out, "{indent}{:?}@{:?}", node.kind, node.text.range()).unwrap();
+
+    for child in &node.children {
+        match child {
+            SyntaxElement::Node(node) => dump_node(out, node, depth + 1),
+            SyntaxElement::Token(token) => writeln!(
+                out,
+                "{indent}  {:?}@{:?} {:?} {:?} {:?}",
+                token.kind,
+                token.span.range(),
+                token.leading.as_str(),
+                token.span.as_str(),
+                token.trailing.as_str(),
+            )
+            .unwrap(),
+        }
+    }
+}
+
+struct Builder<'a> {
+    src: &'a ArcStr,
+    tokens: &'a [Token],
+    next: usize,
+}
+
+impl Builder<'_> {
+    // any token in the node's span that isn't part of a child, like keywords and punctuation, belongs
+    // to the node itself
+    fn node(&mut self, mut node: Node) -> SyntaxNode {
+        let mut elements = Vec::new();
+
+        for child in std::mem::take(&mut node.children) {
+            elements.extend(self.tokens_before(child.span.start));
+            elements.push(SyntaxElement::Node(self.node(child)));
+        }
+
+        elements.extend(self.tokens_before(node.span.end));
+
+        SyntaxNode {
+            kind: node.kind,
+            text: self.text(node.span.clone()),
+            children: elements,
+        }
+    }
+
+    fn tokens_before(&mut self, end: usize) -> Vec<SyntaxElement> {
+        let start = self.next;
+        self.next = self.next.max(end.min(self.tokens.len()));
+
+        self.tokens[start..self.next]
+            .iter()
+            .cloned()
+            .map(SyntaxElement::Token)
+            .collect()
+    }
+
+    fn text(&self, span: Range<usize>) -> Substr {
+        let offset = |i: usize| {
+            self.tokens
+                .get(i)
+                .map_or(self.src.len(), |t| t.span.range().start)
+        };
+
+        match span
+            .end
+            .checked_sub(1)
+            .and_then(|last| self.tokens.get(last))
+        {
+            Some(last) if span.start < span.end => {
+                self.src.substr(offset(span.start)..last.span.range().end)
+            }
+            _ => self.src.substr(offset(span.start)..offset(span.start)),
+        }
+    }
+}
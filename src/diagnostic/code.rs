@@ -0,0 +1,132 @@
+use std::fmt::Display;
+
+// codes are never reused once they've shipped. E00xx is the lexer, E01xx the parser, E02xx is reserved
+// for type errors, E03xx for codegen and E04xx is the interpreter
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Code {
+    UnexpectedCharacter,
+    InvalidLiteralSuffix,
+    UnclosedDelimiter,
+    UnmatchedDelimiter,
+    UnclosedString,
+    UnexpectedToken,
+    KeywordAsName,
+    InvalidPrecedence,
+    LiteralOutOfRange,
+    NestedTooDeeply,
+    UnknownType,
+    WrongTypeArguments,
+    UnboundName,
+    NotAFunction,
+    InvalidOperands,
+    DivisionByZero,
+    RecursiveValue,
+    NoSuchField,
+    AssertionFailed,
+    HostFunctionFailed,
+    OutOfFuel,
+    TooDeep,
+    OutOfMemory,
+    TimedOut,
+}
+
+// every code, for looking them up by name
+pub const CODES: &[Code] = &[
+    Code::UnexpectedCharacter,
+    Code::InvalidLiteralSuffix,
+    Code::UnclosedDelimiter,
+    Code::UnmatchedDelimiter,
+    Code::UnclosedString,
+    Code::UnexpectedToken,
+    Code::KeywordAsName,
+    Code::InvalidPrecedence,
+    Code::LiteralOutOfRange,
+    Code::NestedTooDeeply,
+    Code::UnknownType,
+    Code::WrongTypeArguments,
+    Code::UnboundName,
+    Code::NotAFunction,
+    Code::InvalidOperands,
+    Code::DivisionByZero,
+    Code::RecursiveValue,
+    Code::NoSuchField,
+    Code::AssertionFailed,
+    Code::HostFunctionFailed,
+    Code::OutOfFuel,
+    Code::TooDeep,
+    Code::OutOfMemory,
+    Code::TimedOut,
+];
+
+impl Code {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => "E0001",
+            Code::InvalidLiteralSuffix => "E0002",
+            Code::UnclosedDelimiter => "E0003",
+            Code::UnmatchedDelimiter => "E0004",
+            Code::UnclosedString => "E0005",
+            Code::UnexpectedToken => "E0101",
+            Code::KeywordAsName => "E0102",
+            Code::InvalidPrecedence => "E0103",
+            Code::LiteralOutOfRange => "E0104",
+            Code::NestedTooDeeply => "E0105",
+            Code::UnknownType => "E0201",
+            Code::WrongTypeArguments => "E0202",
+            Code::UnboundName => "E0401",
+            Code::NotAFunction => "E0402",
+            Code::InvalidOperands => "E0403",
+            Code::DivisionByZero => "E0404",
+            Code::RecursiveValue => "E0405",
+            Code::NoSuchField => "E0406",
+            Code::AssertionFailed => "E0407",
+            Code::HostFunctionFailed => "E0408",
+            Code::OutOfFuel => "E0409",
+            Code::TooDeep => "E0410",
+            Code::OutOfMemory => "E0411",
+            Code::TimedOut => "E0412",
+        }
+    }
+
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => include_str!("explanations/E0001.md"),
+            Code::InvalidLiteralSuffix => include_str!("explanations/E0002.md"),
+            Code::UnclosedDelimiter => include_str!("explanations/E0003.md"),
+            Code::UnmatchedDelimiter => include_str!("explanations/E0004.md"),
+            Code::UnclosedString => include_str!("explanations/E0005.md"),
+            Code::UnexpectedToken => include_str!("explanations/E0101.md"),
+            Code::KeywordAsName => include_str!("explanations/E0102.md"),
+            Code::InvalidPrecedence => include_str!("explanations/E0103.md"),
+            Code::LiteralOutOfRange => include_str!("explanations/E0104.md"),
+            Code::NestedTooDeeply => include_str!("explanations/E0105.md"),
+            Code::UnknownType => include_str!("explanations/E0201.md"),
+            Code::WrongTypeArguments => include_str!("explanations/E0202.md"),
+            Code::UnboundName => include_str!("explanations/E0401.md"),
+            Code::NotAFunction => include_str!("explanations/E0402.md"),
+            Code::InvalidOperands => include_str!("explanations/E0403.md"),
+            Code::DivisionByZero => include_str!("explanations/E0404.md"),
+            Code::RecursiveValue => include_str!("explanations/E0405.md"),
+            Code::NoSuchField => include_str!("explanations/E0406.md"),
+            Code::AssertionFailed => include_str!("explanations/E0407.md"),
+            Code::HostFunctionFailed => include_str!("explanations/E0408.md"),
+            Code::OutOfFuel => include_str!("explanations/E0409.md"),
+            Code::TooDeep => include_str!("explanations/E0410.md"),
+            Code::OutOfMemory => include_str!("explanations/E0411.md"),
+            Code::TimedOut => include_str!("explanations/E0412.md"),
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<Code> {
+        CODES
+            .iter()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+            .copied()
+    }
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
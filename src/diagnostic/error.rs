@@ -0,0 +1,100 @@
+use chumsky::{
+    error::{Error, Rich, RichPattern, RichReason},
+    input::Input,
+    label::LabelError,
+    prelude::SimpleSpan,
+    util::MaybeRef,
+};
+
+use super::Code;
+
+// `Rich` plus the code of whatever went wrong. everything chumsky asks of an error is passed through to
+// `Rich`, the code just has to follow along with whichever reason survives
+#[derive(Debug, Clone)]
+pub struct SyntaxError<'src, T> {
+    pub code: Code,
+    pub rich: Rich<'src, T>,
+}
+
+impl<'src, T> SyntaxError<'src, T> {
+    pub fn custom(code: Code, span: SimpleSpan, msg: impl ToString) -> Self {
+        Self {
+            code,
+            rich: Rich::custom(span, msg),
+        }
+    }
+
+    pub fn span(&self) -> &SimpleSpan {
+        self.rich.span()
+    }
+}
+
+impl<'src, I> Error<'src, I> for SyntaxError<'src, I::Token>
+where
+    I: Input<'src, Span = SimpleSpan>,
+    I::Token: PartialEq,
+{
+    fn merge(self, other: Self) -> Self {
+        // `Rich` keeps the first custom reason it sees, otherwise it merges into `self`
+        let code = match (self.rich.reason(), other.rich.reason()) {
+            (RichReason::Custom(_), _) => self.code,
+            (_, RichReason::Custom(_)) => other.code,
+            _ => self.code,
+        };
+
+        Self {
+            code,
+            rich: Error::<I>::merge(self.rich, other.rich),
+        }
+    }
+}
+
+impl<'src, I, L> LabelError<'src, I, L> for SyntaxError<'src, I::Token>
+where
+    I: Input<'src, Span = SimpleSpan>,
+    I::Token: PartialEq,
+    L: Into<RichPattern<'src, I::Token>>,
+{
+    fn expected_found<E: IntoIterator<Item = L>>(
+        expected: E,
+        found: Option<MaybeRef<'src, I::Token>>,
+        span: SimpleSpan,
+    ) -> Self {
+        Self {
+            code: Code::UnexpectedToken,
+            rich: LabelError::<I, L>::expected_found(expected, found, span),
+        }
+    }
+
+    fn merge_expected_found<E: IntoIterator<Item = L>>(
+        self,
+        expected: E,
+        found: Option<MaybeRef<'src, I::Token>>,
+        span: SimpleSpan,
+    ) -> Self {
+        Self {
+            code: self.code,
+            rich: LabelError::<I, L>::merge_expected_found(self.rich, expected, found, span),
+        }
+    }
+
+    fn replace_expected_found<E: IntoIterator<Item = L>>(
+        self,
+        expected: E,
+        found: Option<MaybeRef<'src, I::Token>>,
+        span: SimpleSpan,
+    ) -> Self {
+        Self {
+            code: Code::UnexpectedToken,
+            rich: LabelError::<I, L>::replace_expected_found(self.rich, expected, found, span),
+        }
+    }
+
+    fn label_with(&mut self, label: L) {
+        LabelError::<I, L>::label_with(&mut self.rich, label)
+    }
+
+    fn in_context(&mut self, label: L, span: SimpleSpan) {
+        LabelError::<I, L>::in_context(&mut self.rich, label, span)
+    }
+}
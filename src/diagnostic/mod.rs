@@ -0,0 +1,242 @@
+use std::{io::Write, ops::Range};
+
+use anyhow::{Context, Result};
+use ariadne::{Color, Report, ReportKind};
+use serde::Serialize;
+
+use crate::lexer::{Token, TokenKind};
+
+pub use code::Code;
+pub use error::SyntaxError;
+pub use source_map::{FileId, SourceFile, SourceMap};
+
+mod code;
+mod error;
+mod source_map;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Format {
+    Human,
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: Code,
+    pub message: String,
+    pub range: Range<usize>,
+    pub label: String,
+    pub secondary: Vec<(Range<usize>, String)>,
+    pub suggestions: Vec<Suggestion>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub message: String,
+    pub range: Range<usize>,
+    pub replacement: String,
+}
+
+impl Diagnostic {
+    pub fn from_lex_error(e: SyntaxError<char>) -> Self {
+        let code = e.code;
+        let e = e.rich.map_token(|c| c.to_string());
+
+        Self {
+            severity: Severity::Error,
+            code,
+            message: e.to_string(),
+            range: e.span().into_range(),
+            label: e.reason().to_string(),
+            secondary: Vec::new(),
+            suggestions: Vec::new(),
+        }
+    }
+
+    // everything the lexer and parser found, in that order. the parser trips over anything the lexer
+    // turned into an error token, but the lexer has already said what's wrong with it
+    pub fn from_syntax_errors(
+        lex_errs: Vec<SyntaxError<char>>,
+        parse_errs: Vec<SyntaxError<Token>>,
+        tokens: &[Token],
+        eof: usize,
+    ) -> Vec<Self> {
+        lex_errs
+            .into_iter()
+            .map(Diagnostic::from_lex_error)
+            .chain(
+                parse_errs
+                    .into_iter()
+                    .filter(|e| {
+                        tokens
+                            .get(e.span().start)
+                            .is_none_or(|t| t.kind != TokenKind::Error)
+                    })
+                    .map(|e| Diagnostic::from_parse_error(e, tokens, eof)),
+            )
+            .collect()
+    }
+
+    // parse errors are spanned in token indices, not bytes
+    pub fn from_parse_error(e: SyntaxError<Token>, tokens: &[Token], eof: usize) -> Self {
+        let token_range = |span: Range<usize>| {
+            let start = tokens.get(span.start).map(|t| t.span.range().start).unwrap_or(eof);
+            let end = span
+                .end
+                .checked_sub(1)
+                .and_then(|last| tokens.get(last))
+                .map(|t| t.span.range().end)
+                .unwrap_or(eof)
+                .max(start);
+
+            start..end
+        };
+        let code = e.code;
+        let range = token_range(e.span().into_range());
+        let suggestions = match (code, tokens.get(e.span().start)) {
+            (Code::KeywordAsName, Some(token)) => vec![Suggestion {
+                message: format!("rename it to something like `{}_`", token.span),
+                range: range.clone(),
+                replacement: format!("{}_", token.span),
+            }],
+            _ => Vec::new(),
+        };
+        let e = e.rich.map_token(|t| t.kind.to_string());
+
+        Self {
+            severity: Severity::Error,
+            code,
+            message: e.to_string(),
+            range,
+            label: e.reason().to_string(),
+            secondary: e
+                .contexts()
+                .map(|(label, span)| (token_range(span.into_range()), format!("while parsing this {label}")))
+                .collect(),
+            suggestions,
+        }
+    }
+
+    // every diagnostic gets printed through here, to stdout, whichever file it's about
+    pub fn report(&self, sources: &SourceMap, file: FileId, format: Format) -> Result<()> {
+        match format {
+            Format::Human => self.render(sources, file),
+            Format::Json => writeln!(std::io::stdout(), "{}", self.to_json(sources.get(file))?)
+                .context("can't print a diagnostic"),
+        }
+    }
+
+    fn render(&self, sources: &SourceMap, file: FileId) -> Result<()> {
+        let kind = match self.severity {
+            Severity::Error => ReportKind::Error,
+        };
+        let mut report = Report::build(kind, (file, self.range.clone()))
+            .with_code(self.code.as_str())
+            .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
+            .with_message(&self.message)
+            .with_label(
+                ariadne::Label::new((file, self.range.clone()))
+                    .with_message(&self.label)
+                    .with_color(Color::Red),
+            )
+            .with_labels(self.secondary.iter().map(|(range, message)| {
+                ariadne::Label::new((file, range.clone()))
+                    .with_message(message)
+                    .with_color(Color::Yellow)
+            }));
+
+        for suggestion in &self.suggestions {
+            report = report.with_help(&suggestion.message);
+        }
+
+        report
+            .finish()
+            .print(sources)
+            .context("can't print a diagnostic")
+    }
+
+    fn to_json(&self, file: &SourceFile) -> Result<String> {
+        let span = |range: &Range<usize>| JsonSpan::new(file, range.clone());
+
+        serde_json::to_string(&JsonDiagnostic {
+            severity: self.severity,
+            code: self.code.as_str(),
+            message: &self.message,
+            span: span(&self.range),
+            labels: self
+                .secondary
+                .iter()
+                .map(|(range, message)| JsonLabel { span: span(range), message })
+                .collect(),
+            suggestions: self
+                .suggestions
+                .iter()
+                .map(|suggestion| JsonSuggestion {
+                    message: &suggestion.message,
+                    span: span(&suggestion.range),
+                    replacement: &suggestion.replacement,
+                })
+                .collect(),
+        })
+        .context("can't write a diagnostic as JSON")
+    }
+}
+
+#[derive(Serialize)]
+struct JsonDiagnostic<'a> {
+    severity: Severity,
+    code: &'static str,
+    message: &'a str,
+    span: JsonSpan<'a>,
+    labels: Vec<JsonLabel<'a>>,
+    suggestions: Vec<JsonSuggestion<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonLabel<'a> {
+    span: JsonSpan<'a>,
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct JsonSuggestion<'a> {
+    message: &'a str,
+    span: JsonSpan<'a>,
+    replacement: &'a str,
+}
+
+// lines and columns are 1-based, columns count chars rather than bytes
+#[derive(Serialize)]
+struct JsonSpan<'a> {
+    file: &'a str,
+    start: usize,
+    end: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+}
+
+impl<'a> JsonSpan<'a> {
+    fn new(file: &'a SourceFile, range: Range<usize>) -> Self {
+        let (line, column) = file.line_column(range.start);
+        let (end_line, end_column) = file.line_column(range.end);
+
+        Self {
+            file: &file.name,
+            start: range.start,
+            end: range.end,
+            line,
+            column,
+            end_line,
+            end_column,
+        }
+    }
+}
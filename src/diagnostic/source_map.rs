@@ -0,0 +1,95 @@
+use std::fmt::Display;
+
+use arcstr::{ArcStr, Substr};
+use ariadne::{Cache, Source};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(usize);
+
+// every file diagnostics can point into. spans are `Substr`s, which know the text they were cut from
+// but not which file that was, so files are found by their text
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+pub struct SourceFile {
+    pub name: String,
+    pub src: ArcStr,
+    // where each line starts, in bytes
+    lines: Vec<usize>,
+    // ariadne's view of the same text, kept so it isn't worked out again for every diagnostic
+    source: Source<ArcStr>,
+}
+
+impl SourceMap {
+    pub fn add(&mut self, name: impl ToString, src: ArcStr) -> FileId {
+        self.files.push(SourceFile::new(name, src));
+
+        FileId(self.files.len() - 1)
+    }
+
+    // `src` as the file called `name`, in place of whatever that file held before
+    pub fn set(&mut self, name: impl ToString, src: ArcStr) -> FileId {
+        let file = SourceFile::new(name, src);
+
+        match self.files.iter().position(|f| f.name == file.name) {
+            Some(i) => {
+                self.files[i] = file;
+                FileId(i)
+            }
+            None => {
+                self.files.push(file);
+                FileId(self.files.len() - 1)
+            }
+        }
+    }
+
+    pub fn get(&self, file: FileId) -> &SourceFile {
+        &self.files[file.0]
+    }
+
+    // the file `text` was cut from, if it's one of these
+    pub fn find(&self, text: &Substr) -> Option<FileId> {
+        self.files
+            .iter()
+            .rposition(|file| ArcStr::ptr_eq(&file.src, text.parent()))
+            .map(FileId)
+    }
+}
+
+impl SourceFile {
+    pub fn new(name: impl ToString, src: ArcStr) -> Self {
+        Self {
+            name: name.to_string(),
+            lines: std::iter::once(0)
+                .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+                .collect(),
+            source: Source::from(src.clone()),
+            src,
+        }
+    }
+
+    // 1-based, with columns counting chars rather than bytes
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.src.len());
+        let line = self.lines.partition_point(|start| *start <= offset) - 1;
+
+        (
+            line + 1,
+            self.src[self.lines[line]..offset].chars().count() + 1,
+        )
+    }
+}
+
+impl Cache<FileId> for &SourceMap {
+    type Storage = ArcStr;
+
+    fn fetch(&mut self, file: &FileId) -> Result<&Source<ArcStr>, impl std::fmt::Debug> {
+        Ok::<_, ()>(&self.get(*file).source)
+    }
+
+    fn display<'a>(&self, file: &'a FileId) -> Option<impl Display + 'a> {
+        Some(self.get(*file).name.clone())
+    }
+}
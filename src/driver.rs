@@ -0,0 +1,326 @@
+// what the `caelis` command does, a function for each subcommand. diagnostics are reported as they come
+// up, and a failure that's already been reported comes back as `Reported` so there's nothing left to do
+// with it but exit
+use std::fs;
+
+use anyhow::{bail, Context, Result};
+use arcstr::ArcStr;
+
+use crate::{
+    ast::{Ast, Def, Json, Root},
+    bytecode, cst, interp,
+    value::RuntimeError,
+    Code, Config, Diagnostic, Emit, Error, FileId, Format, Limits, SourceMap,
+};
+
+// diagnostics that have already been printed, so there's nothing left to say about them but to exit
+// with a failure
+#[derive(Debug)]
+pub struct Reported;
+
+impl std::fmt::Display for Reported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the errors have been reported")
+    }
+}
+
+impl std::error::Error for Reported {}
+
+// runs a command on a thread with room for the deepest programs, since checking, compiling and
+// formatting all recurse as deep as the program is nested
+pub use crate::interp::with_stack;
+
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum AstFormat {
+    Debug,
+    Json,
+    /// The lossless syntax tree, trivia included
+    Cst,
+}
+
+pub fn explain(code: &str) -> Result<()> {
+    match Code::parse(code) {
+        Some(code) => print!("{}", code.explanation()),
+        None => bail!("`{code}` is not a caelis error code"),
+    }
+
+    Ok(())
+}
+
+pub fn parse(file: &str, format: AstFormat, error_format: Format) -> Result<()> {
+    let mut sources = SourceMap::default();
+    let (id, src) = read(&mut sources, file)?;
+    let (tree, diagnostics) = crate::syntax_tree(&src);
+    let ok = diagnostics.is_empty();
+
+    // the syntax tree has whatever didn't parse in it too, so it's printed either way
+    if let AstFormat::Cst = format {
+        print!("{}", cst::dump(&tree));
+    }
+
+    report(&sources, id, diagnostics, error_format)?;
+    if !ok {
+        return Err(Reported.into());
+    }
+
+    let root = crate::lower(&tree);
+    match format {
+        AstFormat::Debug => println!("{:#?}", root),
+        AstFormat::Json => println!(
+            "{}",
+            serde_json::to_string_pretty(&Json::new(&root, sources.get(id)))?
+        ),
+        AstFormat::Cst => (),
+    }
+
+    Ok(())
+}
+
+pub fn lsp() -> Result<()> {
+    crate::lsp::run().context("language server failed")
+}
+
+pub fn repl() -> Result<()> {
+    crate::repl::run().context("repl failed")
+}
+
+// source files are compiled first, `.caeb` files are run as they are
+pub fn run(file: &str, interp: bool, limits: Limits, error_format: Format) -> Result<()> {
+    let mut sources = SourceMap::default();
+
+    if interp {
+        return interpret(&mut sources, file, limits, error_format);
+    }
+
+    let (id, program) = match file.ends_with(".caeb") {
+        true => {
+            let program = fs::read(file)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| bytecode::decode(&bytes))
+                .with_context(|| format!("can't load `{file}`"))?;
+
+            (sources.add(&program.file, program.source.clone()), program)
+        }
+        false => {
+            let (id, src) = read(&mut sources, file)?;
+            let program = crate::compile_program(file, &src)
+                .map_err(|e| reported(&sources, id, e, error_format))?;
+
+            (id, program)
+        }
+    };
+
+    let value =
+        crate::run(&program, limits).map_err(|e| reported(&sources, id, e, error_format))?;
+    println!("{value}");
+
+    Ok(())
+}
+
+fn interpret(
+    sources: &mut SourceMap,
+    file: &str,
+    limits: Limits,
+    error_format: Format,
+) -> Result<()> {
+    if file.ends_with(".caeb") {
+        bail!("`{file}` is already compiled, it can't be interpreted");
+    }
+
+    let root = parse_file(sources, file, error_format)?;
+    let main = root
+        .defs
+        .iter()
+        .find_map(|def| match def {
+            Def::Value(value_def) if value_def.name.text().as_str() == "main" => {
+                Some(&value_def.name)
+            }
+            _ => None,
+        })
+        .with_context(|| format!("`{file}` has no `main` to run"))?;
+
+    // values can't leave the interpreter's thread, so they get printed there
+    let value = interp::with_stack(|| {
+        let mut interpreter = interp::Interpreter::with_limits(limits);
+        interpreter.define(&root.defs)?;
+
+        interpreter.get(main).map(|value| value.to_string())
+    })
+    .map_err(|e| runtime_error(sources, &e, error_format))?;
+
+    println!("{value}");
+
+    Ok(())
+}
+
+// `output` is next to the file by default
+pub fn build(file: &str, emit: Emit, output: Option<&str>, error_format: Format) -> Result<()> {
+    let mut sources = SourceMap::default();
+    let (id, src) = read(&mut sources, file)?;
+    let (bytes, extension) = crate::compile(file, &src, &Config { emit })
+        .map_err(|e| reported(&sources, id, e, error_format))?
+        .to_bytes();
+    let output = output.map(str::to_string).unwrap_or_else(|| {
+        std::path::Path::new(file)
+            .with_extension(extension)
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    fs::write(&output, bytes).with_context(|| format!("can't write `{output}`"))
+}
+
+// every test in every file, with a summary for each file like cargo's
+pub fn test(
+    files: &[String],
+    filter: Option<&str>,
+    limits: Limits,
+    error_format: Format,
+) -> Result<()> {
+    let mut sources = SourceMap::default();
+    let mut failed = false;
+
+    for file in files {
+        let root = match parse_file(&mut sources, file, error_format) {
+            Ok(root) => root,
+            Err(e) if e.is::<Reported>() => {
+                failed = true;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let outcomes = match crate::test::run(file, &root, filter, limits) {
+            Ok(outcomes) => outcomes,
+            Err(e) => {
+                e.report(&sources, error_format)?;
+                failed = true;
+                continue;
+            }
+        };
+        let filtered = crate::test::tests(&root).count() - outcomes.len();
+        let passed = outcomes.iter().filter(|o| o.result.is_ok()).count();
+
+        println!("running {} tests in {file}", outcomes.len());
+
+        for outcome in &outcomes {
+            let status = match outcome.result {
+                Ok(()) => "ok",
+                Err(_) => "FAILED",
+            };
+            println!("test {} ... {status}", outcome.test.literal);
+        }
+
+        let failures: Vec<_> = outcomes
+            .iter()
+            .filter_map(|outcome| outcome.result.as_ref().err())
+            .collect();
+
+        let status = match failures.len() {
+            0 => "ok",
+            _ => "FAILED",
+        };
+
+        if !failures.is_empty() {
+            println!();
+            for e in failures {
+                e.report(&sources, error_format)?;
+            }
+            failed = true;
+        }
+
+        println!(
+            "\ntest result: {status}. {passed} passed; {} failed; {filtered} filtered out\n",
+            outcomes.len() - passed,
+        );
+    }
+
+    match failed {
+        true => Err(Reported.into()),
+        false => Ok(()),
+    }
+}
+
+// with `check`, files that aren't formatted are only listed, and that's a failure
+pub fn fmt(files: &[String], check: bool, error_format: Format) -> Result<()> {
+    let mut sources = SourceMap::default();
+    let mut failed = false;
+
+    for file in files {
+        let (id, src) = read(&mut sources, file)?;
+        let (tree, diagnostics) = crate::syntax_tree(&src);
+
+        // there's nothing sensible to do with a file that doesn't parse
+        if !diagnostics.is_empty() {
+            report(&sources, id, diagnostics, error_format)?;
+            failed = true;
+            continue;
+        }
+
+        let formatted = crate::fmt::format(&tree, &crate::lower(&tree));
+
+        if formatted == src {
+            continue;
+        } else if check {
+            println!("{file} is not formatted");
+            failed = true;
+        } else {
+            fs::write(file, formatted).with_context(|| format!("can't write `{file}`"))?;
+        }
+    }
+
+    match failed {
+        true => Err(Reported.into()),
+        false => Ok(()),
+    }
+}
+
+fn read(sources: &mut SourceMap, filename: &str) -> Result<(FileId, ArcStr)> {
+    let src = ArcStr::from(
+        fs::read_to_string(filename).with_context(|| format!("can't read `{filename}`"))?,
+    );
+
+    Ok((sources.add(filename, src.clone()), src))
+}
+
+// lexes and parses `filename`, reporting any diagnostics. there's only a tree if nothing went wrong
+fn parse_file(sources: &mut SourceMap, filename: &str, error_format: Format) -> Result<Root> {
+    let (file, src) = read(sources, filename)?;
+    let (root, diagnostics) = crate::parse(&src);
+    let ok = diagnostics.is_empty();
+
+    report(sources, file, diagnostics, error_format)?;
+
+    ok.then_some(root).ok_or_else(|| Reported.into())
+}
+
+fn report(
+    sources: &SourceMap,
+    file: FileId,
+    diagnostics: Vec<Diagnostic>,
+    error_format: Format,
+) -> Result<()> {
+    for diagnostic in diagnostics {
+        diagnostic.report(sources, file, error_format)?;
+    }
+
+    Ok(())
+}
+
+// `Reported` once the diagnostics have been, or whatever else went wrong
+fn reported(sources: &SourceMap, file: FileId, e: Error, error_format: Format) -> anyhow::Error {
+    match e {
+        Error::Diagnostics(diagnostics) => match report(sources, file, diagnostics, error_format) {
+            Ok(()) => Reported.into(),
+            Err(e) => e,
+        },
+        Error::Other(e) => e,
+    }
+}
+
+fn runtime_error(sources: &SourceMap, e: &RuntimeError, error_format: Format) -> anyhow::Error {
+    match e.report(sources, error_format) {
+        Ok(()) => Reported.into(),
+        Err(e) => e,
+    }
+}
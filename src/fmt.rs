@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+
+use arcstr::{ArcStr, Substr};
+
+use crate::{
+    ast::{Ast, Def, Expr, Name, Root, ValueDef},
+    cst::SyntaxNode,
+    lexer::{self, Token, TokenKind, TriviaKind},
+};
+
+// definitions longer than this get their curried parameters split over several lines
+const MAX_WIDTH: usize = 100;
+
+// the ast doesn't remember parentheses, which way a pipe was written or where the comments were, so
+// formatting works on the syntax tree's tokens. the ast only decides where lines break
+pub fn format(tree: &SyntaxNode, root: &Root) -> String {
+    // what's after the last token is printed from the source
+    let tokens: Vec<Token> = tree
+        .tokens()
+        .into_iter()
+        .filter(|t| t.kind != TokenKind::Eof)
+        .cloned()
+        .collect();
+    let mut layout = Layout {
+        tokens: &tokens,
+        starts: tokens
+            .iter()
+            .enumerate()
+            .map(|(i, t)| (t.span.range().start, i))
+            .collect(),
+        breaks: HashMap::new(),
+    };
+
+    for def in &root.defs {
+        if let Some(start) = layout.start_of(def.text().range().start) {
+            layout.breaks.insert(start, 0);
+        }
+
+        if let Def::Value(value_def) = def {
+            layout.value_def(value_def, 0);
+        }
+    }
+
+    Printer::new(tree.text.parent()).print(&tokens, &layout.breaks)
+}
+
+struct Layout<'a> {
+    tokens: &'a [Token],
+    starts: HashMap<usize, usize>,
+    // token index to the indentation of the line it has to start
+    breaks: HashMap<usize, usize>,
+}
+
+// a node whose text doesn't start or end on a token, which shouldn't happen, is left where it is
+impl Layout<'_> {
+    // the token starting at `offset`
+    fn token(&self, offset: usize) -> Option<usize> {
+        self.starts.get(&offset).copied()
+    }
+
+    // `(<>)` is named by its operator, but the definition starts at the parenthesis
+    fn start_of(&self, offset: usize) -> Option<usize> {
+        let i = self.token(offset)?;
+
+        Some(
+            match (
+                i.checked_sub(1).map(|i| &self.tokens[i].kind),
+                &self.tokens[i].kind,
+            ) {
+                (Some(TokenKind::OpenParen), TokenKind::Operator) => i - 1,
+                _ => i,
+            },
+        )
+    }
+
+    // whether the source breaks the line anywhere between tokens `from` and `to`
+    fn broken(&self, from: usize, to: usize) -> bool {
+        self.tokens[from..to]
+            .iter()
+            .any(|token| token.trailing.contains('\n'))
+            || self.tokens[from + 1..=to]
+                .iter()
+                .any(|token| token.leading.contains('\n'))
+    }
+
+    fn name_start(&self, name: &Name) -> Option<usize> {
+        self.start_of(name.text().range().start)
+    }
+
+    fn value_def(&mut self, def: &ValueDef, indent: usize) -> Option<()> {
+        let start = self.name_start(&def.name)?;
+        let end = self.token(def.text.range().end - 1)?;
+        let mut params = Vec::new();
+        let mut body = &def.body;
+
+        // a function without a parameter type was made up for something like `f >> g`
+        while let Expr::Func(_, name, Some(_), _, inner) = body {
+            params.push(self.name_start(name)?);
+            body = inner;
+        }
+
+        let block = matches!(body, Expr::LetIn(..));
+        // parameters that are already on lines of their own stay that way
+        let broken = params.windows(2).any(|pair| self.broken(pair[0], pair[1]));
+
+        // curried parameters line up under the first one, and a body that was on a line of its own
+        // still is
+        if params.len() > 1
+            && (block || broken || indent + flat(&self.tokens[start..=end]).len() > MAX_WIDTH)
+        {
+            let column = indent + flat(&self.tokens[start..params[0]]).chars().count() + 1;
+
+            for param in &params[1..] {
+                self.breaks.insert(*param, column);
+            }
+
+            let last = params[params.len() - 1];
+            let mut body_start = self.token(body.text().range().start)?;
+            while body_start > last && self.tokens[body_start - 1].kind == TokenKind::OpenParen {
+                body_start -= 1;
+            }
+            if !block && self.broken(last, body_start) {
+                self.breaks.insert(body_start, indent + 2);
+            }
+        }
+
+        if let Expr::LetIn(..) = body {
+            self.let_in(body, indent + 2);
+            self.breaks.insert(end, indent);
+        }
+
+        Some(())
+    }
+
+    // let
+    //   a = 1;
+    // in a
+    //
+    // and a `let` in parentheses starts its line with them: `(let`
+    fn let_in(&mut self, expr: &Expr, indent: usize) -> Option<()> {
+        let Expr::LetIn(text, defs, body) = expr else {
+            return Some(());
+        };
+
+        let mut start = self.token(text.range().start)?;
+        while start > 0 && self.tokens[start - 1].kind == TokenKind::OpenParen {
+            start -= 1;
+        }
+        self.breaks.insert(start, indent);
+
+        for def in defs {
+            if let Some(name) = self.name_start(&def.name) {
+                self.breaks.insert(name, indent + 2);
+            }
+            self.value_def(def, indent + 2);
+        }
+
+        // the body might be in parentheses, which it doesn't know about
+        let body_start = self.token(body.text().range().start)?;
+        if let Some(keyword) = (0..body_start)
+            .rev()
+            .find(|i| self.tokens[*i].kind == TokenKind::In)
+        {
+            self.breaks.insert(keyword, indent);
+        }
+
+        self.let_in(body, indent)
+    }
+}
+
+fn spaced(prev: &Token, next: &Token) -> bool {
+    !matches!(
+        prev.kind,
+        TokenKind::OpenParen | TokenKind::Colon | TokenKind::Period
+    ) && !matches!(
+        next.kind,
+        TokenKind::CloseParen | TokenKind::Comma | TokenKind::Semicolon | TokenKind::Period
+    )
+}
+
+fn flat(tokens: &[Token]) -> String {
+    let mut out = String::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && spaced(&tokens[i - 1], token) {
+            out.push(' ');
+        }
+
+        out.push_str(&token.span);
+    }
+
+    out
+}
+
+struct Comment {
+    text: Substr,
+    own_line: bool,
+    blank_before: bool,
+}
+
+// the comments in the trivia between two tokens, plus whether there's a blank line after the last of them
+fn comments(gaps: &[&Substr]) -> (Vec<Comment>, bool) {
+    let mut comments = Vec::new();
+    let mut newlines = 0;
+
+    for trivia in gaps.iter().flat_map(|gap| lexer::trivia(gap)) {
+        match trivia.kind {
+            TriviaKind::Whitespace => newlines += trivia.text.matches('\n').count(),
+            TriviaKind::Comment => {
+                comments.push(Comment {
+                    text: trivia.text.substr(..trivia.text.trim_end().len()),
+                    own_line: newlines > 0,
+                    blank_before: newlines > 1,
+                });
+                newlines = 0;
+            }
+        }
+    }
+
+    (comments, newlines > 1)
+}
+
+struct Printer<'a> {
+    src: &'a ArcStr,
+    out: String,
+    indent: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(src: &'a ArcStr) -> Self {
+        Self {
+            src,
+            out: String::new(),
+            indent: 0,
+        }
+    }
+
+    fn newline(&mut self, blank: bool, indent: usize) {
+        if self.out.is_empty() {
+            return;
+        }
+
+        self.out.truncate(self.out.trim_end().len());
+        self.out.push('\n');
+        if blank {
+            self.out.push('\n');
+        }
+
+        self.out.push_str(&" ".repeat(indent));
+        self.indent = indent;
+    }
+
+    fn print(mut self, tokens: &[Token], breaks: &HashMap<usize, usize>) -> String {
+        for (i, token) in tokens.iter().enumerate() {
+            let (comments, blank) = match i.checked_sub(1) {
+                Some(prev) => comments(&[&tokens[prev].trailing, &token.leading]),
+                None => comments(&[&token.leading]),
+            };
+            // anything that has to go on a new line without being told where lines up a bit further in
+            let continuation = self.indent + 2;
+            let indent = breaks.get(&i).copied();
+            let mut broken = false;
+
+            for comment in &comments {
+                if comment.own_line {
+                    self.newline(comment.blank_before, indent.unwrap_or(continuation));
+                } else if !self.out.is_empty() {
+                    self.out.push(' ');
+                }
+
+                self.out.push_str(&comment.text);
+                broken = true;
+            }
+
+            match indent {
+                Some(indent) => self.newline(blank, indent),
+                None if broken => self.newline(blank, continuation),
+                None if i > 0 && spaced(&tokens[i - 1], token) => self.out.push(' '),
+                None => (),
+            }
+
+            self.out.push_str(&token.span);
+        }
+
+        let eof = lexer::end_of_input(self.src, tokens);
+        let (comments, _) = match tokens.last() {
+            Some(last) => comments(&[&last.trailing, &eof.leading]),
+            None => comments(&[&eof.leading]),
+        };
+
+        for comment in &comments {
+            if comment.own_line {
+                self.newline(comment.blank_before, 0);
+            } else if !self.out.is_empty() {
+                self.out.push(' ');
+            }
+
+            self.out.push_str(&comment.text);
+        }
+
+        self.out.truncate(self.out.trim_end().len());
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+
+        self.out
+    }
+}
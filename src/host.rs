@@ -0,0 +1,335 @@
+use std::{fmt::Display, rc::Rc};
+
+use anyhow::anyhow;
+use arcstr::{ArcStr, Substr};
+use chumsky::Parser;
+
+use crate::{
+    analysis::{Type, BUILTIN_TYPES},
+    ast::{Ast, TypeRef},
+    bytecode::{self, Program, Vm},
+    cst::SyntaxNode,
+    diagnostic::{Code, Diagnostic, Severity},
+    interp, lexer,
+    limits::Limits,
+    lower, parser,
+    value::{self, Record, RuntimeError},
+    Error,
+};
+
+// a value on the host's side. functions never leave caelis, so there's one of these for everything
+// else
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    // a record's type and its fields, in the order the type gives them
+    Record(String, Vec<(String, Value)>),
+}
+
+// a rust function a program can call like a built-in, and the type it says it has
+pub struct HostFunction {
+    pub name: String,
+    pub signature: TypeRef,
+    ty: Type,
+    function: Box<Function>,
+}
+
+type Function = dyn Fn(&[Value]) -> Result<Value, String>;
+
+// the functions a rust program provides to the caelis code it runs
+#[derive(Default)]
+pub struct Host {
+    functions: Vec<Rc<HostFunction>>,
+    limits: Limits,
+}
+
+// a program loaded by a host, ready to have its definitions called. it can't do anything but work
+// values out, apart from calling the host's functions
+pub struct Script {
+    program: Program,
+    functions: Vec<Rc<HostFunction>>,
+    limits: Limits,
+}
+
+impl Host {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // what every call into the scripts this loads is allowed to do
+    pub fn limit(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    // `signature` is a type like `Int -> Int -> Bool`, made of the built-in types. the function is called
+    // once it's been given an argument for every parameter, with ones of the types the signature says.
+    // registering a name again replaces the function
+    pub fn register(
+        &mut self,
+        name: &str,
+        signature: &str,
+        function: impl Fn(&[Value]) -> Result<Value, String> + 'static,
+    ) -> Result<(), Error> {
+        let src = ArcStr::from(signature);
+        let (tokens, errs) = lexer::tokenize(&src);
+        let (signature, parse_errs) = parser::signature()
+            .parse(tokens.as_slice())
+            .into_output_errors();
+        let signature =
+            signature.and_then(|node| lower::type_ref(&SyntaxNode::new(&src, &tokens, node)));
+
+        let diagnostics = Diagnostic::from_syntax_errors(errs, parse_errs, &tokens, src.len());
+
+        let Some(signature) = signature.filter(|_| diagnostics.is_empty()) else {
+            return Err(Error::Diagnostics(diagnostics));
+        };
+
+        let mut diagnostics = Vec::new();
+        resolve(&signature, &mut diagnostics);
+
+        if !diagnostics.is_empty() {
+            return Err(Error::Diagnostics(diagnostics));
+        }
+
+        let ty = Type::from(&signature);
+        let (params, ret) = ty.params();
+
+        if params.is_empty() {
+            return Err(Error::Other(anyhow!(
+                "`{name}` has to be a function, not `{ty}`"
+            )));
+        } else if params.iter().chain([&ret]).any(|ty| named(ty).is_none()) {
+            return Err(Error::Other(anyhow!(
+                "`{name}` can only take and give `Int`, `Float` and `Bool`, not `{ty}`"
+            )));
+        }
+
+        self.functions.retain(|function| function.name != name);
+        self.functions.push(Rc::new(HostFunction {
+            name: name.to_string(),
+            signature,
+            ty,
+            function: Box::new(function),
+        }));
+
+        Ok(())
+    }
+
+    // `name` is what errors say the program is called
+    pub fn load(&self, name: &str, src: &ArcStr) -> Result<Script, Error> {
+        let hosts: Vec<&str> = self
+            .functions
+            .iter()
+            .map(|function| function.name.as_str())
+            .collect();
+        // compiling goes as deep as the program, see `crate::parse`
+        let program = interp::with_stack(|| {
+            let (root, diagnostics) = crate::parse(src);
+
+            if !diagnostics.is_empty() {
+                return Err(Error::Diagnostics(diagnostics));
+            }
+
+            bytecode::compile_hosted(name, &root, &hosts)
+                .map_err(|e| Error::Diagnostics(vec![e.diagnostic()]))
+        })?;
+
+        Ok(Script {
+            program,
+            functions: self.functions.clone(),
+            limits: self.limits,
+        })
+    }
+}
+
+impl Script {
+    // works out the definition `name` and calls it with `args` one at a time, the way caelis would.
+    // every call starts afresh, so nothing one works out is kept for the next
+    pub fn call(&self, name: &str, args: &[Value]) -> Result<Value, Error> {
+        let runtime = |e: RuntimeError| Error::Diagnostics(vec![e.diagnostic()]);
+        let mut vm = Vm::with_limits(&self.program, self.limits);
+
+        for function in &self.functions {
+            vm.provide(
+                &function.name,
+                value::Value::Host(function.clone(), Vec::new()),
+            );
+        }
+
+        let mut value = vm
+            .get(name)
+            .ok_or_else(|| Error::Other(anyhow!("`{}` has no `{name}`", self.program.file)))?
+            .map_err(runtime)?;
+
+        for arg in args {
+            value = vm.call(value, arg.to_caelis()).map_err(runtime)?;
+        }
+
+        Value::from_caelis(&value).ok_or_else(|| {
+            Error::Other(anyhow!(
+                "`{name}` came out as a function, which can't leave caelis"
+            ))
+        })
+    }
+}
+
+impl HostFunction {
+    pub fn arity(&self) -> usize {
+        self.ty.params().0.len()
+    }
+
+    // `text` is the call, for errors to point at
+    pub fn run<F>(
+        &self,
+        args: &[value::Value<F>],
+        text: &Substr,
+    ) -> Result<value::Value<F>, RuntimeError> {
+        let (params, ret) = self.ty.params();
+        let mut values = Vec::new();
+
+        for (arg, param) in args.iter().zip(params) {
+            match Value::from_caelis(arg) {
+                Some(value) if Some(value.type_name()) == named(param) => values.push(value),
+                _ => {
+                    return Err(RuntimeError::new(
+                        Code::InvalidOperands,
+                        format!("`{}` takes `{param}`, not `{}`", self.name, arg.kind()),
+                        text,
+                        "this is given the wrong type",
+                    ))
+                }
+            }
+        }
+
+        let value = (self.function)(&values).map_err(|message| {
+            RuntimeError::new(
+                Code::HostFunctionFailed,
+                format!("`{}` failed: {message}", self.name),
+                text,
+                "this call failed",
+            )
+        })?;
+
+        if Some(value.type_name()) != named(ret) {
+            return Err(RuntimeError::new(
+                Code::HostFunctionFailed,
+                format!(
+                    "`{}` gave back `{}`, but its signature says `{ret}`",
+                    self.name,
+                    value.type_name()
+                ),
+                text,
+                "this came out as the wrong type",
+            ));
+        }
+
+        Ok(value.to_caelis())
+    }
+}
+
+impl Value {
+    pub fn type_name(&self) -> &str {
+        match self {
+            Value::Int(_) => "Int",
+            Value::Float(_) => "Float",
+            Value::Bool(_) => "Bool",
+            Value::Record(ty, _) => ty,
+        }
+    }
+
+    // anything but a function
+    pub(crate) fn from_caelis<F>(value: &value::Value<F>) -> Option<Value> {
+        Some(match value {
+            value::Value::Int(value) => Value::Int(*value),
+            value::Value::Float(value) => Value::Float(*value),
+            value::Value::Bool(value) => Value::Bool(*value),
+            value::Value::Record(record) => Value::Record(
+                record.ty.clone(),
+                record
+                    .fields
+                    .iter()
+                    .map(|(name, value)| Some((name.clone(), Value::from_caelis(value)?)))
+                    .collect::<Option<_>>()?,
+            ),
+            _ => return None,
+        })
+    }
+
+    pub(crate) fn to_caelis<F>(&self) -> value::Value<F> {
+        match self {
+            Value::Int(value) => value::Value::Int(*value),
+            Value::Float(value) => value::Value::Float(*value),
+            Value::Bool(value) => value::Value::Bool(*value),
+            Value::Record(ty, fields) => value::Value::Record(Rc::new(Record {
+                ty: ty.clone(),
+                fields: fields
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.to_caelis()))
+                    .collect(),
+            })),
+        }
+    }
+}
+
+// the same as caelis shows it
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_caelis::<()>())
+    }
+}
+
+// a host function can only name the built-in types. records belong to the scripts it's called from,
+// which haven't been loaded yet when it's registered
+fn resolve(type_ref: &TypeRef, diagnostics: &mut Vec<Diagnostic>) {
+    let error = |code, message, text: &Substr, label: &str| Diagnostic {
+        severity: Severity::Error,
+        code,
+        message,
+        range: text.range(),
+        label: label.to_string(),
+        secondary: Vec::new(),
+        suggestions: Vec::new(),
+    };
+
+    match type_ref {
+        TypeRef::Named(text, name, args) => {
+            if !BUILTIN_TYPES.contains(&name.text().as_str()) {
+                diagnostics.push(error(
+                    Code::UnknownType,
+                    format!("there's no type called `{}`", name.text()),
+                    name.text(),
+                    "host functions only know `Int`, `Float` and `Bool`",
+                ));
+            } else if !args.is_empty() {
+                diagnostics.push(error(
+                    Code::WrongTypeArguments,
+                    format!(
+                        "`{}` takes 0 type arguments, not {}",
+                        name.text(),
+                        args.len()
+                    ),
+                    text,
+                    "this has the wrong number of type arguments",
+                ));
+            }
+
+            for arg in args {
+                resolve(arg, diagnostics);
+            }
+        }
+        TypeRef::Function(_, arg, ret) => {
+            resolve(arg, diagnostics);
+            resolve(ret, diagnostics);
+        }
+    }
+}
+
+// the name of a type that isn't a function
+fn named(ty: &Type) -> Option<&str> {
+    match ty {
+        Type::Named(name, _) => Some(name),
+        _ => None,
+    }
+}
@@ -0,0 +1,414 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    mem::size_of,
+    rc::Rc,
+};
+
+use arcstr::Substr;
+
+pub use crate::value::RuntimeError;
+use crate::{
+    ast::{Ast, Def, Expr, Name, TypeDef},
+    diagnostic::Code,
+    limits::{Exceeded, Limits, Meter},
+    value::{self, Constructor, BUILTINS},
+};
+
+// evaluation recurses along with the program, which needs a lot more stack than the main thread has
+const STACK_SIZE: usize = 1 << 30;
+
+pub type Value = value::Value<Rc<Closure>>;
+
+pub struct Closure {
+    func: Rc<Func>,
+    env: Env,
+}
+
+// the tree with every name checked, so running it never comes across one that doesn't exist
+struct Term {
+    text: Substr,
+    kind: TermKind,
+}
+
+enum TermKind {
+    Var(Name),
+    Func(Rc<Func>),
+    Call(Box<Term>, Box<Term>),
+    Field(Box<Term>, Name),
+    IfThenElse(Box<Term>, Box<Term>, Box<Term>),
+    LetIn(Vec<(Name, Rc<Term>)>, Box<Term>),
+    Value(Value),
+}
+
+struct Func {
+    param: Name,
+    body: Term,
+}
+
+#[derive(Clone)]
+struct Env(Rc<Scope>);
+
+struct Scope {
+    bindings: RefCell<HashMap<String, Binding>>,
+    parent: Option<Env>,
+}
+
+#[derive(Clone)]
+enum Binding {
+    Value(Value),
+    // definitions are worked out the first time they're needed, which is what lets them refer to each
+    // other in any order
+    Thunk(Rc<Term>),
+    Evaluating,
+}
+
+// evaluates the tree as it is, and is what a program means: the backends have to agree with it. there's
+// no type checker yet, so annotations are ignored and anything wrong with the types only shows up once
+// it runs
+pub struct Interpreter {
+    globals: Env,
+    limits: Limits,
+}
+
+// what one evaluation has used up so far
+struct Run {
+    meter: Meter,
+    depth: usize,
+    // where the stack was when it started
+    stack: usize,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        let bindings = BUILTINS
+            .iter()
+            .map(|(name, builtin)| (name.to_string(), Value::Builtin(*builtin, Vec::new())))
+            .chain([
+                ("true".to_string(), Value::Bool(true)),
+                ("false".to_string(), Value::Bool(false)),
+            ])
+            .map(|(name, value)| (name, Binding::Value(value)))
+            .collect();
+
+        Self {
+            globals: Env::new(None, bindings),
+            limits: Limits::default(),
+        }
+    }
+}
+
+impl Interpreter {
+    pub fn with_limits(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    // adds top-level definitions without running any of them. later definitions replace earlier ones
+    // with the same name, even for code that was defined before them
+    pub fn define(&mut self, defs: &[Def]) -> Result<(), RuntimeError> {
+        let names: HashSet<String> = defs
+            .iter()
+            .filter_map(|def| match def {
+                Def::Value(value_def) => Some(value_def.name.text().to_string()),
+                Def::Type(type_def) => Some(type_def.name.text().to_string()),
+                _ => None,
+            })
+            .collect();
+        let mut bindings = Vec::new();
+
+        for def in defs {
+            match def {
+                Def::Value(value_def) => {
+                    let body = self.lower(&value_def.body, &mut Vec::new(), &names)?;
+                    bindings.push((
+                        value_def.name.text().to_string(),
+                        Binding::Thunk(Rc::new(body)),
+                    ));
+                }
+                Def::Type(type_def) => bindings.push((
+                    type_def.name.text().to_string(),
+                    Binding::Value(constructor(type_def)),
+                )),
+                Def::Generic(_) | Def::Fixity(_) | Def::Test(_) => (),
+            }
+        }
+
+        self.globals.0.bindings.borrow_mut().extend(bindings);
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: &Name) -> Result<Value, RuntimeError> {
+        // the name asked for isn't somewhere in the code that needed it, so it's not in the trace
+        self.globals.lookup(name, None, &mut self.run())
+    }
+
+    fn run(&self) -> Run {
+        Run {
+            meter: Meter::new(self.limits),
+            depth: 0,
+            stack: stack_position(),
+        }
+    }
+
+    // `locals` are the parameters and `let` bindings in scope, `pending` the top-level names that are
+    // being defined along with this
+    fn lower(
+        &self,
+        expr: &Expr,
+        locals: &mut Vec<String>,
+        pending: &HashSet<String>,
+    ) -> Result<Term, RuntimeError> {
+        let kind = match expr {
+            Expr::Call(..) if let Some((record, field)) = expr.field_access() => TermKind::Field(
+                Box::new(self.lower(record, locals, pending)?),
+                field.clone(),
+            ),
+            Expr::SymbolRef(_, name) => {
+                if !self.defined(name.text(), locals, pending) {
+                    return Err(RuntimeError::unbound(name));
+                }
+
+                TermKind::Var(name.clone())
+            }
+            Expr::Func(_, param, _, _, body) => {
+                locals.push(param.text().to_string());
+                let body = self.lower(body, locals, pending);
+                locals.pop();
+
+                TermKind::Func(Rc::new(Func {
+                    param: param.clone(),
+                    body: body?,
+                }))
+            }
+            Expr::Call(_, func, arg) => TermKind::Call(
+                Box::new(self.lower(func, locals, pending)?),
+                Box::new(self.lower(arg, locals, pending)?),
+            ),
+            Expr::IfThenElse(_, condition, then_expr, else_expr) => TermKind::IfThenElse(
+                Box::new(self.lower(condition, locals, pending)?),
+                Box::new(self.lower(then_expr, locals, pending)?),
+                Box::new(self.lower(else_expr, locals, pending)?),
+            ),
+            Expr::LetIn(_, defs, body) => {
+                let scope = locals.len();
+                locals.extend(defs.iter().map(|def| def.name.text().to_string()));
+
+                let lowered = defs
+                    .iter()
+                    .map(|def| {
+                        let body = self.lower(&def.body, locals, pending)?;
+                        Ok((def.name.clone(), Rc::new(body)))
+                    })
+                    .collect::<Result<Vec<_>, RuntimeError>>()
+                    .and_then(|defs| {
+                        Ok(TermKind::LetIn(
+                            defs,
+                            Box::new(self.lower(body, locals, pending)?),
+                        ))
+                    });
+                locals.truncate(scope);
+
+                lowered?
+            }
+            Expr::Float(_, value) => TermKind::Value(Value::Float(*value)),
+            Expr::Int(_, value) => TermKind::Value(Value::Int(*value)),
+            Expr::Error(text) => return Err(RuntimeError::unparsed(text)),
+        };
+
+        Ok(Term {
+            text: expr.text().clone(),
+            kind,
+        })
+    }
+
+    fn defined(&self, name: &str, locals: &[String], pending: &HashSet<String>) -> bool {
+        locals.iter().any(|local| local == name)
+            || pending.contains(name)
+            || self.globals.0.bindings.borrow().contains_key(name)
+    }
+}
+
+// runs `f` on a thread with room for deep recursion
+pub fn with_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .expect("Failed to spawn the interpreter thread")
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    })
+}
+
+fn eval(term: &Term, env: &Env, run: &mut Run) -> Result<Value, RuntimeError> {
+    run.meter.step().map_err(|e| e.at(&term.text))?;
+
+    match &term.kind {
+        TermKind::Var(name) => env.lookup(name, Some(name.text()), run),
+        TermKind::Func(func) => {
+            run.meter
+                .allocate(size_of::<Closure>())
+                .map_err(|e| e.at(&term.text))?;
+
+            Ok(Value::Function(Rc::new(Closure {
+                func: func.clone(),
+                env: env.clone(),
+            })))
+        }
+        TermKind::Call(func, arg) => {
+            let callee = eval(func, env, run)?;
+            let arg = eval(arg, env, run)?;
+
+            match callee {
+                Value::Function(closure) => {
+                    let bindings = HashMap::from([(
+                        closure.func.param.text().to_string(),
+                        Binding::Value(arg),
+                    )]);
+
+                    run.call(|run| {
+                        eval(
+                            &closure.func.body,
+                            &Env::new(Some(closure.env.clone()), bindings),
+                            run,
+                        )
+                    })
+                    .map_err(|e| e.at(&term.text))?
+                    .map_err(|e| e.called_from(&term.text))
+                }
+                callee => {
+                    let value = callee.apply(arg, &term.text, &func.text)?;
+                    run.meter
+                        .allocate(value.footprint())
+                        .map_err(|e| e.at(&term.text))?;
+
+                    Ok(value)
+                }
+            }
+        }
+        TermKind::Field(record, field) => eval(record, env, run)?.field(field.text(), &record.text),
+        TermKind::IfThenElse(condition, then_term, else_term) => {
+            match eval(condition, env, run)?.condition(&condition.text)? {
+                true => eval(then_term, env, run),
+                false => eval(else_term, env, run),
+            }
+        }
+        TermKind::LetIn(defs, body) => {
+            run.meter
+                .allocate(size_of::<Scope>() + defs.len() * size_of::<(String, Binding)>())
+                .map_err(|e| e.at(&term.text))?;
+            let bindings = defs
+                .iter()
+                .map(|(name, def)| (name.text().to_string(), Binding::Thunk(def.clone())))
+                .collect();
+
+            eval(body, &Env::new(Some(env.clone()), bindings), run)
+        }
+        TermKind::Value(value) => Ok(value.clone()),
+    }
+}
+
+impl Run {
+    // runs `f` as one call deeper, as long as the limits and the stack have room for it. the last
+    // eighth of `with_stack`'s stack is kept for whatever runs between one call and the next
+    fn call<T>(&mut self, f: impl FnOnce(&mut Run) -> T) -> Result<T, Exceeded> {
+        self.meter.call(self.depth + 1)?;
+        self.meter.allocate(size_of::<Scope>())?;
+
+        if self.stack.abs_diff(stack_position()) > STACK_SIZE - STACK_SIZE / 8 {
+            return Err(Exceeded::depth(self.depth));
+        }
+
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+
+        Ok(result)
+    }
+}
+
+// roughly where the top of the stack is
+fn stack_position() -> usize {
+    let marker = 0u8;
+
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+impl Env {
+    fn new(parent: Option<Env>, bindings: HashMap<String, Binding>) -> Self {
+        Env(Rc::new(Scope {
+            bindings: RefCell::new(bindings),
+            parent,
+        }))
+    }
+
+    // `site` is where the value is needed, for the trace if working it out goes wrong
+    fn lookup(
+        &self,
+        name: &Name,
+        site: Option<&Substr>,
+        run: &mut Run,
+    ) -> Result<Value, RuntimeError> {
+        let mut env = self.clone();
+
+        loop {
+            let binding = env.0.bindings.borrow().get(name.text().as_str()).cloned();
+
+            match binding {
+                Some(Binding::Value(value)) => return Ok(value),
+                Some(Binding::Thunk(term)) => {
+                    env.set(name, Binding::Evaluating);
+                    let value = run
+                        .call(|run| eval(&term, &env, run))
+                        .unwrap_or_else(|e| Err(e.at(name.text())))
+                        .map_err(|e| match site {
+                            Some(site) => e.called_from(site),
+                            None => e,
+                        });
+                    env.set(
+                        name,
+                        match &value {
+                            Ok(value) => Binding::Value(value.clone()),
+                            Err(_) => Binding::Thunk(term),
+                        },
+                    );
+
+                    return value;
+                }
+                Some(Binding::Evaluating) => {
+                    return Err(RuntimeError::new(
+                        Code::RecursiveValue,
+                        format!("`{}` is defined in terms of itself", name.text()),
+                        name.text(),
+                        "this is needed to work out itself",
+                    ))
+                }
+                None => match env.0.parent.clone() {
+                    Some(parent) => env = parent,
+                    None => return Err(RuntimeError::unbound(name)),
+                },
+            }
+        }
+    }
+
+    fn set(&self, name: &Name, binding: Binding) {
+        self.0
+            .bindings
+            .borrow_mut()
+            .insert(name.text().to_string(), binding);
+    }
+}
+
+fn constructor(def: &TypeDef) -> Value {
+    Constructor::value(
+        def.name.text().to_string(),
+        def.fields
+            .iter()
+            .map(|(name, _)| name.text().to_string())
+            .collect(),
+    )
+}
@@ -7,10 +7,29 @@ use chumsky::{
     text::*,
 };
 
+use crate::diagnostic::{Code, SyntaxError};
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub span: Substr,
     pub kind: TokenKind,
+    // the whitespace and comments around the token. a token gets the rest of its own line as trailing
+    // trivia and everything before it that isn't anyone else's as leading trivia, so putting every
+    // token's leading trivia, text and trailing trivia back together gives the source again
+    pub leading: Substr,
+    pub trailing: Substr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Comment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: Substr,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -20,9 +39,14 @@ pub enum TokenKind {
     If,
     Then,
     Else,
+    Infixl,
+    Infixr,
+    Test,
     Arrow,
     PipeInto,
     PipeFrom,
+    ComposeInto,
+    ComposeFrom,
     DollarSign,
     Ampersand,
     Pipe,
@@ -34,21 +58,32 @@ pub enum TokenKind {
     OpenParen,
     CloseParen,
     Name,
+    Operator,
     Float,
     Int,
+    String,
+    Error,
+    // only ever made by `end_of_input`, the parser never sees it
+    Eof,
 }
 
 impl Display for TokenKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            TokenKind::Let => write!(f, "let"),
-            TokenKind::In => write!(f, "in"),
-            TokenKind::If => write!(f, "if"),
-            TokenKind::Then => write!(f, "then"),
-            TokenKind::Else => write!(f, "else"),
+            // spelled out in `KEYWORDS`
+            TokenKind::Let
+            | TokenKind::In
+            | TokenKind::If
+            | TokenKind::Then
+            | TokenKind::Else
+            | TokenKind::Infixl
+            | TokenKind::Infixr
+            | TokenKind::Test => write!(f, "{}", self.keyword().unwrap_or("keyword")),
             TokenKind::Arrow => write!(f, "->"),
             TokenKind::PipeInto => write!(f, "|>"),
             TokenKind::PipeFrom => write!(f, "<|"),
+            TokenKind::ComposeInto => write!(f, ">>"),
+            TokenKind::ComposeFrom => write!(f, "<<"),
             TokenKind::DollarSign => write!(f, "$"),
             TokenKind::Ampersand => write!(f, "&"),
             TokenKind::Pipe => write!(f, "|"),
@@ -60,14 +95,166 @@ impl Display for TokenKind {
             TokenKind::OpenParen => write!(f, "("),
             TokenKind::CloseParen => write!(f, ")"),
             TokenKind::Name => write!(f, "name"),
+            TokenKind::Operator => write!(f, "operator"),
             TokenKind::Float => write!(f, "float"),
             TokenKind::Int => write!(f, "int"),
+            TokenKind::String => write!(f, "string"),
+            TokenKind::Error => write!(f, "invalid token"),
+            TokenKind::Eof => write!(f, "end of input"),
         }
     }
 }
 
-pub fn tokenize<'src>(text: &'src ArcStr) -> (Option<Vec<Token>>, Vec<Rich<'src, char>>) {
-    create(text).parse(text.as_str()).into_output_errors()
+// the one place keywords are spelled out, everything else goes through `TokenKind::keyword`
+pub const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("let", TokenKind::Let),
+    ("in", TokenKind::In),
+    ("if", TokenKind::If),
+    ("then", TokenKind::Then),
+    ("else", TokenKind::Else),
+    ("infixl", TokenKind::Infixl),
+    ("infixr", TokenKind::Infixr),
+    ("test", TokenKind::Test),
+];
+
+impl TokenKind {
+    pub fn keyword(&self) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, kind)| kind == self)
+            .map(|(keyword, _)| *keyword)
+    }
+}
+
+const OPERATOR_CHARS: &str = "!$%&*+-./<=>?@^|~";
+
+// never gives up on the input: anything that can't be lexed becomes an error token so the parser still
+// gets to look at the rest
+pub fn tokenize<'src>(text: &'src ArcStr) -> (Vec<Token>, Vec<SyntaxError<'src, char>>) {
+    let (raw_tokens, mut errs) = create(text).parse(text.as_str()).into_output_errors();
+    let mut tokens: Vec<Token> = Vec::new();
+
+    for token in raw_tokens.unwrap_or_default() {
+        match tokens.last_mut() {
+            // a run of junk is reported once, not per character
+            Some(last)
+                if last.kind == TokenKind::Error
+                    && token.kind == TokenKind::Error
+                    && last.span.range().end == token.span.range().start =>
+            {
+                last.span = text.substr(last.span.range().start..token.span.range().end)
+            }
+            _ => tokens.push(token),
+        }
+    }
+
+    errs.extend(
+        tokens
+            .iter()
+            .filter(|t| t.kind == TokenKind::Error)
+            .map(|t| {
+                SyntaxError::custom(
+                    Code::UnexpectedCharacter,
+                    t.span.range().into(),
+                    format!("unexpected `{}`", t.span),
+                )
+            }),
+    );
+    errs.extend(unbalanced_parens(&mut tokens));
+    errs.sort_by_key(|e| e.span().start);
+    attach_trivia(text, &mut tokens);
+
+    (tokens, errs)
+}
+
+// whatever comes after the last token. it never goes to the parser, it's only there so nothing in the
+// source is lost
+pub fn end_of_input(text: &ArcStr, tokens: &[Token]) -> Token {
+    let start = tokens.last().map(|t| t.trailing.range().end).unwrap_or(0);
+
+    Token {
+        span: text.substr(text.len()..),
+        kind: TokenKind::Eof,
+        leading: text.substr(start..),
+        trailing: text.substr(text.len()..),
+    }
+}
+
+// splits a token's leading or trailing trivia into comments and the whitespace between them
+pub fn trivia(text: &Substr) -> Vec<Trivia> {
+    let mut trivia = Vec::new();
+    let mut rest = 0;
+
+    while rest < text.len() {
+        let (kind, len) = match text[rest..].strip_prefix('#') {
+            Some(comment) => (
+                TriviaKind::Comment,
+                1 + comment.find('\n').unwrap_or(comment.len()),
+            ),
+            None => (
+                TriviaKind::Whitespace,
+                text[rest..].find('#').unwrap_or(text.len() - rest),
+            ),
+        };
+
+        trivia.push(Trivia {
+            kind,
+            text: text.substr(rest..rest + len),
+        });
+        rest += len;
+    }
+
+    trivia
+}
+
+fn attach_trivia(text: &ArcStr, tokens: &mut [Token]) {
+    let mut prev_end = 0;
+
+    for i in 0..tokens.len() {
+        let range = tokens[i].span.range();
+        let next = tokens
+            .get(i + 1)
+            .map(|t| t.span.range().start)
+            .unwrap_or(text.len());
+        let line_end = text[range.end..next]
+            .find('\n')
+            .map(|n| range.end + n)
+            .unwrap_or(next);
+
+        tokens[i].leading = text.substr(prev_end..range.start);
+        tokens[i].trailing = text.substr(range.end..line_end);
+        prev_end = line_end;
+    }
+}
+
+// checked here rather than left to the parser so an unclosed paren points at where it was opened
+// instead of at the end of the file. the parens become error tokens, so the parser takes them as
+// already reported and parses around them
+fn unbalanced_parens<'src>(tokens: &mut [Token]) -> Vec<SyntaxError<'src, char>> {
+    let mut open = Vec::new();
+    let mut unbalanced = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenParen => open.push(i),
+            TokenKind::CloseParen if open.pop().is_none() => unbalanced.push((i, "unmatched `)`")),
+            _ => (),
+        }
+    }
+    unbalanced.extend(open.into_iter().map(|i| (i, "unclosed `(`")));
+
+    unbalanced
+        .into_iter()
+        .map(|(i, message)| {
+            let code = match tokens[i].kind {
+                TokenKind::OpenParen => Code::UnclosedDelimiter,
+                _ => Code::UnmatchedDelimiter,
+            };
+            tokens[i].kind = TokenKind::Error;
+
+            SyntaxError::custom(code, tokens[i].span.range().into(), message)
+        })
+        .collect()
 }
 
 // this only exists for coercion and should only ever be used by `tokenize`
@@ -77,31 +264,50 @@ fn create<
         + StrInput<'src, Slice = &'src str, Span = SimpleSpan>,
 >(
     text: &'src ArcStr,
-) -> impl Parser<'src, I, Vec<Token>, extra::Err<Rich<'src, char>>> {
-    choice((
-        just("let").to(TokenKind::Let),
-        just("in").to(TokenKind::In),
-        just("if").to(TokenKind::If),
-        just("then").to(TokenKind::Then),
-        just("else").to(TokenKind::Else),
-        just("->").to(TokenKind::Arrow),
-        just("|>").to(TokenKind::PipeInto),
-        just("<|").to(TokenKind::PipeFrom),
-        just('$').to(TokenKind::DollarSign),
-        just('&').to(TokenKind::Ampersand),
-        just('|').to(TokenKind::Pipe),
-        just('=').to(TokenKind::Equal),
+) -> impl Parser<'src, I, Vec<Token>, extra::Err<SyntaxError<'src, char>>> {
+    // whitespace and comments. `attach_trivia` hands them out to the tokens afterwards, here they only
+    // have to be skipped, including in a file that has nothing else
+    let trivia = just('#')
+        .then(any().and_is(just('\n').not()).repeated())
+        .padded()
+        .repeated()
+        .padded();
+
+    let token = choice((
+        // keywords only count as whole identifiers, so `index` is still a name
+        ident().map(|s: &str| {
+            KEYWORDS
+                .iter()
+                .find(|(keyword, _)| *keyword == s)
+                .map(|(_, kind)| kind.clone())
+                .unwrap_or(TokenKind::Name)
+        }),
+        // symbol runs are lexed greedily, so `|>` and `|>>` are different tokens
+        one_of(OPERATOR_CHARS)
+            .repeated()
+            .at_least(1)
+            .to_slice()
+            .map(|s: &str| match s {
+                "->" => TokenKind::Arrow,
+                "|>" => TokenKind::PipeInto,
+                "<|" => TokenKind::PipeFrom,
+                ">>" => TokenKind::ComposeInto,
+                "<<" => TokenKind::ComposeFrom,
+                "$" => TokenKind::DollarSign,
+                "&" => TokenKind::Ampersand,
+                "|" => TokenKind::Pipe,
+                "=" => TokenKind::Equal,
+                "." => TokenKind::Period,
+                _ => TokenKind::Operator,
+            }),
         just(':').to(TokenKind::Colon),
         just(';').to(TokenKind::Semicolon),
-        just('.').to(TokenKind::Period),
         just(',').to(TokenKind::Comma),
         just('(').to(TokenKind::OpenParen),
         just(')').to(TokenKind::CloseParen),
-        ident().to(TokenKind::Name),
-        int(10)
-            .then(just('.').then(text::digits(10)).or_not())
-            .to(TokenKind::Float),
-        int(10).to(TokenKind::Int),
+        number(),
+        string(),
+        any().to(TokenKind::Error),
     ))
     .map_with(|kind, info| {
         let SimpleSpan {
@@ -113,15 +319,104 @@ fn create<
         Token {
             span: text.substr(start..end),
             kind,
+            leading: text.substr(start..start),
+            trailing: text.substr(end..end),
+        }
+    });
+
+    trivia.ignore_then(token.then_ignore(trivia).repeated().collect())
+}
+
+fn number<
+    'src,
+    I: ValueInput<'src, Token = char, Span = SimpleSpan>
+        + StrInput<'src, Slice = &'src str, Span = SimpleSpan>,
+>() -> impl Parser<'src, I, TokenKind, extra::Err<SyntaxError<'src, char>>> + Clone {
+    let digits = |radix: u32| {
+        any()
+            .filter(move |c: &char| c.is_digit(radix))
+            .then(any().filter(move |c: &char| c.is_digit(radix) || *c == '_').repeated())
+            .ignored()
+    };
+    let exponent = one_of("eE").then(one_of("+-").or_not()).then(digits(10));
+
+    choice((
+        digits(10)
+            .then(just('.'))
+            .then(digits(10))
+            .then(exponent.or_not())
+            .to(TokenKind::Float),
+        digits(10).then(exponent).to(TokenKind::Float),
+        just("0x").then(digits(16)).to(TokenKind::Int),
+        just("0o").then(digits(8)).to(TokenKind::Int),
+        just("0b").then(digits(2)).to(TokenKind::Int),
+        digits(10).to(TokenKind::Int),
+    ))
+    .then(ident().or_not())
+    .validate(|(kind, suffix), info, emitter| {
+        match (&kind, suffix) {
+            (_, None | Some("f64")) | (TokenKind::Int, Some("i64")) => (),
+            (_, Some(suffix)) => emitter.emit(SyntaxError::custom(
+                Code::InvalidLiteralSuffix,
+                info.span(),
+                format!("invalid suffix `{suffix}` for {kind} literal"),
+            )),
+        }
+
+        // `1f64` is a float no matter how it's written
+        if suffix == Some("f64") {
+            TokenKind::Float
+        } else {
+            kind
         }
     })
-    .padded_by(
-        just('#')
-            .then(any().and_is(just('\n').not()).repeated())
-            .padded()
-            .repeated(),
-    )
-    .padded()
-    .repeated()
-    .collect()
+}
+
+// strings don't go past the end of the line. `\"` and `\\` are the only escapes, a backslash before
+// anything else is just a backslash
+fn string<
+    'src,
+    I: ValueInput<'src, Token = char, Span = SimpleSpan>
+        + StrInput<'src, Slice = &'src str, Span = SimpleSpan>,
+>() -> impl Parser<'src, I, TokenKind, extra::Err<SyntaxError<'src, char>>> + Clone {
+    just('"')
+        .then(
+            just('\\')
+                .then(one_of("\"\\"))
+                .ignored()
+                .or(none_of("\"\n").ignored())
+                .repeated(),
+        )
+        .then(just('"').or_not())
+        .validate(|(_, end), info, emitter| {
+            if end.is_none() {
+                emitter.emit(SyntaxError::custom(
+                    Code::UnclosedString,
+                    info.span(),
+                    "this string is never closed",
+                ))
+            }
+
+            TokenKind::String
+        })
+}
+
+// what a string literal stands for, without its quotes and escapes
+pub fn unescape(literal: &str) -> String {
+    let inner = literal.strip_prefix('"').unwrap_or(literal);
+    let inner = inner.strip_suffix('"').unwrap_or(inner);
+    let mut out = String::new();
+    let mut chars = inner.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('\\', Some(&next @ ('"' | '\\'))) => {
+                out.push(next);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
 }
@@ -0,0 +1,206 @@
+// caelis as a library. the functions here are the front door: they go from source text to tokens, a
+// syntax tree, what its names refer to and finally something that runs, handing back diagnostics for
+// the caller to report however it likes. embedding caelis goes through `host`, and `driver` is the
+// `caelis` command itself. everything else is internal
+use std::fmt::Display;
+
+use anyhow::anyhow;
+use arcstr::ArcStr;
+use chumsky::Parser;
+
+use bytecode::Vm;
+use cst::{SyntaxKind, SyntaxNode};
+
+pub use analysis::Analysis;
+pub use ast::Root;
+pub use bytecode::{Program, Value};
+pub use diagnostic::{
+    Code, Diagnostic, FileId, Format, Severity, SourceFile, SourceMap, Suggestion,
+};
+pub use lexer::{Token, TokenKind};
+pub use limits::Limits;
+
+pub(crate) mod analysis;
+pub mod ast;
+pub(crate) mod bytecode;
+pub(crate) mod c;
+#[cfg(feature = "llvm")]
+mod compiler;
+pub mod cst;
+pub(crate) mod diagnostic;
+pub mod driver;
+pub(crate) mod fmt;
+pub mod host;
+pub(crate) mod interp;
+pub(crate) mod lexer;
+pub(crate) mod limits;
+mod lower;
+pub(crate) mod lsp;
+mod parser;
+pub(crate) mod repl;
+pub(crate) mod test;
+pub(crate) mod value;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Emit {
+    /// A `.caeb` file for `caelis run`
+    #[default]
+    Bytecode,
+    /// C99 source, for machines without LLVM
+    C,
+    /// LLVM IR with debug info, for `llc` or `clang`. only part of the language compiles to it so far
+    Llvm,
+}
+
+// how `compile` should go about it
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    pub emit: Emit,
+}
+
+pub enum Artifact {
+    Bytecode(Program),
+    C(String),
+    Llvm(String),
+}
+
+#[derive(Debug)]
+pub enum Error {
+    // what's wrong with the program, with ranges into the text it was given
+    Diagnostics(Vec<Diagnostic>),
+    // anything else, like a program with no `main` being compiled to C
+    Other(anyhow::Error),
+}
+
+impl Artifact {
+    // what goes in the file, and the extension the file should have
+    pub fn to_bytes(&self) -> (Vec<u8>, &'static str) {
+        match self {
+            Artifact::Bytecode(program) => (bytecode::encode(program), "caeb"),
+            Artifact::C(c) => (c.clone().into_bytes(), "c"),
+            Artifact::Llvm(ir) => (ir.clone().into_bytes(), "ll"),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Diagnostics(diagnostics) => match diagnostics.as_slice() {
+                [diagnostic] => write!(f, "{}", diagnostic.message),
+                _ => write!(f, "{} errors", diagnostics.len()),
+            },
+            Error::Other(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+pub fn tokenize(src: &ArcStr) -> (Vec<Token>, Vec<Diagnostic>) {
+    let (tokens, errs) = lexer::tokenize(src);
+
+    (
+        tokens,
+        errs.into_iter().map(Diagnostic::from_lex_error).collect(),
+    )
+}
+
+// the parser recovers from errors, so there's always a tree. whatever it had to skip is still in the
+// syntax tree, it's only left out of the ast.
+// only parentheses, functions, `if`s and `let`s are limited in how deep they go, so a long chain of
+// operators makes a tree as deep as it's long. everything that walks one, lowering included, runs on
+// `interp::with_stack` to have room for that
+pub fn parse(src: &ArcStr) -> (Root, Vec<Diagnostic>) {
+    interp::with_stack(|| {
+        let (tree, diagnostics) = cst(src);
+
+        (lower::root(&tree), diagnostics)
+    })
+}
+
+// the ast is a typed view of the syntax tree, without the trivia, punctuation and anything broken
+pub fn lower(tree: &SyntaxNode) -> Root {
+    interp::with_stack(|| lower::root(tree))
+}
+
+// the lossless tree, comments and all, which prints back as exactly `src`
+pub fn syntax_tree(src: &ArcStr) -> (SyntaxNode, Vec<Diagnostic>) {
+    interp::with_stack(|| cst(src))
+}
+
+fn cst(src: &ArcStr) -> (SyntaxNode, Vec<Diagnostic>) {
+    let (tokens, errs) = lexer::tokenize(src);
+
+    let (node, parse_errs) = parser::create(parser::fixities(&tokens))
+        .parse(tokens.as_slice())
+        .into_output_errors();
+    // only if the parser couldn't recover at all, which it always should
+    let node = node.unwrap_or_else(|| {
+        let error = cst::Node::new(SyntaxKind::Error, 0..tokens.len(), Vec::new());
+        cst::Node::new(SyntaxKind::Root, 0..tokens.len(), vec![error])
+    });
+
+    let diagnostics = Diagnostic::from_syntax_errors(errs, parse_errs, &tokens, src.len());
+
+    (SyntaxNode::root(src, &tokens, node), diagnostics)
+}
+
+// what every name refers to and the types that can be inferred, along with the names that don't refer
+// to anything and types given the wrong number of arguments. there's no type checker: types are only
+// inferred where annotations and literals make them plain, and a program using a value as the wrong
+// type is only caught when it runs
+pub fn resolve(root: &Root) -> (Analysis, Vec<Diagnostic>) {
+    let analysis = interp::with_stack(|| Analysis::new(root));
+    let diagnostics = analysis.diagnostics.clone();
+
+    (analysis, diagnostics)
+}
+
+// all the way from source to something that runs. `name` is what errors at runtime say the program
+// is called
+pub fn compile(name: &str, src: &ArcStr, config: &Config) -> Result<Artifact, Error> {
+    let program = compile_program(name, src)?;
+
+    match config.emit {
+        Emit::Bytecode => Ok(Artifact::Bytecode(program)),
+        Emit::C => c::emit(&program).map(Artifact::C).map_err(Error::Other),
+        Emit::Llvm => llvm(name, src).map(Artifact::Llvm).map_err(Error::Other),
+    }
+}
+
+#[cfg(feature = "llvm")]
+fn llvm(name: &str, src: &ArcStr) -> anyhow::Result<String> {
+    use anyhow::Context;
+
+    interp::with_stack(|| compiler::compile(name, src, &lower::root(&cst(src).0)))
+        .with_context(|| format!("can't compile `{name}` to LLVM IR"))
+}
+
+#[cfg(not(feature = "llvm"))]
+fn llvm(_name: &str, _src: &ArcStr) -> anyhow::Result<String> {
+    Err(anyhow!("this caelis was built without LLVM"))
+}
+
+pub(crate) fn compile_program(name: &str, src: &ArcStr) -> Result<Program, Error> {
+    interp::with_stack(|| {
+        let (tree, diagnostics) = cst(src);
+
+        if !diagnostics.is_empty() {
+            return Err(Error::Diagnostics(diagnostics));
+        }
+
+        bytecode::compile(name, &lower::root(&tree))
+            .map_err(|e| Error::Diagnostics(vec![e.diagnostic()]))
+    })
+}
+
+// works out the program's `main`. errors point into the source it was compiled from
+pub fn run(program: &Program, limits: Limits) -> Result<Value, Error> {
+    let Some(value) = Vm::with_limits(program, limits).get("main") else {
+        let e = anyhow!("`{}` has no `main` to run", program.file);
+        return Err(Error::Other(e));
+    };
+
+    value.map_err(|e| Error::Diagnostics(vec![e.diagnostic()]))
+}
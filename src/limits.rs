@@ -0,0 +1,113 @@
+use std::time::{Duration, Instant};
+
+use arcstr::Substr;
+
+use crate::{diagnostic::Code, value::RuntimeError};
+
+// the clock is only looked at every so many steps, which is plenty often to stop on time
+const CLOCK_INTERVAL: u64 = 1 << 10;
+
+// how much a program is allowed to do before it's stopped, for running code that can't be trusted to
+// finish. `None` is no limit, which is the default
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Limits {
+    // steps of evaluation, which are instructions in the VM
+    pub fuel: Option<u64>,
+    // calls and definitions being worked out at once
+    pub depth: Option<usize>,
+    // bytes allocated for frames, closures, records and the like, all told. nothing's given back when
+    // it's freed, so this is a budget for the whole run rather than a limit on what's live at once
+    pub allocation: Option<usize>,
+    pub timeout: Option<Duration>,
+}
+
+// keeps track of a run against its limits. a VM has one for as long as it lives, while the interpreter
+// starts a new one for everything it's asked to work out
+pub struct Meter {
+    limits: Limits,
+    steps: u64,
+    allocated: usize,
+    deadline: Option<Instant>,
+}
+
+// a limit that's been reached, which becomes an error once it's known where
+pub struct Exceeded {
+    code: Code,
+    message: String,
+    label: &'static str,
+}
+
+impl Meter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            steps: 0,
+            allocated: 0,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), Exceeded> {
+        self.steps += 1;
+
+        if self.limits.fuel.is_some_and(|fuel| self.steps > fuel) {
+            return Err(Exceeded {
+                code: Code::OutOfFuel,
+                message: format!("ran out of fuel after {} steps", self.steps - 1),
+                label: "this was running when it stopped",
+            });
+        }
+
+        match self.limits.timeout {
+            Some(timeout)
+                if self.steps.is_multiple_of(CLOCK_INTERVAL)
+                    && self
+                        .deadline
+                        .is_some_and(|deadline| Instant::now() > deadline) =>
+            {
+                Err(Exceeded {
+                    code: Code::TimedOut,
+                    message: format!("ran for longer than {timeout:?}"),
+                    label: "this was running when it stopped",
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // `depth` is how many calls there would be with this one
+    pub fn call(&self, depth: usize) -> Result<(), Exceeded> {
+        match self.limits.depth {
+            Some(limit) if depth > limit => Err(Exceeded::depth(limit)),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn allocate(&mut self, bytes: usize) -> Result<(), Exceeded> {
+        self.allocated += bytes;
+
+        match self.limits.allocation {
+            Some(limit) if self.allocated > limit => Err(Exceeded {
+                code: Code::OutOfMemory,
+                message: format!("allocated more than {limit} bytes"),
+                label: "this went over the allocation budget",
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Exceeded {
+    // for when there's no room for call number `limit + 1`, whatever the reason
+    pub fn depth(limit: usize) -> Self {
+        Exceeded {
+            code: Code::TooDeep,
+            message: format!("went more than {limit} calls deep"),
+            label: "this is one call too many",
+        }
+    }
+
+    pub fn at(self, text: &Substr) -> RuntimeError {
+        RuntimeError::new(self.code, self.message, text, self.label)
+    }
+}
@@ -0,0 +1,261 @@
+// reads the ast off the syntax tree. the parser has already checked the shape of every node, so a node
+// that's missing a part is one it had to skip, and it's left out or becomes an error
+use arcstr::Substr;
+
+use crate::{
+    ast::{
+        Associativity, Ast, Def, Expr, FixityDef, GenericDef, Name, Root, TestDef, TypeDef,
+        TypeRef, ValueDef,
+    },
+    cst::{SyntaxKind, SyntaxNode},
+    lexer::{self, TokenKind},
+    parser,
+};
+
+pub fn root(tree: &SyntaxNode) -> Root {
+    let defs: Vec<Def> = tree.nodes().filter_map(def).collect();
+    let text = match (defs.first(), defs.last()) {
+        (Some(first), Some(last)) => first
+            .text()
+            .parent()
+            .substr(first.text().range().start..last.text().range().end),
+        _ => Substr::new(),
+    };
+
+    Root { text, defs }
+}
+
+fn def(node: &SyntaxNode) -> Option<Def> {
+    match node.kind {
+        SyntaxKind::GenericDef => generic_def(node).map(Def::Generic),
+        SyntaxKind::ValueDef => value_def(node).map(Def::Value),
+        SyntaxKind::TypeDef => type_def(node).map(Def::Type),
+        SyntaxKind::FixityDef => fixity_def(node).map(Def::Fixity),
+        SyntaxKind::TestDef => test_def(node).map(Def::Test),
+        _ => None,
+    }
+}
+
+fn generic_def(node: &SyntaxNode) -> Option<GenericDef> {
+    let mut nodes = node.nodes();
+    let def_name = name(nodes.next()?)?;
+    let args = nodes
+        .map(|arg| {
+            let mut nodes = arg.nodes();
+            let arg_name = name(nodes.next()?)?;
+            let bounds = nodes.map(type_ref).collect::<Option<_>>()?;
+
+            Some((arg_name, bounds))
+        })
+        .collect::<Option<_>>()?;
+
+    Some(GenericDef {
+        text: node.text.clone(),
+        name: def_name,
+        args,
+    })
+}
+
+fn value_def(node: &SyntaxNode) -> Option<ValueDef> {
+    let mut nodes = node.nodes();
+
+    Some(ValueDef {
+        text: node.text.clone(),
+        name: name(nodes.next()?)?,
+        body: expr(nodes.next()?),
+    })
+}
+
+fn type_def(node: &SyntaxNode) -> Option<TypeDef> {
+    let mut nodes = node.nodes();
+    let def_name = name(nodes.next()?)?;
+    let fields = nodes
+        .map(|field| {
+            let mut nodes = field.nodes();
+
+            Some((name(nodes.next()?)?, type_ref(nodes.next()?)?))
+        })
+        .collect::<Option<_>>()?;
+
+    Some(TypeDef {
+        text: node.text.clone(),
+        name: def_name,
+        fields,
+    })
+}
+
+pub fn fixity_def(node: &SyntaxNode) -> Option<FixityDef> {
+    let associativity = match node.token(TokenKind::Infixl) {
+        Some(_) => Associativity::Left,
+        None => Associativity::Right,
+    };
+
+    Some(FixityDef {
+        text: node.text.clone(),
+        associativity,
+        precedence: node.token(TokenKind::Int)?.span.parse().ok()?,
+        operators: node
+            .own_tokens()
+            .filter(|t| t.kind == TokenKind::Operator)
+            .map(|t| Name(t.span.clone()))
+            .collect(),
+    })
+}
+
+fn test_def(node: &SyntaxNode) -> Option<TestDef> {
+    let literal = node.token(TokenKind::String)?.span.clone();
+
+    Some(TestDef {
+        text: node.text.clone(),
+        name: lexer::unescape(&literal),
+        literal,
+        body: expr(node.nodes().next()?),
+    })
+}
+
+// the name itself, without the parentheses around an operator
+fn name(node: &SyntaxNode) -> Option<Name> {
+    node.tokens()
+        .into_iter()
+        .find(|t| !matches!(t.kind, TokenKind::OpenParen | TokenKind::CloseParen))
+        .map(|t| Name(t.span.clone()))
+}
+
+pub fn type_ref(node: &SyntaxNode) -> Option<TypeRef> {
+    match node.kind {
+        SyntaxKind::TypeAnnotation | SyntaxKind::ParenType => type_ref(node.nodes().next()?),
+        SyntaxKind::NamedType => {
+            let name = Name(node.token(TokenKind::Name)?.span.clone());
+            let args: Vec<TypeRef> = node.nodes().map(type_ref).collect::<Option<_>>()?;
+
+            Some(TypeRef::Named(node.text.clone(), name, args))
+        }
+        SyntaxKind::FunctionType => {
+            let mut nodes = node.nodes();
+            let arg = type_ref(nodes.next()?)?;
+            let ret = type_ref(nodes.next()?)?;
+
+            Some(TypeRef::Function(
+                node.text.clone(),
+                Box::new(arg),
+                Box::new(ret),
+            ))
+        }
+        _ => None,
+    }
+}
+
+pub fn expr(node: &SyntaxNode) -> Expr {
+    try_expr(node).unwrap_or_else(|| Expr::Error(node.text.clone()))
+}
+
+fn try_expr(node: &SyntaxNode) -> Option<Expr> {
+    let mut nodes = node.nodes();
+    let mut next = || nodes.next().map(|node| Box::new(expr(node)));
+
+    Some(match node.kind {
+        SyntaxKind::SymbolRef => {
+            let name = name(node.nodes().next()?)?;
+            Expr::SymbolRef(name.text().clone(), name)
+        }
+        SyntaxKind::Literal => {
+            let token = node.own_tokens().next()?;
+            let float = token.kind == TokenKind::Float;
+
+            // out of range literals have already been reported
+            parser::number_literal(token.span.clone(), float).unwrap_or_else(|_| match float {
+                true => Expr::Float(token.span.clone(), 0.0),
+                false => Expr::Int(token.span.clone(), 0),
+            })
+        }
+        SyntaxKind::Paren => return try_expr(node.nodes().next()?),
+        SyntaxKind::Func => {
+            let name = name(node.nodes().next()?)?;
+            let mut types = node
+                .nodes()
+                .filter(|node| node.kind == SyntaxKind::TypeAnnotation)
+                .map(type_ref);
+            let arg_type = types.next()??;
+            let ret_type = types.next().flatten();
+            let body = Box::new(expr(node.nodes().last()?));
+
+            Expr::Func(node.text.clone(), name, Some(arg_type), ret_type, body)
+        }
+        SyntaxKind::Call => {
+            let (func, arg) = (next()?, next()?);
+            Expr::Call(node.text.clone(), func, arg)
+        }
+        SyntaxKind::Binary => {
+            let (lhs, rhs) = (next()?, next()?);
+            let op = node.own_tokens().next()?;
+
+            let text = node.text.clone();
+
+            match op.kind {
+                TokenKind::PipeInto => Expr::Call(text, rhs, lhs),
+                TokenKind::PipeFrom => Expr::Call(text, lhs, rhs),
+                TokenKind::ComposeInto => compose(text, op.span.clone(), lhs, rhs),
+                TokenKind::ComposeFrom => compose(text, op.span.clone(), rhs, lhs),
+                _ => binary_op(text, lhs, op.span.clone(), rhs),
+            }
+        }
+        SyntaxKind::FieldAccess => {
+            let record = next()?;
+            let period = node.token(TokenKind::Period)?.span.clone();
+            let field = Name(node.token(TokenKind::Name)?.span.clone());
+
+            field_access(node.text.clone(), record, period, field)
+        }
+        SyntaxKind::IfThenElse => {
+            let (condition, then_expr, else_expr) = (next()?, next()?, next()?);
+
+            Expr::IfThenElse(node.text.clone(), condition, then_expr, else_expr)
+        }
+        SyntaxKind::LetIn => {
+            let defs = node
+                .nodes()
+                .filter(|node| node.kind == SyntaxKind::ValueDef)
+                .map(value_def)
+                .collect::<Option<_>>()?;
+            let body = Box::new(expr(node.nodes().last()?));
+
+            Expr::LetIn(node.text.clone(), defs, body)
+        }
+        _ => return None,
+    })
+}
+
+// `a <> b` is just `(<>) a b`
+fn binary_op(text: Substr, lhs: Box<Expr>, op: Substr, rhs: Box<Expr>) -> Expr {
+    let op_ref = Box::new(Expr::SymbolRef(op.clone(), Name(op.clone())));
+    let partial = Box::new(Expr::Call(
+        text.parent().substr(text.range().start..op.range().end),
+        op_ref,
+        lhs,
+    ));
+
+    Expr::Call(text, partial, rhs)
+}
+
+// `f >> g` is `x -> g (f x)`. the parameter is named by the operator, which nobody can write as a
+// name, so it can't hide anything `f` or `g` refer to
+fn compose(text: Substr, op: Substr, first: Box<Expr>, then: Box<Expr>) -> Expr {
+    let param = Box::new(Expr::SymbolRef(op.clone(), Name(op.clone())));
+    let inner = Box::new(Expr::Call(text.clone(), first, param));
+    let body = Box::new(Expr::Call(text.clone(), then, inner));
+
+    Expr::Func(text, Name(op), None, None, body)
+}
+
+// `p.x` is just `(.) p x`, except that `x` is a field rather than something in scope
+fn field_access(text: Substr, record: Box<Expr>, period: Substr, field: Name) -> Expr {
+    let op_ref = Box::new(Expr::SymbolRef(period.clone(), Name(period.clone())));
+    let partial = Box::new(Expr::Call(
+        text.parent().substr(text.range().start..period.range().end),
+        op_ref,
+        record,
+    ));
+    let field_ref = Box::new(Expr::SymbolRef(field.text().clone(), field.clone()));
+
+    Expr::Call(text, partial, field_ref)
+}
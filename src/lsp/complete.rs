@@ -0,0 +1,286 @@
+use arcstr::ArcStr;
+use lsp_types::{
+    CompletionItem, CompletionItemKind, ParameterInformation, ParameterLabel, SignatureHelp,
+    SignatureInformation,
+};
+
+use crate::{
+    analysis::{Analysis, SymbolKind, Type, BUILTIN_TYPES},
+    ast::{Ast, Def, Expr, Name},
+    lexer::{self, TokenKind, KEYWORDS},
+};
+
+// stands in for whatever is being typed at the cursor
+const PLACEHOLDER: &str = "__cursor__";
+
+// whatever is being typed usually doesn't parse yet, which would leave it out of the tree entirely. so
+// completion works on a copy of the document with the definition being typed cut off at the cursor, a
+// placeholder there and just enough after it to close the definition off. the definitions after it
+// are kept, since anything at the top level can be used before it's defined
+struct Snapshot {
+    analysis: Analysis,
+    call: Option<(Name, usize)>,
+}
+
+enum Context {
+    Value,
+    Type,
+    Field(Option<String>),
+}
+
+pub fn completions(text: &str, full: &Analysis, offset: usize) -> Vec<CompletionItem> {
+    let start = prefix_start(text, offset);
+    let before = text[..start].trim_end();
+    let snapshot = snapshot(text, start);
+    let analysis = snapshot.as_ref().map(|s| &s.analysis).unwrap_or(full);
+
+    let item = |symbol: usize| {
+        let symbol = &analysis.symbols[symbol];
+
+        CompletionItem {
+            label: symbol.name.text().to_string(),
+            kind: Some(match symbol.kind {
+                SymbolKind::Value | SymbolKind::Parameter => CompletionItemKind::VARIABLE,
+                SymbolKind::Function => CompletionItemKind::FUNCTION,
+                SymbolKind::Type | SymbolKind::Generic => CompletionItemKind::STRUCT,
+                SymbolKind::Field => CompletionItemKind::FIELD,
+                SymbolKind::TypeParameter => CompletionItemKind::TYPE_PARAMETER,
+            }),
+            detail: symbol.detail(),
+            ..Default::default()
+        }
+    };
+
+    match context(before, analysis, start) {
+        Context::Value => analysis
+            .visible(start)
+            .into_iter()
+            .filter(|i| {
+                matches!(
+                    analysis.symbols[*i].kind,
+                    SymbolKind::Value | SymbolKind::Function | SymbolKind::Parameter
+                )
+            })
+            .map(item)
+            .chain(KEYWORDS.iter().map(|(keyword, _)| CompletionItem {
+                label: keyword.to_string(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                ..Default::default()
+            }))
+            .collect(),
+        Context::Type => analysis
+            .visible(start)
+            .into_iter()
+            .filter(|i| {
+                matches!(
+                    analysis.symbols[*i].kind,
+                    SymbolKind::Type | SymbolKind::TypeParameter
+                )
+            })
+            .map(item)
+            .chain(BUILTIN_TYPES.iter().map(|name| CompletionItem {
+                label: name.to_string(),
+                kind: Some(CompletionItemKind::STRUCT),
+                ..Default::default()
+            }))
+            .collect(),
+        // fields of the type in front of the `.` if it's known, otherwise every field there is
+        Context::Field(owner) => analysis
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.kind == SymbolKind::Type)
+            .filter(|symbol| {
+                owner
+                    .as_ref()
+                    .is_none_or(|owner| symbol.name.text() == owner)
+            })
+            .flat_map(|symbol| symbol.children.clone())
+            .map(item)
+            .collect(),
+    }
+}
+
+pub fn signature_help(text: &str, offset: usize) -> Option<SignatureHelp> {
+    let snapshot = snapshot(text, prefix_start(text, offset))?;
+    let (head, active) = snapshot.call?;
+    let (_, symbol) = snapshot.analysis.symbol_at(head.text().range().start)?;
+    let symbol = &snapshot.analysis.symbols[symbol];
+    let ty = symbol.ty.as_ref()?;
+    let (params, ret) = ty.params();
+
+    if params.is_empty() {
+        return None;
+    }
+
+    // parameter labels are offsets into the signature, in utf-16 like everything else
+    let mut label = format!("{} : ", symbol.name.text());
+    let mut parameters = Vec::new();
+
+    for param in &params {
+        let start = label.encode_utf16().count() as u32;
+        match param {
+            Type::Function(..) => label.push_str(&format!("({param})")),
+            _ => label.push_str(&param.to_string()),
+        }
+        parameters.push(ParameterInformation {
+            label: ParameterLabel::LabelOffsets([start, label.encode_utf16().count() as u32]),
+            documentation: None,
+        });
+        label.push_str(" -> ");
+    }
+
+    label.push_str(&ret.to_string());
+
+    Some(SignatureHelp {
+        signatures: vec![SignatureInformation {
+            label,
+            documentation: None,
+            parameters: Some(parameters),
+            active_parameter: None,
+        }],
+        active_signature: Some(0),
+        active_parameter: Some(active.min(params.len() - 1) as u32),
+    })
+}
+
+fn prefix_start(text: &str, offset: usize) -> usize {
+    text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_alphanumeric() || *c == '_')
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(offset)
+}
+
+fn context(before: &str, analysis: &Analysis, offset: usize) -> Context {
+    if let Some(owner) = before.strip_suffix('.') {
+        let owner = &owner[prefix_start(owner, owner.len())..];
+        let ty = analysis
+            .visible(offset)
+            .into_iter()
+            .find(|i| analysis.symbols[*i].name.text() == owner)
+            .and_then(|i| analysis.symbols[i].ty.clone());
+
+        return Context::Field(match ty {
+            Some(Type::Named(name, _)) => Some(name),
+            _ => None,
+        });
+    }
+
+    // types only ever come after a `:`, possibly inside parentheses that were opened right after one
+    let (tokens, _) = lexer::tokenize(&ArcStr::from(before));
+    let mut open = Vec::new();
+
+    for (i, token) in tokens.iter().enumerate() {
+        match token.kind {
+            TokenKind::OpenParen => open.push(i),
+            TokenKind::CloseParen => {
+                open.pop();
+            }
+            _ => (),
+        }
+    }
+
+    let after_colon = |i: usize| i > 0 && tokens[i - 1].kind == TokenKind::Colon;
+
+    if tokens.last().is_some_and(|t| t.kind == TokenKind::Colon)
+        || open.into_iter().any(after_colon)
+    {
+        Context::Type
+    } else {
+        Context::Value
+    }
+}
+
+fn snapshot(text: &str, start: usize) -> Option<Snapshot> {
+    let before = &text[..start];
+    let after = &text[next_definition(text, start)..];
+    let (tokens, _) = lexer::tokenize(&ArcStr::from(before));
+    let open = tokens.iter().fold(0usize, |open, token| match token.kind {
+        TokenKind::OpenParen => open + 1,
+        TokenKind::CloseParen => open.saturating_sub(1),
+        _ => open,
+    });
+    let parens = ")".repeat(open);
+
+    [";", " -> 0;", " in 0;", "; in 0;"]
+        .into_iter()
+        .find_map(|end| {
+            let cut = format!("{before}{PLACEHOLDER}{parens}{end}");
+            let patched = ArcStr::from(format!("{cut}\n{after}"));
+            let (root, _) = crate::parse(&patched);
+
+            // the definition under the cursor has to have made it into the tree
+            let current = root
+                .defs
+                .iter()
+                .find(|def| def.text().range().end == cut.len())?;
+
+            let call = match current {
+                Def::Value(def) => find_call(&def.body, start),
+                _ => None,
+            };
+
+            Some(Snapshot {
+                analysis: Analysis::new(&root),
+                call,
+            })
+        })
+}
+
+// definitions start at the beginning of a line, and anything that's indented or doesn't start with a
+// name, `test` or a fixity carries on the one before
+fn next_definition(text: &str, offset: usize) -> usize {
+    text[offset..]
+        .match_indices('\n')
+        .map(|(i, _)| offset + i + 1)
+        .find(|&line| {
+            let word: String = text[line..]
+                .chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect();
+            let keyword = KEYWORDS.iter().find(|(keyword, _)| *keyword == word);
+
+            word.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && keyword.is_none_or(|(_, kind)| {
+                    matches!(
+                        kind,
+                        TokenKind::Test | TokenKind::Infixl | TokenKind::Infixr
+                    )
+                })
+        })
+        .unwrap_or(text.len())
+}
+
+// the function the placeholder is being passed to, and which argument it is
+fn find_call(expr: &Expr, offset: usize) -> Option<(Name, usize)> {
+    match expr {
+        Expr::Call(_, func, arg) => match &**arg {
+            Expr::SymbolRef(_, name) if name.text().range().start == offset => {
+                let mut head = &**func;
+                let mut index = 0;
+
+                while let Expr::Call(_, func, _) = head {
+                    head = func;
+                    index += 1;
+                }
+
+                match head {
+                    Expr::SymbolRef(_, name) => Some((name.clone(), index)),
+                    _ => None,
+                }
+            }
+            _ => find_call(func, offset).or_else(|| find_call(arg, offset)),
+        },
+        Expr::Func(_, _, _, _, body) => find_call(body, offset),
+        Expr::IfThenElse(_, condition, then_expr, else_expr) => find_call(condition, offset)
+            .or_else(|| find_call(then_expr, offset))
+            .or_else(|| find_call(else_expr, offset)),
+        Expr::LetIn(_, defs, body) => defs
+            .iter()
+            .find_map(|def| find_call(&def.body, offset))
+            .or_else(|| find_call(body, offset)),
+        Expr::SymbolRef(..) | Expr::Float(..) | Expr::Int(..) | Expr::Error(_) => None,
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use arcstr::ArcStr;
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::{
+    notification::{
+        DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+        PublishDiagnostics,
+    },
+    request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, InlayHintRequest,
+        References, Request as _, SignatureHelpRequest,
+    },
+    CompletionOptions, CompletionParams, CompletionResponse, DiagnosticRelatedInformation,
+    DiagnosticSeverity, DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse,
+    GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverContents, HoverParams,
+    HoverProviderCapability, InlayHint, InlayHintKind, InlayHintLabel, InlayHintParams, Location,
+    MarkupContent, MarkupKind, NumberOrString, OneOf, Position, PublishDiagnosticsParams, Range,
+    ReferenceParams, ServerCapabilities, SignatureHelp, SignatureHelpOptions, SignatureHelpParams,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+
+use crate::{
+    analysis::{Analysis, SymbolKind},
+    ast::{Ast, Name},
+    cst::SyntaxNode,
+    diagnostic::Diagnostic,
+    lexer::{self, TriviaKind},
+};
+
+mod complete;
+
+struct Document {
+    text: ArcStr,
+    tree: SyntaxNode,
+    analysis: Analysis,
+    diagnostics: Vec<lsp_types::Diagnostic>,
+}
+
+#[derive(Default)]
+struct Server {
+    documents: HashMap<Uri, Document>,
+}
+
+pub fn run() -> Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    connection.initialize(serde_json::to_value(ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        references_provider: Some(OneOf::Left(true)),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions {
+            trigger_characters: Some(vec![":".to_string(), ".".to_string()]),
+            ..Default::default()
+        }),
+        signature_help_provider: Some(SignatureHelpOptions {
+            trigger_characters: Some(vec![" ".to_string(), "(".to_string()]),
+            ..Default::default()
+        }),
+        inlay_hint_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    })?)?;
+
+    Server::default().main_loop(&connection)?;
+    // the writer thread only stops once every sender is gone
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
+
+impl Server {
+    fn main_loop(&mut self, connection: &Connection) -> Result<()> {
+        for message in &connection.receiver {
+            match message {
+                Message::Request(request) => {
+                    if connection.handle_shutdown(&request)? {
+                        return Ok(());
+                    }
+
+                    connection
+                        .sender
+                        .send(Message::Response(self.handle_request(request)))?;
+                }
+                Message::Notification(notification) => {
+                    if let Some(uri) = self.handle_notification(notification) {
+                        connection
+                            .sender
+                            .send(Message::Notification(self.diagnostics(uri)))?;
+                    }
+                }
+                Message::Response(_) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_request(&self, request: Request) -> Response {
+        match request.method.clone().as_str() {
+            HoverRequest::METHOD => self.respond(request, Self::hover),
+            GotoDefinition::METHOD => self.respond(request, Self::definition),
+            References::METHOD => self.respond(request, Self::references),
+            DocumentSymbolRequest::METHOD => self.respond(request, Self::document_symbols),
+            Completion::METHOD => self.respond(request, Self::completion),
+            SignatureHelpRequest::METHOD => self.respond(request, Self::signature_help),
+            InlayHintRequest::METHOD => self.respond(request, Self::inlay_hints),
+            method => Response::new_err(
+                request.id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unhandled request `{method}`"),
+            ),
+        }
+    }
+
+    fn respond<P: serde::de::DeserializeOwned, R: serde::Serialize>(
+        &self,
+        request: Request,
+        handler: impl FnOnce(&Self, P) -> R,
+    ) -> Response {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    // returns the document that needs its diagnostics published, if any
+    fn handle_notification(&mut self, notification: Notification) -> Option<Uri> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let params: lsp_types::DidOpenTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.update(params.text_document.uri.clone(), &params.text_document.text);
+
+                Some(params.text_document.uri)
+            }
+            // only full syncs are advertised, so the last change is the whole document
+            DidChangeTextDocument::METHOD => {
+                let params: lsp_types::DidChangeTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.update(
+                    params.text_document.uri.clone(),
+                    &params.content_changes.last()?.text,
+                );
+
+                Some(params.text_document.uri)
+            }
+            DidCloseTextDocument::METHOD => {
+                let params: lsp_types::DidCloseTextDocumentParams =
+                    serde_json::from_value(notification.params).ok()?;
+                self.documents.remove(&params.text_document.uri);
+
+                Some(params.text_document.uri)
+            }
+            _ => None,
+        }
+    }
+
+    fn update(&mut self, uri: Uri, text: &str) {
+        let text = ArcStr::from(text);
+        let (tree, mut diagnostics) = crate::syntax_tree(&text);
+        let (analysis, errs) = crate::resolve(&crate::lower(&tree));
+        diagnostics.extend(errs);
+        let diagnostics = diagnostics
+            .into_iter()
+            .map(|d| lsp_diagnostic(&uri, &text, d))
+            .collect();
+
+        self.documents.insert(
+            uri,
+            Document {
+                text,
+                tree,
+                analysis,
+                diagnostics,
+            },
+        );
+    }
+
+    // closed documents get their diagnostics cleared
+    fn diagnostics(&self, uri: Uri) -> Notification {
+        let diagnostics = self
+            .documents
+            .get(&uri)
+            .map(|document| document.diagnostics.clone())
+            .unwrap_or_default();
+
+        Notification::new(
+            PublishDiagnostics::METHOD.to_string(),
+            PublishDiagnosticsParams::new(uri, diagnostics, None),
+        )
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let (name, symbol) = document
+            .analysis
+            .symbol_at(offset(&document.text, position.position)?)?;
+        let symbol = &document.analysis.symbols[symbol];
+
+        let mut value = match symbol.detail() {
+            Some(detail) => format!("```caelis\n{} : {detail}\n```", symbol.name.text()),
+            None => format!("```caelis\n{}\n```", symbol.name.text()),
+        };
+
+        let documented = matches!(
+            symbol.kind,
+            SymbolKind::Value | SymbolKind::Function | SymbolKind::Type | SymbolKind::Generic
+        );
+
+        if let Some(docs) = documented
+            .then(|| doc_comment(&document.tree, symbol.text.range()))
+            .flatten()
+        {
+            value.push_str("\n\n");
+            value.push_str(&docs);
+        }
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value,
+            }),
+            range: Some(range(&document.text, name.text().range())),
+        })
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let (_, symbol) = document
+            .analysis
+            .symbol_at(offset(&document.text, position.position)?)?;
+
+        Some(GotoDefinitionResponse::Scalar(location(
+            &position.text_document.uri,
+            &document.text,
+            &document.analysis.symbols[symbol].name,
+        )))
+    }
+
+    fn references(&self, params: ReferenceParams) -> Option<Vec<Location>> {
+        let position = params.text_document_position;
+        let uri = &position.text_document.uri;
+        let document = self.documents.get(uri)?;
+        let (_, symbol) = document
+            .analysis
+            .symbol_at(offset(&document.text, position.position)?)?;
+        let declaration = &document.analysis.symbols[symbol].name;
+
+        Some(
+            params
+                .context
+                .include_declaration
+                .then_some(declaration)
+                .into_iter()
+                .chain(document.analysis.references_to(symbol))
+                .map(|name| location(uri, &document.text, name))
+                .collect(),
+        )
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(&params.text_document.uri)?;
+
+        Some(DocumentSymbolResponse::Nested(
+            document
+                .analysis
+                .top_level
+                .iter()
+                .map(|symbol| document_symbol(document, *symbol))
+                .collect(),
+        ))
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let position = params.text_document_position;
+        let document = self.documents.get(&position.text_document.uri)?;
+        let offset = offset(&document.text, position.position)?;
+
+        Some(CompletionResponse::Array(complete::completions(
+            &document.text,
+            &document.analysis,
+            offset,
+        )))
+    }
+
+    fn signature_help(&self, params: SignatureHelpParams) -> Option<SignatureHelp> {
+        let position = params.text_document_position_params;
+        let document = self.documents.get(&position.text_document.uri)?;
+
+        complete::signature_help(&document.text, offset(&document.text, position.position)?)
+    }
+
+    // bindings can't be annotated, so they get whatever type could be worked out for them
+    fn inlay_hints(&self, params: InlayHintParams) -> Option<Vec<InlayHint>> {
+        let document = self.documents.get(&params.text_document.uri)?;
+
+        Some(
+            document
+                .analysis
+                .symbols
+                .iter()
+                .filter(|symbol| symbol.kind == SymbolKind::Value)
+                .filter_map(|symbol| {
+                    let ty = symbol.ty.as_ref().filter(|ty| ty.is_known())?;
+                    let position = position(&document.text, symbol.name.text().range().end);
+
+                    (params.range.start <= position && position <= params.range.end).then(|| {
+                        InlayHint {
+                            position,
+                            label: InlayHintLabel::String(format!(": {ty}")),
+                            kind: Some(InlayHintKind::TYPE),
+                            text_edits: None,
+                            tooltip: None,
+                            padding_left: None,
+                            padding_right: Some(true),
+                            data: None,
+                        }
+                    })
+                })
+                .collect(),
+        )
+    }
+}
+
+#[allow(deprecated)]
+fn document_symbol(document: &Document, symbol: usize) -> DocumentSymbol {
+    let symbol = &document.analysis.symbols[symbol];
+    let children: Vec<DocumentSymbol> = symbol
+        .children
+        .iter()
+        .map(|child| document_symbol(document, *child))
+        .collect();
+    let name_range = range(&document.text, symbol.name.text().range());
+
+    DocumentSymbol {
+        name: symbol.name.text().to_string(),
+        detail: symbol.detail(),
+        kind: match symbol.kind {
+            SymbolKind::Value => lsp_types::SymbolKind::VARIABLE,
+            SymbolKind::Function => lsp_types::SymbolKind::FUNCTION,
+            SymbolKind::Parameter => lsp_types::SymbolKind::VARIABLE,
+            SymbolKind::Type => lsp_types::SymbolKind::STRUCT,
+            SymbolKind::Field => lsp_types::SymbolKind::FIELD,
+            SymbolKind::Generic => lsp_types::SymbolKind::INTERFACE,
+            SymbolKind::TypeParameter => lsp_types::SymbolKind::TYPE_PARAMETER,
+        },
+        tags: None,
+        deprecated: None,
+        // children share their parent's definition, so they only get their name
+        range: match symbol.kind {
+            SymbolKind::Field | SymbolKind::TypeParameter => name_range,
+            _ => range(&document.text, symbol.text.range()),
+        },
+        selection_range: name_range,
+        children: (!children.is_empty()).then_some(children),
+    }
+}
+
+fn lsp_diagnostic(uri: &Uri, text: &str, diagnostic: Diagnostic) -> lsp_types::Diagnostic {
+    lsp_types::Diagnostic {
+        range: range(text, diagnostic.range),
+        severity: Some(DiagnosticSeverity::ERROR),
+        code: Some(NumberOrString::String(diagnostic.code.to_string())),
+        source: Some("caelis".to_string()),
+        message: diagnostic.message,
+        related_information: (!diagnostic.secondary.is_empty()).then(|| {
+            diagnostic
+                .secondary
+                .into_iter()
+                .map(|(secondary, message)| DiagnosticRelatedInformation {
+                    location: Location::new(uri.clone(), range(text, secondary)),
+                    message,
+                })
+                .collect()
+        }),
+        ..Default::default()
+    }
+}
+
+// `#` comment lines directly above a definition, which all end up in the leading trivia of its first
+// token. a blank line cuts them off
+fn doc_comment(tree: &SyntaxNode, def: std::ops::Range<usize>) -> Option<String> {
+    let first = *tree.find(def)?.tokens().first()?;
+    let mut lines = Vec::new();
+
+    for trivia in lexer::trivia(&first.leading).into_iter().rev() {
+        match trivia.kind {
+            TriviaKind::Comment => {
+                lines.push(trivia.text.trim_start_matches('#').trim().to_string())
+            }
+            TriviaKind::Whitespace if trivia.text.matches('\n').count() > 1 => break,
+            TriviaKind::Whitespace => (),
+        }
+    }
+
+    (!lines.is_empty()).then(|| lines.into_iter().rev().collect::<Vec<_>>().join("\n"))
+}
+
+fn location(uri: &Uri, text: &str, name: &Name) -> Location {
+    Location::new(uri.clone(), range(text, name.text().range()))
+}
+
+fn range(text: &str, range: std::ops::Range<usize>) -> Range {
+    Range::new(position(text, range.start), position(text, range.end))
+}
+
+// lsp positions count columns in utf-16 code units
+fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+    Position::new(
+        before.matches('\n').count() as u32,
+        before[line_start..].encode_utf16().count() as u32,
+    )
+}
+
+fn offset(text: &str, position: Position) -> Option<usize> {
+    let line_start = match position.line {
+        0 => 0,
+        line => text.match_indices('\n').nth(line as usize - 1)?.0 + 1,
+    };
+    let line = text[line_start..].split('\n').next().unwrap_or_default();
+    let mut units = 0;
+
+    for (i, c) in line.char_indices() {
+        if units >= position.character {
+            return Some(line_start + i);
+        }
+
+        units += c.len_utf16() as u32;
+    }
+
+    Some(line_start + line.len())
+}
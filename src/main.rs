@@ -1,72 +1,213 @@
-use std::{env, fs};
+// the `caelis` command line, which only works out what was asked for. doing it is up to `driver`
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    io::{self, Write},
+    process::ExitCode,
+    time::Duration,
+};
 
-use arcstr::ArcStr;
-use ariadne::{sources, Color, Label, Report, ReportKind};
-use chumsky::Parser;
+use anyhow::{Context, Result};
+use caelis::{
+    driver::{self, AstFormat, Reported},
+    Emit, Format, Limits,
+};
+use clap::Parser;
 
-mod ast;
-mod compiler;
-mod lexer;
-mod parser;
+#[derive(clap::Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(required = true)]
+    file: Option<String>,
+    #[arg(long, value_enum, global = true, default_value_t = Format::Human)]
+    error_format: Format,
+    /// Print a backtrace if caelis itself goes wrong
+    #[arg(long, global = true)]
+    backtrace: bool,
+}
 
-//TODO: this needs some clean-up ~~and also we need to be able to resolve imports!!! that's
-//important~~ no imports are pain
-fn main() {
-    let filename = env::args().nth(1).expect("Expected file argument");
-    let src = fs::read_to_string(&filename).expect("Failed to read file");
-    let arcstr = ArcStr::from(src.as_str());
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Print a longer explanation of an error code
+    Explain { code: String },
+    /// Parse a file and print its syntax tree
+    Parse {
+        file: String,
+        #[arg(long, value_enum, default_value_t = AstFormat::Debug)]
+        format: AstFormat,
+    },
+    /// Run the language server over stdio
+    Lsp,
+    /// Start an interactive session
+    Repl,
+    /// Run a file and print what its `main` comes out as
+    ///
+    /// Source files are compiled to bytecode first, `.caeb` files are run as they are
+    Run {
+        file: String,
+        /// Evaluate the program directly instead of compiling it
+        #[arg(long)]
+        interp: bool,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Compile a file without running it
+    Build {
+        file: String,
+        #[arg(long, value_enum, default_value_t = Emit::Bytecode)]
+        emit: Emit,
+        /// Where to write the output, next to the file by default
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Run the tests in files
+    Test {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Only run tests whose names contain this
+        #[arg(long)]
+        filter: Option<String>,
+        #[command(flatten)]
+        limits: LimitArgs,
+    },
+    /// Format files in place
+    Fmt {
+        #[arg(required = true)]
+        files: Vec<String>,
+        /// Fail if any file isn't formatted instead of changing it
+        #[arg(long)]
+        check: bool,
+    },
+}
 
-    let (tokens, errs) = lexer::tokenize(&arcstr);
+// every test gets these to itself
+#[derive(clap::Args)]
+struct LimitArgs {
+    /// Stop after this many steps
+    #[arg(long)]
+    fuel: Option<u64>,
+    /// Stop when calls go more than this deep
+    #[arg(long)]
+    max_depth: Option<usize>,
+    /// Stop once this many bytes have been allocated in all, counting what's since been freed
+    #[arg(long)]
+    max_alloc: Option<usize>,
+    /// Stop after this many seconds
+    #[arg(long, value_parser = seconds)]
+    timeout: Option<Duration>,
+}
 
-    let parse_errs = if let Some(tokens) = &tokens {
-        let (ast, parse_errs) = parser::create()
-            .parse(tokens.as_slice())
-            .into_output_errors();
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    report_ices(cli.backtrace);
 
-        if let Some(defs) = ast.filter(|_| errs.len() + parse_errs.len() == 0) {
-            println!("{:#?}", defs)
+    // checking, compiling and formatting a program all go as deep as it's nested
+    match driver::with_stack(|| run(&cli)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.is::<Reported>() => ExitCode::FAILURE,
+        Err(e) if broken_pipe(&e) => ExitCode::FAILURE,
+        Err(e) => {
+            // stderr might be gone too, and then there's nowhere left to say so
+            let _ = writeln!(io::stderr(), "error: {e:#}");
+            ExitCode::FAILURE
         }
+    }
+}
+
+// a panic is always a bug in caelis rather than in the program it was given, so it says as much instead
+// of leaving the user with rust's message. it's written with `writeln!` because `eprintln!` panics when
+// stderr is closed, and a panic in here aborts
+fn report_ices(backtrace: bool) {
+    std::panic::set_hook(Box::new(move |info| {
+        let message = info
+            .payload()
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| info.payload().downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "something went wrong".to_string());
+        let location = info
+            .location()
+            .map(|location| format!("\n  at {location}"))
+            .unwrap_or_default();
+
+        // whatever was reading stdout stopping early, like `head` does, isn't a bug
+        if message.starts_with("failed printing to stdout: Broken pipe") {
+            return;
+        }
+
+        // `RUST_BACKTRACE` works too
+        let trace = match backtrace {
+            true => Backtrace::force_capture(),
+            false => Backtrace::capture(),
+        };
+        let trace = match trace.status() {
+            BacktraceStatus::Captured => format!("\n{trace}"),
+            _ => "run with `--backtrace` to see where it happened".to_string(),
+        };
+
+        let _ = writeln!(
+            io::stderr(),
+            "[ICE] internal compiler error: {message}{location}\n\
+             this is a bug in caelis, please report it along with the code that caused it\n\
+             {trace}"
+        );
+    }));
+}
+
+// whatever was reading stdout stopped early, like `head` does, so there's no one left to tell
+fn broken_pipe(e: &anyhow::Error) -> bool {
+    e.chain().any(|e| {
+        e.downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() == io::ErrorKind::BrokenPipe)
+    })
+}
 
-        parse_errs
-    } else {
-        Vec::new()
-    };
+//TODO: this needs some clean-up ~~and also we need to be able to resolve imports!!! that's
+//important~~ no imports are pain
+fn run(cli: &Cli) -> Result<()> {
+    let error_format = cli.error_format;
 
-    errs.into_iter()
-        .map(|e| e.map_token(|c| c.to_string()))
-        .for_each(|e| {
-            Report::build(ReportKind::Error, (filename.clone(), e.span().into_range()))
-                .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
-                .with_message(e.to_string())
-                .with_label(
-                    Label::new((filename.clone(), e.span().into_range()))
-                        .with_message(e.reason().to_string())
-                        .with_color(Color::Red),
-                )
-                .finish()
-                .print(sources([(filename.clone(), src.clone())]))
-                .unwrap()
-        });
+    match &cli.command {
+        Some(Command::Explain { code }) => driver::explain(code),
+        Some(Command::Parse { file, format }) => driver::parse(file, *format, error_format),
+        Some(Command::Lsp) => driver::lsp(),
+        Some(Command::Repl) => driver::repl(),
+        Some(Command::Run {
+            file,
+            interp,
+            limits,
+        }) => driver::run(file, *interp, limits.into(), error_format),
+        Some(Command::Build { file, emit, output }) => {
+            driver::build(file, *emit, output.as_deref(), error_format)
+        }
+        Some(Command::Test {
+            files,
+            filter,
+            limits,
+        }) => driver::test(files, filter.as_deref(), limits.into(), error_format),
+        Some(Command::Fmt { files, check }) => driver::fmt(files, *check, error_format),
+        None => {
+            let file = cli.file.as_ref().context("there's no file to parse")?;
+            driver::parse(file, AstFormat::Debug, error_format)
+        }
+    }
+}
+
+impl From<&LimitArgs> for Limits {
+    fn from(args: &LimitArgs) -> Self {
+        Limits {
+            fuel: args.fuel,
+            depth: args.max_depth,
+            allocation: args.max_alloc,
+            timeout: args.timeout,
+        }
+    }
+}
 
-    parse_errs.into_iter()
-        .map(|e| (e.reason().found().map(|t| t.span.range()).unwrap_or(arcstr.len()..arcstr.len()), e.map_token(|t| format!("{}", t.kind))))
-        .for_each(|(range, e)| {
-            Report::build(ReportKind::Error, (filename.clone(), range.clone()))
-                .with_config(ariadne::Config::new().with_index_type(ariadne::IndexType::Byte))
-                .with_message(e.to_string())
-                .with_label(
-                    Label::new((filename.clone(), range))
-                        .with_message(e.reason().to_string())
-                        .with_color(Color::Red),
-                )
-                .with_labels(e.contexts().map(|(label, span)| {
-                    let range = tokens.clone().unwrap().get(span.start).unwrap().span.range().start..tokens.clone().unwrap().get(span.end).unwrap().span.range().end;
-                    Label::new((filename.clone(), range))
-                        .with_message(format!("while parsing this {label}"))
-                        .with_color(Color::Yellow)
-                }))
-                .finish()
-                .print(sources([(filename.clone(), src.clone())]))
-                .unwrap()
-        });
+fn seconds(arg: &str) -> Result<Duration, String> {
+    arg.parse()
+        .ok()
+        .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+        .ok_or_else(|| format!("`{arg}` isn't a number of seconds"))
 }
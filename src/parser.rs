@@ -1,7 +1,23 @@
+use std::collections::HashSet;
+
 use arcstr::Substr;
-use chumsky::{pratt::*, prelude::*};
+use chumsky::{
+    error::RichPattern,
+    input::{Checkpoint, Cursor, InputRef},
+    inspector::Inspector,
+    label::LabelError,
+    pratt::*,
+    prelude::*,
+    util::MaybeRef,
+};
 
-use crate::ast::{self, Ast, Def, Expr, TypeRef};
+use crate::{
+    ast::{Associativity, Ast, Expr},
+    cst::{Node, SyntaxKind, SyntaxNode},
+    diagnostic::{Code, SyntaxError},
+    lexer::{Token, TokenKind},
+    lower,
+};
 
 macro_rules! parser_shell {
     ($v:vis $name:ident, $ret:ty, $code:expr $(, $($arg:ident: $ty:ty),+)?) => {
@@ -13,12 +29,12 @@ macro_rules! parser_shell {
 
 macro_rules! parser {
     ($v:vis $name:ident, $ret:ty, $code:expr $(, $($arg:ident: $ty:ty),+)?) => {
-        parser_shell!($v $name, impl chumsky::prelude::Parser<'src, I, $ret, chumsky::extra::Err<chumsky::prelude::Rich<'src, crate::lexer::Token>>> + Clone, $code $(, $($arg: $ty),+)?);
+        parser_shell!($v $name, impl chumsky::prelude::Parser<'src, I, $ret, crate::parser::Extra<'src>> + Clone, $code $(, $($arg: $ty),+)?);
     };
 }
 
 macro_rules! rec_parser {
-    ($name:ident, $ret:ty, $this:ident => $code:expr) => {
+    ($name:ident, $ret:ty, $this:ident => $code:expr $(, $($arg:ident: $ty:ty),+)?) => {
         parser_shell!(
             $name,
             chumsky::prelude::Recursive<
@@ -27,7 +43,7 @@ macro_rules! rec_parser {
                     'src,
                     I,
                     $ret,
-                    chumsky::extra::Err<chumsky::prelude::Rich<'src, crate::lexer::Token>>,
+                    crate::parser::Extra<'src>,
                 >,
             >,
             {
@@ -36,13 +52,14 @@ macro_rules! rec_parser {
 
                 $this
             }
+            $(, $($arg: $ty),+)?
         );
     };
 }
 
 macro_rules! rec_child_parser {
     ($name:ident, $ret:ty, $parent:ident: $parent_ret:ty => $code:expr) => {
-        parser!($name, $ret, $code, $parent: chumsky::prelude::Recursive<chumsky::recursive::Indirect<'src, 'src, I, $parent_ret, chumsky::extra::Err<chumsky::prelude::Rich<'src, crate::lexer::Token>>>>);
+        parser!($name, $ret, $code, $parent: chumsky::prelude::Recursive<chumsky::recursive::Indirect<'src, 'src, I, $parent_ret, crate::parser::Extra<'src>>>);
     };
 }
 
@@ -52,139 +69,424 @@ macro_rules! token {
             crate::lexer::Token {
                 kind: crate::lexer::TokenKind::$kind,
                 span: s,
+                ..
             } => s,
         }
     };
 }
 
+// past this, the parser itself could run out of stack, so it's an error instead
+pub const MAX_DEPTH: usize = 256;
+
+// operators chain without the parser going any deeper, but everything that walks the tree afterwards
+// recurses once per operator. that all happens on `interp::with_stack`, which has room for trees a good
+// deal deeper than this, and no chain anyone writes by hand comes near it
+pub const MAX_TREE_DEPTH: usize = 10_000;
+
+pub type Extra<'src> = extra::Full<SyntaxError<'src, Token>, Depth, ()>;
+
+// how many expressions deep the parser is, carried along as the parser's state
+#[derive(Default)]
+pub struct Depth(usize);
+
+impl<'src, I: Input<'src>> Inspector<'src, I> for Depth {
+    type Checkpoint = ();
+
+    fn on_token(&mut self, _: &I::Token) {}
+
+    fn on_save<'parse>(&self, _: &Cursor<'src, 'parse, I>) {}
+
+    // `nested` always puts the depth back however its parser went, so going back never changes it
+    fn on_rewind<'parse>(&mut self, _: &Checkpoint<'src, 'parse, I, ()>) {}
+}
+
+#[derive(Debug, Clone)]
+pub struct Fixity {
+    pub operator: String,
+    pub associativity: Associativity,
+    pub precedence: u16,
+}
+
+// operators every program gets unless it declares its own fixity for them
+const DEFAULT_FIXITIES: &[(&str, Associativity, u16)] = &[
+    ("*", Associativity::Left, 7),
+    ("/", Associativity::Left, 7),
+    ("%", Associativity::Left, 7),
+    ("+", Associativity::Left, 6),
+    ("-", Associativity::Left, 6),
+    ("==", Associativity::Left, 4),
+    ("!=", Associativity::Left, 4),
+    ("<", Associativity::Left, 4),
+    ("<=", Associativity::Left, 4),
+    (">", Associativity::Left, 4),
+    (">=", Associativity::Left, 4),
+    ("&&", Associativity::Right, 3),
+    ("||", Associativity::Right, 2),
+];
+
+// fixity declarations have to be known before any expression can be parsed, so they get picked out
+// of the token stream ahead of time. malformed ones are skipped here and reported by `create`
+pub fn fixities(tokens: &[Token]) -> Vec<Fixity> {
+    with_defaults(declared_fixities(tokens))
+}
+
+pub fn declared_fixities(tokens: &[Token]) -> Vec<Fixity> {
+    let Some(src) = tokens.first().map(|t| t.span.parent().clone()) else {
+        return Vec::new();
+    };
+    let declared: Vec<Option<Node>> = fixity_definition()
+        .map(Some)
+        .or(any().to(None))
+        .repeated()
+        .collect()
+        .parse(tokens)
+        .into_output()
+        .unwrap_or_default();
+
+    declared
+        .into_iter()
+        .flatten()
+        .filter_map(|node| lower::fixity_def(&SyntaxNode::new(&src, tokens, node)))
+        .flat_map(|def| {
+            def.operators.into_iter().map(move |op| Fixity {
+                operator: op.text().to_string(),
+                associativity: def.associativity,
+                precedence: def.precedence,
+            })
+        })
+        .collect()
+}
+
+pub fn with_defaults(mut fixities: Vec<Fixity>) -> Vec<Fixity> {
+    for (operator, associativity, precedence) in DEFAULT_FIXITIES {
+        if !fixities.iter().any(|f| f.operator == *operator) {
+            fixities.push(Fixity {
+                operator: operator.to_string(),
+                associativity: *associativity,
+                precedence: *precedence,
+            });
+        }
+    }
+
+    fixities
+}
+
 parser!(
     pub create,
-    Vec<Def>,
+    Node,
     choice((
-        generic_definition()
-            .map(|def| Def::Generic(def)),
-        definition(expr())
-            .map(|def| Def::Value(def)),
-        type_definition()
-            .map(|def| Def::Type(def)),
+        generic_definition(),
+        definition(expr(fixities.clone())),
+        test_definition(expr(fixities)),
+        type_definition(),
+        fixity_definition(),
+    ))
+    .validate(|def, e, emitter| match depth(&def) > MAX_TREE_DEPTH {
+        true => {
+            emitter.emit(SyntaxError::custom(
+                Code::NestedTooDeeply,
+                e.span(),
+                format!("this definition is nested more than {MAX_TREE_DEPTH} deep"),
+            ));
+            Node::new(SyntaxKind::Error, e.span(), Vec::new())
+        }
+        false => def,
+    })
+    // a broken definition stays in the tree as an error, so the rest of the file still gets parsed
+    .recover_with(via_parser(
+        skip_definition().map_with(|_, e| Node::new(SyntaxKind::Error, e.span(), Vec::new())),
     ))
     .repeated()
     .collect()
-    //TODO: I'm tired
-    // .map(|defs: Vec<Def>| {
-    //     ast::Root {
-    //         text: defs.first().map(|f| f.text().range().start..defs.last().unwrap().text().range().end).unwrap_or(0..0),
-    //         defs,
-    //     }
-    // })
-    .labelled("definition")
+    .map_with(|defs, e| Node::new(SyntaxKind::Root, e.span(), defs))
+    .labelled("definition"),
+    fixities: Vec<Fixity>
+);
+
+// a bare expression, the way the repl takes it
+parser!(
+    pub expression,
+    Node,
+    expr(fixities).then_ignore(token!(Semicolon)),
+    fixities: Vec<Fixity>
+);
+
+// a type by itself, like the signature of a function the host provides
+parser!(
+    pub signature,
+    Node,
+    inner_type_ref().then_ignore(end())
+);
+
+// an unfinished definition at the end of the file is skipped too, so the ones before it survive
+parser!(
+    skip_definition,
+    (),
+    choice((
+        not_semicolon().repeated().then(token!(Semicolon)).ignored(),
+        not_semicolon().repeated().at_least(1).then(end()).ignored(),
+    ))
+);
+
+parser!(
+    not_semicolon,
+    (),
+    select! { t @ Token { .. } if t.kind != TokenKind::Semicolon => () }
 );
 
 parser!(
     generic_definition,
-    ast::GenericDef,
-    name()
+    Node,
+    binding_name()
         .then_ignore(token!(DollarSign))
-        .then(generic_arg_def().separated_by(token!(Comma)).collect())
-        .then(token!(Semicolon))
-        .map(|((name, args), end_span)| ast::GenericDef {
-            text: end_span
-                .parent()
-                .substr(name.text().range().start..end_span.range().end),
-            name,
-            args
+        .then(generic_arg_def().separated_by(token!(Comma)).collect::<Vec<_>>())
+        .then_ignore(token!(Semicolon))
+        .map_with(|(name, args), e| {
+            Node::new(SyntaxKind::GenericDef, e.span(), [vec![name], args].concat())
         })
         .labelled("generic definition")
 );
 
 parser!(
     generic_arg_def,
-    (ast::Name, Vec<TypeRef>),
-    name()
-        .then(type_ref().separated_by(token!(Ampersand)).collect())
+    Node,
+    binding_name()
+        .then(type_ref().separated_by(token!(Ampersand)).collect::<Vec<_>>())
+        .map_with(|(name, bounds), e| {
+            Node::new(SyntaxKind::GenericArg, e.span(), [vec![name], bounds].concat())
+        })
         .labelled("generic type argument")
 );
 
 rec_child_parser!(
     definition,
-    ast::ValueDef,
-    expr: Box<Expr> => name()
+    Node,
+    expr: Node => binding_name()
         .then_ignore(token!(Equal))
         .then(expr)
-        .then(token!(Semicolon))
-        .map(|((name, body), end_span)| ast::ValueDef { text: end_span.parent().substr(name.text().range().start..end_span.range().end), name, body: *body })
+        .then_ignore(token!(Semicolon))
+        .map_with(|(name, body), e| Node::new(SyntaxKind::ValueDef, e.span(), vec![name, body]))
         .labelled("value definition")
 );
 
+rec_child_parser!(
+    test_definition,
+    Node,
+    expr: Node => token!(Test)
+        .then(token!(String).labelled("test name"))
+        .then(token!(Equal))
+        .ignore_then(expr)
+        .then_ignore(token!(Semicolon))
+        .map_with(|body, e| Node::new(SyntaxKind::TestDef, e.span(), vec![body]))
+        .labelled("test")
+);
+
 parser!(
     type_definition,
-    ast::TypeDef,
-    name()
+    Node,
+    binding_name()
         .then_ignore(token!(Pipe))
-        .then(field_def().separated_by(token!(Comma)).collect())
-        .then(token!(Semicolon))
-        .map(|((name, fields), end_span)| ast::TypeDef {
-            text: end_span
-                .parent()
-                .substr(name.text().range().start..end_span.range().end),
-            name,
-            fields
+        .then(field_def().separated_by(token!(Comma)).collect::<Vec<_>>())
+        .then_ignore(token!(Semicolon))
+        .map_with(|(name, fields), e| {
+            Node::new(SyntaxKind::TypeDef, e.span(), [vec![name], fields].concat())
         })
         .labelled("type definition")
 );
 
 parser!(
     field_def,
-    (ast::Name, TypeRef),
-    name().then(type_ref()).labelled("field definition")
+    Node,
+    binding_name()
+        .then(type_ref())
+        .map_with(|(name, type_ref), e| Node::new(SyntaxKind::Field, e.span(), vec![name, type_ref]))
+        .labelled("field definition")
+);
+
+parser!(
+    fixity_definition,
+    Node,
+    choice((token!(Infixl), token!(Infixr)))
+        .then(precedence())
+        .then(operator().separated_by(token!(Comma)).at_least(1))
+        .then(token!(Semicolon))
+        .map_with(|_, e| Node::new(SyntaxKind::FixityDef, e.span(), Vec::new()))
+        .labelled("fixity declaration")
+);
+
+parser!(
+    precedence,
+    u16,
+    token!(Int)
+        .labelled("operator precedence")
+        .try_map(|s, span| {
+            s.parse::<u16>()
+                .ok()
+                .filter(|p| *p <= 9)
+                .ok_or_else(|| {
+                    SyntaxError::custom(
+                        Code::InvalidPrecedence,
+                        span,
+                        "operator precedence must be an integer from 0 to 9",
+                    )
+                })
+        })
 );
 
+// nothing's too deep once part of it has had to be skipped, whatever was wrong has been reported
+fn depth(node: &Node) -> usize {
+    let mut deepest = 0;
+    let mut nodes = vec![(node, 1)];
+
+    while let Some((node, depth)) = nodes.pop() {
+        if node.kind == SyntaxKind::Error {
+            return 0;
+        }
+
+        deepest = deepest.max(depth);
+        nodes.extend(node.children.iter().map(|child| (child, depth + 1)));
+    }
+
+    deepest
+}
+
+// `parser`, unless the expression it's in is already as deep as expressions can go. parentheses,
+// functions, `if`s and `let`s all parse the expression inside them recursively
+parser!(
+    nested,
+    Node,
+    custom(move |input: &mut InputRef<'src, '_, I, Extra<'src>>| {
+        let before = input.cursor();
+        let depth = input.state().0 + 1;
+
+        if depth > MAX_DEPTH {
+            let here: SimpleSpan = input.span_since(&before);
+
+            return Err(SyntaxError::custom(
+                Code::NestedTooDeeply,
+                (here.start..here.start + 1).into(),
+                format!("this is nested more than {MAX_DEPTH} deep"),
+            ));
+        }
+
+        input.state().0 = depth;
+        let node = input.parse(&parser);
+        input.state().0 = depth - 1;
+
+        node
+    }),
+    parser: impl Parser<'src, I, Node, Extra<'src>> + Clone
+);
+
+// operators, composition and pipes all look the same in the tree, it's their token that says what they do
+fn binary(lhs: Node, rhs: Node) -> Node {
+    Node::around(SyntaxKind::Binary, vec![lhs, rhs])
+}
+
+// binding powers: pipes sit at 1, declared precedences 0-9 map onto 2-11, composition is 12 and
+// application is 13
 rec_parser!(
     expr,
-    Box<Expr>,
-    this => if_then_else(this.clone())
-        .or(let_in(this.clone()))
-        .map(|expr| Box::new(expr))
-        .or(non_call_expr(this.clone()).pratt((
-            postfix(2, non_call_expr(this.clone()), |func: Box<Expr>, arg: Box<Expr>, _| {
-                Box::new(Expr::Call(func.text().parent().substr(func.text().range().start..arg.text().range().end), func, arg))
-            }),
-            infix(left(1), token!(PipeInto), |arg: Box<Expr>, _, func: Box<Expr>, _| {
-                Box::new(Expr::Call(arg.text().parent().substr(arg.text().range().start..func.text().range().end), arg, func))
-            }),
-        )))
-        .labelled("expression")
+    Node,
+    this => {
+        let declared: HashSet<String> = fixities.iter().map(|f| f.operator.clone()).collect();
+        let declared_ops = fixities
+            .iter()
+            .map(|fixity| {
+                let operator = fixity.operator.clone();
+                let power = fixity.precedence + 2;
+
+                infix(
+                    match fixity.associativity {
+                        Associativity::Left => left(power),
+                        Associativity::Right => right(power),
+                    },
+                    token!(Operator).filter(move |s: &Substr| s.as_str() == operator),
+                    |lhs, _, rhs, _| binary(lhs, rhs),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let expr = if_then_else(this.clone())
+            .or(let_in(this.clone()))
+            .or(field_expr(this.clone()).pratt((
+                postfix(13, field_expr(this.clone()), |func, arg, _| {
+                    Node::around(SyntaxKind::Call, vec![func, arg])
+                }),
+                declared_ops,
+                // anything without a fixity binds like `infixl 9`
+                infix(
+                    left(11),
+                    token!(Operator).filter(move |s: &Substr| !declared.contains(s.as_str())),
+                    |lhs, _, rhs, _| binary(lhs, rhs),
+                ),
+                infix(left(12), token!(ComposeInto), |f, _, g, _| binary(f, g)),
+                infix(right(12), token!(ComposeFrom), |f, _, g, _| binary(f, g)),
+                infix(left(1), token!(PipeInto), |arg, _, func, _| binary(arg, func)),
+                infix(right(1), token!(PipeFrom), |func, _, arg, _| binary(func, arg)),
+            )))
+            .labelled("expression");
+
+        nested(expr)
+    },
+    fixities: Vec<Fixity>
+);
+
+// `p.x` binds tighter than anything, application included
+rec_child_parser!(
+    field_expr,
+    Node,
+    expr: Node => non_call_expr(expr).foldl_with(
+        token!(Period).then(name()).repeated(),
+        |record, _, e| Node::new(SyntaxKind::FieldAccess, e.span(), vec![record]),
+    )
 );
 
 rec_child_parser!(
     non_call_expr,
-    Box<Expr>,
-    expr: Box<Expr> => choice((
+    Node,
+    expr: Node => choice((
         fn_def(expr.clone()),
         constant(),
         literal(),
-    )).map(|expr| Box::new(expr))
-    .or(choice((
-        token!(PipeFrom).ignore_then(expr.clone()),
-        expr.clone().delimited_by(token!(OpenParen), token!(CloseParen)),
-    )))
+        // the lexer has already complained about these
+        token!(Error).map_with(|_, e| Node::new(SyntaxKind::Error, e.span(), Vec::new())),
+    ))
+    .or(expr
+        .clone()
+        .delimited_by(token!(OpenParen), token!(CloseParen))
+        .map_with(|inner, e| Node::new(SyntaxKind::Paren, e.span(), vec![inner]))
+        .recover_with(via_parser(
+            balanced_parens().map_with(|_, e| Node::new(SyntaxKind::Error, e.span(), Vec::new())),
+        )))
+);
+
+rec_parser!(
+    balanced_parens,
+    (),
+    this => token!(OpenParen)
+        .then_ignore(
+            this.clone()
+                .or(select! { t @ Token { .. } if !matches!(t.kind, TokenKind::OpenParen | TokenKind::CloseParen) => () })
+                .repeated(),
+        )
+        .then(token!(CloseParen))
+        .ignored()
 );
 
 rec_child_parser!(
     fn_def,
-    Expr,
-    expr: Box<Expr> => name()
+    Node,
+    expr: Node => binding_name()
         .then(type_ref())
         .then_ignore(token!(Arrow))
         .then(type_ref().or_not())
         .then(expr)
-        .map(|(((arg_name, arg_type), ret_type), body)| {
-            Expr::Func(
-                arg_name.text().parent().substr(arg_name.text().range().start..body.text().range().end),
-                arg_name,
-                arg_type,
-                ret_type,
-                body,
+        .map_with(|(((name, arg_type), ret_type), body), e| {
+            Node::new(
+                SyntaxKind::Func,
+                e.span(),
+                [name, arg_type].into_iter().chain(ret_type).chain([body]).collect(),
             )
         })
         .labelled("function definition")
@@ -192,19 +494,18 @@ rec_child_parser!(
 
 rec_child_parser!(
     if_then_else,
-    Expr,
-    expr: Box<Expr> => token!(If)
-        .then(expr.clone())
+    Node,
+    expr: Node => token!(If)
+        .ignore_then(expr.clone())
         .then_ignore(token!(Then))
         .then(expr.clone())
         .then_ignore(token!(Else))
         .then(expr)
-        .map(|(((start_span, condition_expr), then_expr), else_expr)| {
-            Expr::IfThenElse(
-                start_span.parent().substr(start_span.range().start..else_expr.text().range().end),
-                condition_expr,
-                then_expr,
-                else_expr,
+        .map_with(|((condition_expr, then_expr), else_expr), e| {
+            Node::new(
+                SyntaxKind::IfThenElse,
+                e.span(),
+                vec![condition_expr, then_expr, else_expr],
             )
         })
         .labelled("branching expression")
@@ -212,17 +513,13 @@ rec_child_parser!(
 
 rec_child_parser!(
     let_in,
-    Expr,
-    expr: Box<Expr> => token!(Let)
-        .then(definition(expr.clone()).repeated().collect())
+    Node,
+    expr: Node => token!(Let)
+        .ignore_then(definition(expr.clone()).repeated().collect::<Vec<_>>())
         .then_ignore(token!(In))
         .then(expr)
-        .map(|((start_span, defs), body)| {
-            Expr::LetIn(
-                start_span.parent().substr(start_span.range().start..body.text().range().end),
-                defs,
-                body,
-            )
+        .map_with(|(defs, body), e| {
+            Node::new(SyntaxKind::LetIn, e.span(), [defs, vec![body]].concat())
         })
         .labelled("let expression")
 );
@@ -230,81 +527,166 @@ rec_child_parser!(
 //TODO: probably don't need this
 parser!(
     constant,
-    Expr,
-    name()
-        .map(|name| Expr::SymbolRef(name.text().clone(), name))
+    Node,
+    value_name()
+        .map(|name| Node::around(SyntaxKind::SymbolRef, vec![name]))
         .labelled("name reference")
 );
 
 parser!(
     literal,
-    Expr,
+    Node,
     //TODO: add support for other literals
     number().labelled("literal")
 );
 
 parser!(
     number,
-    Expr,
+    Node,
     choice((
         token!(Float)
-            .map(|s| Expr::Float(s.clone(), s.parse().unwrap()))
-            .labelled("float literal"),
+            .labelled("float literal")
+            .validate(|s, info, emitter| {
+                if let Err(msg) = number_literal(s, true) {
+                    emitter.emit(SyntaxError::custom(Code::LiteralOutOfRange, info.span(), msg));
+                }
+            }),
         token!(Int)
-            .map(|s| Expr::Int(s.clone(), s.parse().unwrap()))
-            .labelled("int literal"),
+            .labelled("int literal")
+            .validate(|s, info, emitter| {
+                if let Err(msg) = number_literal(s, false) {
+                    emitter.emit(SyntaxError::custom(Code::LiteralOutOfRange, info.span(), msg));
+                }
+            }),
     ))
+    .map_with(|_, e| Node::new(SyntaxKind::Literal, e.span(), Vec::new()))
     .labelled("number literal")
 );
 
+// the lexer has already checked the shape of the literal and reported bad suffixes, all that can go
+// wrong here is the value not fitting
+pub fn number_literal(s: Substr, float: bool) -> Result<Expr, String> {
+    let (radix, body) = match s.get(..2) {
+        Some("0x") => (16, &s[2..]),
+        Some("0o") => (8, &s[2..]),
+        Some("0b") => (2, &s[2..]),
+        _ => (10, s.as_str()),
+    };
+    let suffix_start = body
+        .char_indices()
+        .find(|&(i, c)| {
+            let exponent = radix == 10
+                && matches!(c, 'e' | 'E')
+                && body[i + 1..].starts_with(|n: char| n.is_ascii_digit() || n == '+' || n == '-');
+
+            !(c.is_digit(radix) || matches!(c, '_' | '.' | '+' | '-') || exponent)
+        })
+        .map(|(i, _)| i)
+        .unwrap_or(body.len());
+    let (body, float) = match body.split_at(suffix_start) {
+        (body, "f64") => (body, true),
+        (body, _) => (body, float),
+    };
+    let digits: String = body.chars().filter(|c| *c != '_').collect();
+
+    if float && radix == 10 {
+        match digits.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Expr::Float(s, value)),
+            _ => Err(format!("float literal `{s}` is out of range for f64")),
+        }
+    } else {
+        match i64::from_str_radix(&digits, radix) {
+            Ok(value) if float => Ok(Expr::Float(s, value as f64)),
+            Ok(value) => Ok(Expr::Int(s, value)),
+            Err(_) => Err(format!("integer literal `{s}` is out of range for i64")),
+        }
+    }
+}
+
 parser!(
     type_ref,
-    TypeRef,
+    Node,
     token!(Colon)
         .ignore_then(
             name()
-                .map(|r| TypeRef::Named(r.text().clone(), r, Vec::new()))
-                .or(token!(OpenParen)
-                    .ignore_then(inner_type_ref())
-                    .then_ignore(token!(CloseParen)))
+                .map_with(|_, e| Node::new(SyntaxKind::NamedType, e.span(), Vec::new()))
+                .or(inner_type_ref()
+                    .delimited_by(token!(OpenParen), token!(CloseParen))
+                    .map_with(|inner, e| Node::new(SyntaxKind::ParenType, e.span(), vec![inner])))
         )
+        .map_with(|inner, e| Node::new(SyntaxKind::TypeAnnotation, e.span(), vec![inner]))
         .labelled("type reference")
 );
 
 rec_parser!(
     inner_type_ref,
-    TypeRef,
+    Node,
     this => non_fn_inner_type_ref(this.clone()).pratt(infix(
         right(1),
         token!(Arrow),
-        |arg: TypeRef, _, ret: TypeRef, _| TypeRef::Function(arg.text().parent().substr(arg.text().range().start..ret.text().range().end), Box::new(arg), Box::new(ret)),
+        |arg, _, ret, _| Node::around(SyntaxKind::FunctionType, vec![arg, ret]),
     ))
 );
 
 rec_child_parser!(
     non_fn_inner_type_ref,
-    TypeRef,
-    type_ref: TypeRef => recursive(|this| {
+    Node,
+    type_ref: Node => recursive(|this| {
         choice((
             name()
-                .then(this.clone().repeated().collect())
-                .map(|(name, type_args)| {
-                    let type_args: Vec<TypeRef> = type_args;
-                    let end = type_args.last()
-                        .map(|t| t.text().range().end)
-                        .unwrap_or(name.text().range().end);
-
-                    TypeRef::Named(name.text().parent().substr(name.text().range().start..end), name, type_args)
-                }),
+                .ignore_then(this.clone().repeated().collect())
+                .map_with(|type_args, e| Node::new(SyntaxKind::NamedType, e.span(), type_args)),
             type_ref
                 .clone()
-                .delimited_by(token!(OpenParen), token!(CloseParen)),
+                .delimited_by(token!(OpenParen), token!(CloseParen))
+                .map_with(|inner, e| Node::new(SyntaxKind::ParenType, e.span(), vec![inner])),
         ))
     })
 );
 
 parser!(
     name,
-    ast::Name,
-    token!(Name).map(ast::Name).labelled("name")
+    (),
+    token!(Name).ignored().labelled("name")
+);
+
+parser!(
+    operator,
+    (),
+    token!(Operator).ignored().labelled("operator")
+);
+
+parser!(
+    value_name,
+    Node,
+    name()
+        .or(operator().delimited_by(token!(OpenParen), token!(CloseParen)))
+        .map_with(|_, e| Node::new(SyntaxKind::Name, e.span(), Vec::new()))
+);
+
+// a keyword where a name is being bound gets accepted with an error, rather than leaving the user with a
+// generic "expected name"
+parser!(
+    binding_name,
+    Node,
+    value_name().or(select! { t @ Token { .. } if t.kind.keyword().is_some() => t }
+        .then(any().or_not().rewind())
+        // only complain when the keyword is clearly being bound, otherwise this is just an ordinary
+        // "found `in`" error at the keyword
+        .try_map(|(t, next): (Token, Option<Token>), span| match next.map(|next| next.kind) {
+            Some(TokenKind::Equal | TokenKind::Colon | TokenKind::DollarSign | TokenKind::Pipe) => Ok(t),
+            _ => Err(LabelError::<I, RichPattern<Token>>::expected_found(
+                [],
+                Some(MaybeRef::Val(t)),
+                span,
+            )),
+        })
+        .validate(|t: Token, info, emitter| {
+            emitter.emit(SyntaxError::custom(
+                Code::KeywordAsName,
+                info.span(),
+                format!("`{}` is a keyword and can't be used as a name", t.kind),
+            ));
+        })
+        .map_with(|_, e| Node::new(SyntaxKind::Name, e.span(), Vec::new())))
 );
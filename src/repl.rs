@@ -0,0 +1,448 @@
+use std::{fs, ops::Range};
+
+use anyhow::Result;
+use arcstr::ArcStr;
+use chumsky::Parser;
+use rustyline::{error::ReadlineError, DefaultEditor};
+
+use crate::{
+    analysis::{Analysis, Type},
+    ast::{Ast, Def, Expr, Root, ValueDef},
+    bytecode::{self, Program, Vm},
+    cst::SyntaxNode,
+    diagnostic::{Diagnostic, FileId, Format, SourceMap, Suggestion},
+    lexer::{self, Token, TokenKind},
+    lower,
+    parser::{self, Fixity},
+    test,
+    value::{RuntimeError, Value},
+};
+
+// what an expression gets compiled as a definition of
+const IT: &str = "it = ";
+
+const HELP: &str = "\
+definitions stay in scope for the rest of the session, expressions get evaluated
+anything that doesn't end in `;` yet carries on over the next line
+
+:type <expr>     show the type of an expression without running it
+:load <file>     add the definitions in a file
+:help            show this
+:quit            leave, like ctrl-d";
+
+enum Entry {
+    Done,
+    Incomplete,
+}
+
+// the session with an entry added, and how its tests came out
+type Compiled = (Root, Program, Vec<Result<u32, RuntimeError>>);
+
+// a program can't be added to once it's compiled, so every entry gets compiled along with all the
+// definitions before it, and run on a VM of its own
+#[derive(Default)]
+struct Session {
+    // every definition so far
+    src: String,
+    // newest first, so a redeclared operator takes its latest fixity
+    fixities: Vec<Fixity>,
+    // the latest entry and each loaded file, so errors in them can say where they are
+    sources: SourceMap,
+}
+
+// what was just typed or loaded, and where it starts in the session it gets compiled as part of
+#[derive(Clone, Copy)]
+struct Input {
+    file: FileId,
+    offset: usize,
+}
+
+pub fn run() -> Result<()> {
+    let mut editor = DefaultEditor::new()?;
+    let mut session = Session::default();
+    let mut buffer = String::new();
+
+    loop {
+        let prompt = if buffer.is_empty() { "> " } else { "| " };
+        let line = match editor.readline(prompt) {
+            Ok(line) => line,
+            // ctrl-c throws away whatever is half typed
+            Err(ReadlineError::Interrupted) => {
+                buffer.clear();
+                continue;
+            }
+            Err(ReadlineError::Eof) => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        if buffer.is_empty() {
+            let command = line.trim();
+
+            if command.is_empty() {
+                continue;
+            } else if let Some(command) = command.strip_prefix(':') {
+                editor.add_history_entry(line.as_str())?;
+
+                if !session.command(command)? {
+                    return Ok(());
+                }
+
+                continue;
+            }
+        }
+
+        buffer.push_str(&line);
+        buffer.push('\n');
+
+        if let Entry::Done = session.entry(&buffer)? {
+            editor.add_history_entry(buffer.trim_end())?;
+            buffer.clear();
+        }
+    }
+}
+
+impl Session {
+    // false once it's time to leave
+    fn command(&mut self, command: &str) -> Result<bool> {
+        let (name, arg) = command
+            .split_once(char::is_whitespace)
+            .map(|(name, arg)| (name, arg.trim()))
+            .unwrap_or((command, ""));
+
+        match name {
+            "type" | "t" => self.type_of(arg)?,
+            "load" | "l" => self.load(arg)?,
+            "help" | "h" | "?" => println!("{HELP}"),
+            "quit" | "q" => return Ok(false),
+            _ => eprintln!("unknown command `:{name}`, try `:help`"),
+        }
+
+        Ok(true)
+    }
+
+    fn entry(&mut self, input: &str) -> Result<Entry> {
+        let src = ArcStr::from(input);
+        let (tokens, lex_errs) = lexer::tokenize(&src);
+
+        // nothing's finished before its `;`
+        if tokens.last().is_none_or(|t| t.kind != TokenKind::Semicolon) {
+            return Ok(Entry::Incomplete);
+        }
+
+        let fixities = self.fixities(&tokens);
+        let definition = is_definition(&tokens);
+        let parse_errs = if definition {
+            parser::create(fixities.clone())
+                .parse(tokens.as_slice())
+                .into_errors()
+        } else {
+            parser::expression(fixities.clone())
+                .parse(tokens.as_slice())
+                .into_errors()
+        };
+
+        let last = tokens.last().map(|t| t.span.range().end).unwrap_or(0);
+        let diagnostics = Diagnostic::from_syntax_errors(lex_errs, parse_errs, &tokens, src.len());
+
+        // a `let` has `;`s inside it, so running out of input means there's more to come
+        if !diagnostics.is_empty() && diagnostics.iter().all(|d| d.range.start >= last) {
+            return Ok(Entry::Incomplete);
+        }
+
+        let file = self.sources.set("<repl>", src.clone());
+
+        if !diagnostics.is_empty() {
+            for diagnostic in diagnostics {
+                diagnostic.report(&self.sources, file, Format::Human)?;
+            }
+
+            return Ok(Entry::Done);
+        }
+
+        match definition {
+            true => self.define(input, fixities, file)?,
+            false => self.evaluate(input, file)?,
+        }
+
+        Ok(Entry::Done)
+    }
+
+    // the session so far with `input` after it, as one program, along with a function for each test.
+    // `input` is the text of `file`, with `prefix` in front of it to make it a definition
+    fn compile(&mut self, prefix: &str, input: &str, file: FileId) -> Result<Option<Compiled>> {
+        let at = Input {
+            file,
+            offset: self.src.len() + prefix.len(),
+        };
+        let src = ArcStr::from(format!("{}{prefix}{input}", self.src));
+        let (root, diagnostics) = crate::parse(&src);
+
+        if !diagnostics.is_empty() {
+            for diagnostic in diagnostics {
+                self.report(diagnostic, &src, at)?;
+            }
+
+            return Ok(None);
+        }
+
+        match bytecode::compile_tests("<repl>", &root) {
+            Ok((program, tests)) => Ok(Some((root, program, tests))),
+            Err(e) => {
+                self.report_error(&e, at)?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn define(&mut self, input: &str, fixities: Vec<Fixity>, file: FileId) -> Result<()> {
+        let Some((root, program, tests)) = self.compile("", input, file)? else {
+            return Ok(());
+        };
+
+        let start = self.src.len();
+        let at = Input {
+            file,
+            offset: start,
+        };
+        self.fixities = fixities;
+        self.src.push_str(input);
+        self.src.push('\n');
+
+        // values get worked out straight away so anything wrong with them shows up here, and tests get
+        // run. only this entry's definitions are shown, the ones before it already have been
+        let mut tests = tests.into_iter();
+
+        for def in &root.defs {
+            let test = match def {
+                Def::Test(_) => tests.next(),
+                _ => None,
+            };
+
+            if def.text().range().start < start {
+                continue;
+            }
+
+            match (def, test) {
+                (Def::Value(value_def), _) => self.show(&program, value_def, at)?,
+                (Def::Test(test_def), Some(function)) => {
+                    let value = function.and_then(|function| Vm::new(&program).eval(function));
+
+                    match test::check(test_def, value) {
+                        Ok(()) => println!("test {} ... ok", test_def.literal),
+                        Err(e) => self.report_error(&e, at)?,
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn show(&mut self, program: &Program, value_def: &ValueDef, at: Input) -> Result<()> {
+        let name = value_def.name.text();
+        let ty = self.type_of_name(name);
+
+        match &value_def.body {
+            Expr::Func(..) => println!("{name} : {ty}"),
+            _ => match Vm::new(program).get(name) {
+                Some(Ok(value)) => println!("{name} : {} = {value}", known_type(ty, &value)),
+                Some(Err(e)) => self.report_error(&e, at)?,
+                None => (),
+            },
+        }
+
+        Ok(())
+    }
+
+    // an expression is compiled as a definition of `it`, which is also how its type gets worked out
+    fn evaluate(&mut self, input: &str, file: FileId) -> Result<()> {
+        let at = Input {
+            file,
+            offset: self.src.len() + IT.len(),
+        };
+        let Some((_, program, _)) = self.compile(IT, input, file)? else {
+            return Ok(());
+        };
+
+        match Vm::new(&program).get("it") {
+            Some(Ok(value)) => {
+                let ty = self.type_of_expr(input);
+                println!("{value} : {}", known_type(ty, &value));
+            }
+            Some(Err(e)) => self.report_error(&e, at)?,
+            None => (),
+        }
+
+        Ok(())
+    }
+
+    fn type_of(&mut self, input: &str) -> Result<()> {
+        let input = match input.trim_end().ends_with(';') {
+            true => input.to_string(),
+            false => format!("{input};"),
+        };
+        let src = ArcStr::from(input.as_str());
+        let file = self.sources.set("<repl>", src.clone());
+        let (tokens, lex_errs) = lexer::tokenize(&src);
+        let (expr, parse_errs) = parser::expression(self.fixities(&tokens))
+            .parse(tokens.as_slice())
+            .into_output_errors();
+        let expr = expr.map(|node| lower::expr(&SyntaxNode::new(&src, &tokens, node)));
+        let diagnostics = Diagnostic::from_syntax_errors(lex_errs, parse_errs, &tokens, src.len());
+
+        if !diagnostics.is_empty() {
+            for diagnostic in diagnostics {
+                diagnostic.report(&self.sources, file, Format::Human)?;
+            }
+
+            return Ok(());
+        }
+
+        // compiling it is enough to find names that aren't defined, without running anything
+        if let Some(expr) = expr {
+            if self.compile(IT, &input, file)?.is_some() {
+                println!("{} : {}", expr.text(), self.type_of_expr(&input));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn load(&mut self, file: &str) -> Result<()> {
+        let text = match fs::read_to_string(file) {
+            Ok(text) => ArcStr::from(text),
+            Err(e) => {
+                eprintln!("can't read `{file}`: {e}");
+                return Ok(());
+            }
+        };
+        let id = self.sources.set(file, text.clone());
+        let (_, diagnostics) = crate::parse(&text);
+
+        if !diagnostics.is_empty() {
+            for diagnostic in diagnostics {
+                diagnostic.report(&self.sources, id, Format::Human)?;
+            }
+
+            return Ok(());
+        }
+
+        let (tokens, _) = lexer::tokenize(&text);
+        let fixities = self.fixities(&tokens);
+        self.define(&text, fixities, id)
+    }
+
+    // whatever this entry declares, on top of everything declared before it
+    fn fixities(&self, tokens: &[Token]) -> Vec<Fixity> {
+        let mut fixities = parser::declared_fixities(tokens);
+
+        for fixity in &self.fixities {
+            if !fixities.iter().any(|f| f.operator == fixity.operator) {
+                fixities.push(fixity.clone());
+            }
+        }
+
+        parser::with_defaults(fixities)
+    }
+
+    fn type_of_name(&self, name: &str) -> Type {
+        let src = ArcStr::from(self.src.as_str());
+        let (root, _) = crate::parse(&src);
+        let analysis = Analysis::new(&root);
+
+        analysis
+            .top_level
+            .iter()
+            .rev()
+            .map(|i| &analysis.symbols[*i])
+            .find(|symbol| symbol.name.text() == name)
+            .and_then(|symbol| symbol.ty.clone())
+            .unwrap_or(Type::Unknown)
+    }
+
+    // an expression's type is whatever a definition of it would get
+    fn type_of_expr(&self, input: &str) -> Type {
+        let src = ArcStr::from(format!("{}{IT}{input}", self.src));
+        let (root, _) = crate::parse(&src);
+        let analysis = Analysis::new(&root);
+
+        analysis
+            .top_level
+            .last()
+            .and_then(|i| analysis.symbols[*i].ty.clone())
+            .unwrap_or(Type::Unknown)
+    }
+
+    // errors point into the session as a whole. ones in what was just typed or loaded are shown against
+    // that, leaving out whatever they point at from before it. the rest are in definitions from earlier
+    // on, which only get shown as one whole once something's gone wrong in them
+    fn report(&mut self, diagnostic: Diagnostic, session: &ArcStr, at: Input) -> Result<()> {
+        let Some(range) = at.within(&diagnostic.range) else {
+            let file = self.sources.set("<session>", session.clone());
+            return diagnostic.report(&self.sources, file, Format::Human);
+        };
+
+        let diagnostic = Diagnostic {
+            range,
+            secondary: diagnostic
+                .secondary
+                .into_iter()
+                .filter_map(|(range, label)| Some((at.within(&range)?, label)))
+                .collect(),
+            suggestions: diagnostic
+                .suggestions
+                .into_iter()
+                .filter_map(|s| {
+                    Some(Suggestion {
+                        range: at.within(&s.range)?,
+                        ..s
+                    })
+                })
+                .collect(),
+            ..diagnostic
+        };
+
+        diagnostic.report(&self.sources, at.file, Format::Human)
+    }
+
+    fn report_error(&mut self, e: &RuntimeError, at: Input) -> Result<()> {
+        self.report(e.diagnostic(), e.span.parent(), at)
+    }
+}
+
+impl Input {
+    // `range` in the session as a range in the file, if any of it's in there
+    fn within(&self, range: &Range<usize>) -> Option<Range<usize>> {
+        (range.end > self.offset || range.start >= self.offset)
+            .then(|| range.start.saturating_sub(self.offset)..range.end - self.offset)
+    }
+}
+
+// the type checker isn't there yet, so when types can't be worked out from the code a value still
+// knows what it is
+fn known_type<F>(ty: Type, value: &Value<F>) -> String {
+    match value.type_name() {
+        Some(name) if !ty.is_known() => name.to_string(),
+        _ => ty.to_string(),
+    }
+}
+
+// `x = ...`, `T | ...`, `f $ ...`, fixity declarations and tests are definitions, anything else is an
+// expression
+fn is_definition(tokens: &[Token]) -> bool {
+    let kinds: Vec<&TokenKind> = tokens.iter().take(4).map(|t| &t.kind).collect();
+    let defines = |kind: &TokenKind| {
+        matches!(
+            kind,
+            TokenKind::Equal | TokenKind::Pipe | TokenKind::DollarSign
+        )
+    };
+
+    match kinds.as_slice() {
+        [TokenKind::Infixl | TokenKind::Infixr | TokenKind::Test, ..] => true,
+        [TokenKind::Name, next, ..] => defines(next),
+        [TokenKind::OpenParen, TokenKind::Operator, TokenKind::CloseParen, next] => defines(next),
+        _ => false,
+    }
+}
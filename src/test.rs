@@ -0,0 +1,59 @@
+use crate::{
+    ast::{Ast, Def, Root, TestDef},
+    bytecode::{self, Vm},
+    diagnostic::Code,
+    limits::Limits,
+    value::{RuntimeError, Value},
+};
+
+pub struct Outcome<'a> {
+    pub test: &'a TestDef,
+    pub result: Result<(), RuntimeError>,
+}
+
+pub fn tests(root: &Root) -> impl Iterator<Item = &TestDef> {
+    root.defs.iter().filter_map(|def| match def {
+        Def::Test(test_def) => Some(test_def),
+        _ => None,
+    })
+}
+
+// runs the tests whose names contain `filter`. each gets a VM of its own, so one that fails halfway
+// through working out a definition can't trip up the ones after it
+pub fn run<'a>(
+    file: &str,
+    root: &'a Root,
+    filter: Option<&str>,
+    limits: Limits,
+) -> Result<Vec<Outcome<'a>>, RuntimeError> {
+    let (program, functions) = bytecode::compile_tests(file, root)?;
+
+    Ok(tests(root)
+        .zip(functions)
+        .filter(|(test, _)| filter.is_none_or(|filter| test.name.contains(filter)))
+        .map(|(test, function)| Outcome {
+            test,
+            result: function
+                .and_then(|function| check(test, Vm::with_limits(&program, limits).eval(function))),
+        })
+        .collect())
+}
+
+// a test passes when it comes out `true`
+pub fn check<F>(test: &TestDef, value: Result<Value<F>, RuntimeError>) -> Result<(), RuntimeError> {
+    match value? {
+        Value::Bool(true) => Ok(()),
+        Value::Bool(false) => Err(RuntimeError::new(
+            Code::AssertionFailed,
+            format!("test `{}` came out false", test.name),
+            test.body.text(),
+            "this is false",
+        )),
+        value => Err(RuntimeError::new(
+            Code::InvalidOperands,
+            format!("a test has to come out as a `Bool`, not `{}`", value.kind()),
+            test.body.text(),
+            "this is what the test comes out as",
+        )),
+    }
+}
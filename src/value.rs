@@ -0,0 +1,459 @@
+use std::{fmt::Display, mem::size_of, ops::Range, rc::Rc};
+
+use arcstr::Substr;
+
+use crate::{
+    ast::{Ast, Name},
+    diagnostic::{Code, Diagnostic, Format, Severity, SourceMap},
+    host::HostFunction,
+};
+
+// how many places a stack trace shows
+const TRACE_LENGTH: usize = 8;
+
+// the built-ins every program can use without defining them. anything defined with the same name
+// shadows them
+pub const BUILTINS: &[(&str, Builtin)] = &[
+    ("+", Builtin::Add),
+    ("-", Builtin::Sub),
+    ("*", Builtin::Mul),
+    ("/", Builtin::Div),
+    ("%", Builtin::Rem),
+    ("==", Builtin::Eq),
+    ("!=", Builtin::Ne),
+    ("<", Builtin::Lt),
+    ("<=", Builtin::Le),
+    (">", Builtin::Gt),
+    (">=", Builtin::Ge),
+    ("&&", Builtin::And),
+    ("||", Builtin::Or),
+    ("not", Builtin::Not),
+    ("assert", Builtin::Assert),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    And,
+    Or,
+    Not,
+    Assert,
+}
+
+// what a program works out to, whichever backend runs it. `F` is how that backend represents the
+// functions a program defines
+#[derive(Clone)]
+pub enum Value<F> {
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    Record(Rc<Record<F>>),
+    Function(F),
+    // a built-in and whatever it's been given so far
+    Builtin(Builtin, Vec<Value<F>>),
+    // the same for a type's name, which builds a record once it's been given every field
+    Constructor(Rc<Constructor>, Vec<Value<F>>),
+    // and for a function the program embedding caelis provides
+    Host(Rc<HostFunction>, Vec<Value<F>>),
+}
+
+pub struct Record<F> {
+    pub ty: String,
+    pub fields: Vec<(String, Value<F>)>,
+}
+
+pub struct Constructor {
+    pub ty: String,
+    pub fields: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RuntimeError {
+    pub code: Code,
+    pub message: String,
+    pub span: Substr,
+    pub label: String,
+    // the calls, and the definitions being worked out, that it happened in. innermost first
+    pub trace: Vec<Substr>,
+}
+
+impl Builtin {
+    pub fn name(self) -> &'static str {
+        BUILTINS
+            .iter()
+            .find(|(_, builtin)| *builtin == self)
+            .map(|(name, _)| *name)
+            .unwrap_or_default()
+    }
+
+    pub fn arity(self) -> usize {
+        match self {
+            Builtin::Not | Builtin::Assert => 1,
+            _ => 2,
+        }
+    }
+
+    // `&&` and `||` get both sides already worked out, there's nothing to short-circuit in a language
+    // without side effects
+    pub fn run<F>(self, args: &[Value<F>], text: &Substr) -> Result<Value<F>, RuntimeError> {
+        let value = match (self, args) {
+            (Builtin::Not, [Value::Bool(a)]) => Some(Value::Bool(!a)),
+            // gives back `true` so checks can be chained with `&&`
+            (Builtin::Assert, [Value::Bool(true)]) => Some(Value::Bool(true)),
+            (Builtin::Assert, [Value::Bool(false)]) => {
+                return Err(RuntimeError::new(
+                    Code::AssertionFailed,
+                    "assertion failed",
+                    text,
+                    "this is false",
+                ))
+            }
+            (Builtin::And, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(*a && *b)),
+            (Builtin::Or, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(*a || *b)),
+            (Builtin::Eq, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(a == b)),
+            (Builtin::Ne, [Value::Bool(a), Value::Bool(b)]) => Some(Value::Bool(a != b)),
+            (Builtin::Eq, [Value::Record(a), Value::Record(b)]) => a.equals(b).map(Value::Bool),
+            (Builtin::Ne, [Value::Record(a), Value::Record(b)]) => {
+                a.equals(b).map(|equal| Value::Bool(!equal))
+            }
+            (Builtin::Div | Builtin::Rem, [Value::Int(_), Value::Int(0)]) => {
+                return Err(RuntimeError::new(
+                    Code::DivisionByZero,
+                    "division by zero",
+                    text,
+                    "the right-hand side of this is 0",
+                ))
+            }
+            (_, [Value::Int(a), Value::Int(b)]) => self.int(*a, *b),
+            (_, [Value::Float(a), Value::Float(b)]) => self.float(*a, *b),
+            _ => None,
+        };
+
+        value.ok_or_else(|| {
+            let kinds: Vec<String> = args.iter().map(|arg| format!("`{}`", arg.kind())).collect();
+
+            RuntimeError::new(
+                Code::InvalidOperands,
+                format!("`{}` can't be used on {}", self.name(), kinds.join(" and ")),
+                text,
+                "these don't go together",
+            )
+        })
+    }
+
+    // ints wrap around like the machine ones they'll be compiled to
+    fn int<F>(self, a: i64, b: i64) -> Option<Value<F>> {
+        Some(match self {
+            Builtin::Add => Value::Int(a.wrapping_add(b)),
+            Builtin::Sub => Value::Int(a.wrapping_sub(b)),
+            Builtin::Mul => Value::Int(a.wrapping_mul(b)),
+            Builtin::Div => Value::Int(a.wrapping_div(b)),
+            Builtin::Rem => Value::Int(a.wrapping_rem(b)),
+            Builtin::Eq => Value::Bool(a == b),
+            Builtin::Ne => Value::Bool(a != b),
+            Builtin::Lt => Value::Bool(a < b),
+            Builtin::Le => Value::Bool(a <= b),
+            Builtin::Gt => Value::Bool(a > b),
+            Builtin::Ge => Value::Bool(a >= b),
+            Builtin::And | Builtin::Or | Builtin::Not | Builtin::Assert => return None,
+        })
+    }
+
+    fn float<F>(self, a: f64, b: f64) -> Option<Value<F>> {
+        Some(match self {
+            Builtin::Add => Value::Float(a + b),
+            Builtin::Sub => Value::Float(a - b),
+            Builtin::Mul => Value::Float(a * b),
+            Builtin::Div => Value::Float(a / b),
+            Builtin::Rem => Value::Float(a % b),
+            Builtin::Eq => Value::Bool(a == b),
+            Builtin::Ne => Value::Bool(a != b),
+            Builtin::Lt => Value::Bool(a < b),
+            Builtin::Le => Value::Bool(a <= b),
+            Builtin::Gt => Value::Bool(a > b),
+            Builtin::Ge => Value::Bool(a >= b),
+            Builtin::And | Builtin::Or | Builtin::Not | Builtin::Assert => return None,
+        })
+    }
+}
+
+impl<F> Value<F> {
+    // functions don't have a type until there's a type checker to give them one
+    pub fn type_name(&self) -> Option<&str> {
+        match self {
+            Value::Int(_) => Some("Int"),
+            Value::Float(_) => Some("Float"),
+            Value::Bool(_) => Some("Bool"),
+            Value::Record(record) => Some(&record.ty),
+            Value::Function(_) | Value::Builtin(..) | Value::Constructor(..) | Value::Host(..) => {
+                None
+            }
+        }
+    }
+
+    pub fn kind(&self) -> &str {
+        self.type_name().unwrap_or("function")
+    }
+
+    // roughly how much memory a value a built-in or a constructor gives back takes up, beyond what's
+    // counted for it where it's kept
+    pub fn footprint(&self) -> usize {
+        match self {
+            Value::Record(record) => {
+                size_of::<Record<F>>() + record.fields.len() * size_of::<(String, Value<F>)>()
+            }
+            Value::Builtin(_, args) | Value::Constructor(_, args) | Value::Host(_, args) => {
+                args.len() * size_of::<Value<F>>()
+            }
+            Value::Int(_) | Value::Float(_) | Value::Bool(_) | Value::Function(_) => 0,
+        }
+    }
+
+    // calls anything but a function the program defined, which every backend does the same way.
+    // `call` is the whole call and `func` the part that's being called
+    pub fn apply(
+        self,
+        arg: Value<F>,
+        call: &Substr,
+        func: &Substr,
+    ) -> Result<Value<F>, RuntimeError> {
+        match self {
+            Value::Builtin(builtin, mut args) => {
+                args.push(arg);
+
+                if args.len() < builtin.arity() {
+                    Ok(Value::Builtin(builtin, args))
+                } else {
+                    builtin.run(&args, call)
+                }
+            }
+            Value::Constructor(constructor, mut args) => {
+                args.push(arg);
+
+                if args.len() < constructor.fields.len() {
+                    Ok(Value::Constructor(constructor, args))
+                } else {
+                    Ok(Value::Record(Rc::new(Record {
+                        ty: constructor.ty.clone(),
+                        fields: constructor.fields.iter().cloned().zip(args).collect(),
+                    })))
+                }
+            }
+            Value::Host(function, mut args) => {
+                args.push(arg);
+
+                if args.len() < function.arity() {
+                    Ok(Value::Host(function, args))
+                } else {
+                    function.run(&args, call)
+                }
+            }
+            value => Err(RuntimeError::new(
+                Code::NotAFunction,
+                format!("`{value}` isn't a function"),
+                func,
+                "this is called like a function",
+            )),
+        }
+    }
+
+    // `record` is where the value came from and `field` the name after the `.`
+    pub fn field(self, field: &Substr, record: &Substr) -> Result<Value<F>, RuntimeError>
+    where
+        F: Clone,
+    {
+        match self {
+            Value::Record(value) => value
+                .fields
+                .iter()
+                .find(|(name, _)| name == field.as_str())
+                .map(|(_, value)| value.clone())
+                .ok_or_else(|| {
+                    RuntimeError::new(
+                        Code::NoSuchField,
+                        format!("`{}` has no field `{field}`", value.ty),
+                        field,
+                        format!("not a field of `{}`", value.ty),
+                    )
+                }),
+            value => Err(RuntimeError::new(
+                Code::NoSuchField,
+                format!("`{value}` has no fields"),
+                record,
+                format!("this is `{}`, not a record", value.kind()),
+            )),
+        }
+    }
+
+    // which branch an `if` takes, `text` being its condition
+    pub fn condition(&self, text: &Substr) -> Result<bool, RuntimeError> {
+        match self {
+            Value::Bool(value) => Ok(*value),
+            value => Err(RuntimeError::new(
+                Code::InvalidOperands,
+                format!("`if` needs a `Bool`, not `{}`", value.kind()),
+                text,
+                "this decides which branch is taken",
+            )),
+        }
+    }
+}
+
+impl<F> Record<F> {
+    // records are equal when every field is, which can't be said of records holding functions
+    fn equals(&self, other: &Record<F>) -> Option<bool> {
+        if self.ty != other.ty {
+            return Some(false);
+        }
+
+        self.fields
+            .iter()
+            .zip(&other.fields)
+            .try_fold(true, |equal, ((_, a), (_, b))| {
+                let field = match (a, b) {
+                    (Value::Int(a), Value::Int(b)) => a == b,
+                    (Value::Float(a), Value::Float(b)) => a == b,
+                    (Value::Bool(a), Value::Bool(b)) => a == b,
+                    (Value::Record(a), Value::Record(b)) => a.equals(b)?,
+                    _ => return None,
+                };
+
+                Some(equal && field)
+            })
+    }
+}
+
+impl Constructor {
+    // a type with no fields is just its one value
+    pub fn value<F>(ty: String, fields: Vec<String>) -> Value<F> {
+        match fields.is_empty() {
+            true => Value::Record(Rc::new(Record {
+                ty,
+                fields: Vec::new(),
+            })),
+            false => Value::Constructor(Rc::new(Constructor { ty, fields }), Vec::new()),
+        }
+    }
+}
+
+impl<F> Display for Value<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{value}"),
+            // `{:?}` keeps the `.0` on whole numbers
+            Value::Float(value) => write!(f, "{value:?}"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Record(record) => {
+                let fields: Vec<String> = record
+                    .fields
+                    .iter()
+                    .map(|(name, value)| format!("{name} = {value}"))
+                    .collect();
+
+                match fields.is_empty() {
+                    true => write!(f, "{}", record.ty),
+                    false => write!(f, "{} {{ {} }}", record.ty, fields.join(", ")),
+                }
+            }
+            Value::Function(_) => write!(f, "<function>"),
+            Value::Builtin(builtin, _) => write!(f, "<built-in ({})>", builtin.name()),
+            Value::Constructor(constructor, _) => write!(f, "<constructor of {}>", constructor.ty),
+            Value::Host(function, _) => write!(f, "<host function ({})>", function.name),
+        }
+    }
+}
+
+impl RuntimeError {
+    pub fn new(code: Code, message: impl ToString, span: &Substr, label: impl ToString) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            span: span.clone(),
+            label: label.to_string(),
+            trace: Vec::new(),
+        }
+    }
+
+    // `text` is a call or a definition that was in progress when it happened
+    pub fn called_from(mut self, text: &Substr) -> Self {
+        self.trace.push(text.clone());
+        self
+    }
+
+    pub fn unbound(name: &Name) -> Self {
+        Self::new(
+            Code::UnboundName,
+            format!("`{}` isn't defined", name.text()),
+            name.text(),
+            "nothing with this name is in scope",
+        )
+    }
+
+    pub fn unparsed(text: &Substr) -> Self {
+        Self::new(
+            Code::UnexpectedToken,
+            "this can't run until it parses",
+            text,
+            "this didn't parse",
+        )
+    }
+
+    // the error's text is enough to find the file it's in. text from nowhere the map knows about still
+    // gets shown, just without a file name
+    pub fn report(&self, sources: &SourceMap, format: Format) -> anyhow::Result<()> {
+        match sources.find(&self.span) {
+            Some(file) => self.diagnostic().report(sources, file, format),
+            None => {
+                let mut sources = SourceMap::default();
+                let file = sources.add("<unknown>", self.span.parent().clone());
+
+                self.diagnostic().report(&sources, file, format)
+            }
+        }
+    }
+
+    // the trace as labels, each place once. recursion can go through the same few thousands of times,
+    // and only the innermost places are shown
+    fn stack_trace(&self) -> Vec<(Range<usize>, String)> {
+        let mut places: Vec<(Range<usize>, usize)> = Vec::new();
+
+        for text in &self.trace {
+            match places.iter_mut().find(|(range, _)| *range == text.range()) {
+                Some((_, count)) => *count += 1,
+                None => places.push((text.range(), 1)),
+            }
+        }
+
+        places
+            .into_iter()
+            .take(TRACE_LENGTH)
+            .map(|(range, count)| match count {
+                1 => (range, "while working this out".to_string()),
+                count => (range, format!("while working this out, {count} times over")),
+            })
+            .collect()
+    }
+
+    // the range is into `span.parent()`, which might not be the file that's being run
+    pub fn diagnostic(&self) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            code: self.code,
+            message: self.message.clone(),
+            range: self.span.range(),
+            label: self.label.clone(),
+            secondary: self.stack_trace(),
+            suggestions: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,45 @@
+// a `.caeb` file is checked before it runs, so one that's been damaged gets turned away instead of
+// taking the VM down with it
+use std::{fs, path::Path, process::Command};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+#[test]
+fn damaged_bytecode_is_rejected() {
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("bytecode");
+    fs::create_dir_all(&out).unwrap();
+
+    let caeb = out.join("fib.caeb");
+    let built = Command::new(CAELIS)
+        .args(["build", "-o"])
+        .arg(&caeb)
+        .arg(Path::new(env!("CARGO_MANIFEST_DIR")).join("test/programs/fib.cae"))
+        .status()
+        .unwrap();
+    assert!(built.success());
+
+    let bytes = fs::read(&caeb).unwrap();
+    let damaged = out.join("damaged.caeb");
+    let mut failures = Vec::new();
+
+    // every byte flipped in turn. whatever that does to the program, it either runs, within limits,
+    // or gets reported as an error
+    for i in 0..bytes.len() {
+        let mut bytes = bytes.clone();
+        bytes[i] ^= 0xff;
+        fs::write(&damaged, &bytes).unwrap();
+
+        let output = Command::new(CAELIS)
+            .args(["run", "--fuel", "1000000", "--timeout", "5"])
+            .arg(&damaged)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        if !matches!(output.status.code(), Some(0 | 1)) || stderr.contains("[ICE]") {
+            failures.push(format!("byte {i}: {}\n{stderr}", output.status));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,85 @@
+// every program in test/programs has to print the same and exit the same way when it's compiled to C
+// as when the VM runs it. this needs a C compiler, and is skipped when there isn't one
+use std::{fs, path::Path, process::Command};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+#[test]
+fn c_agrees_with_the_vm() {
+    if Command::new("cc").arg("--version").output().is_err() {
+        eprintln!("skipping, there's no `cc` to compile the C with");
+        return;
+    }
+
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/programs");
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("c_backend");
+    fs::create_dir_all(&out).unwrap();
+
+    let mut files: Vec<_> = fs::read_dir(&programs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "cae"))
+        .collect();
+    files.sort();
+
+    let mut failures = Vec::new();
+
+    for file in &files {
+        let name = file.file_stem().unwrap().to_string_lossy();
+        let c = out.join(format!("{name}.c"));
+        let exe = out.join(&*name);
+
+        let expected = Command::new(CAELIS).arg("run").arg(file).output().unwrap();
+
+        let built = Command::new(CAELIS)
+            .args(["build", "--emit", "c", "-o"])
+            .arg(&c)
+            .arg(file)
+            .status()
+            .unwrap();
+        assert!(built.success(), "`{name}` didn't compile to C");
+
+        let compiled = Command::new("cc")
+            .args(["-std=c99", "-O2", "-o"])
+            .arg(&exe)
+            .arg(&c)
+            .arg("-lm")
+            .output()
+            .unwrap();
+        assert!(
+            compiled.status.success(),
+            "the C for `{name}` didn't compile:\n{}",
+            String::from_utf8_lossy(&compiled.stderr)
+        );
+
+        let actual = Command::new(&exe).output().unwrap();
+
+        // errors are reported differently, but they should at least start with the same line
+        let agrees = if expected.status.success() {
+            actual.stdout == expected.stdout
+        } else {
+            let first_line = |output: &[u8]| {
+                String::from_utf8_lossy(output)
+                    .lines()
+                    .next()
+                    .unwrap_or_default()
+                    .to_string()
+            };
+
+            first_line(&actual.stderr) == first_line(&expected.stdout)
+        };
+
+        if !agrees || actual.status.code() != expected.status.code() {
+            failures.push(format!(
+                "{name}: the VM printed {:?} and exited with {:?}, the C printed {:?} and {:?} and exited with {:?}",
+                String::from_utf8_lossy(&expected.stdout),
+                expected.status.code(),
+                String::from_utf8_lossy(&actual.stdout),
+                String::from_utf8_lossy(&actual.stderr),
+                actual.status.code(),
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,77 @@
+// the syntax tree prints back as exactly the source it came from, whatever state that source is in
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use arcstr::ArcStr;
+use caelis::cst::{SyntaxElement, SyntaxKind, SyntaxNode};
+
+fn round_trip(src: &str) -> SyntaxNode {
+    let src = ArcStr::from(src);
+    let (tree, _) = caelis::syntax_tree(&src);
+    assert_eq!(
+        tree.to_string(),
+        src.as_str(),
+        "the tree for {src:?} isn't lossless"
+    );
+
+    tree
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            walk(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "cae") {
+            files.push(path);
+        }
+    }
+}
+
+fn has_error(node: &SyntaxNode) -> bool {
+    node.kind == SyntaxKind::Error
+        || node.children.iter().any(|child| match child {
+            SyntaxElement::Node(node) => has_error(node),
+            SyntaxElement::Token(_) => false,
+        })
+}
+
+#[test]
+fn every_file_round_trips() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut files = Vec::new();
+    walk(&root.join("test"), &mut files);
+    walk(&root.join("tests"), &mut files);
+    assert!(!files.is_empty());
+
+    for file in files {
+        round_trip(&fs::read_to_string(&file).unwrap());
+    }
+}
+
+#[test]
+fn nothing_but_trivia() {
+    for src in ["", "   \n\n", "# a comment", "# one\n\n  # two\n"] {
+        let tree = round_trip(src);
+        assert_eq!(tree.nodes().count(), 0, "{src:?} has definitions");
+    }
+}
+
+#[test]
+fn broken_input_is_kept() {
+    for src in [
+        "main = (1 + ;\nf = 2;\n",
+        "main = 1 +\n",
+        "main = (x +) 2; # after\n",
+        "f = \"never closed\n",
+        "main = 1 § 2;\n",
+        ")(\n",
+        "let in = ;;",
+    ] {
+        let tree = round_trip(src);
+        assert!(has_error(&tree), "nothing in {src:?} is an error");
+    }
+}
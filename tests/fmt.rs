@@ -0,0 +1,122 @@
+// `caelis fmt` on copies of files: every `.cae` under tests/fmt/ has to come out as the `.expected` file
+// next to it, formatting has to be idempotent, and `--check` has to fail exactly when something would change.
+// `CAELIS_BLESS=1 cargo test --test fmt` writes what caelis does now as the expectation instead
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, ExitStatus},
+};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn files(dir: &str, extension: &str) -> Vec<PathBuf> {
+    let mut files: Vec<_> = fs::read_dir(root().join(dir))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    files
+}
+
+// a copy of `src` to format, so the fixtures are never touched
+fn scratch(name: &str, src: &str) -> PathBuf {
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("fmt-{name}"));
+    fs::write(&file, src).unwrap();
+    file
+}
+
+fn fmt(file: &Path, check: bool) -> ExitStatus {
+    let mut command = Command::new(CAELIS);
+    command.arg("fmt");
+    if check {
+        command.arg("--check");
+    }
+
+    command.arg(file).output().unwrap().status
+}
+
+// what `caelis fmt` turns `src` into
+fn formatted(name: &str, src: &str) -> String {
+    let file = scratch(name, src);
+    assert!(fmt(&file, false).success(), "couldn't format {name}");
+    fs::read_to_string(file).unwrap()
+}
+
+#[test]
+fn expected() {
+    let bless = std::env::var_os("CAELIS_BLESS").is_some_and(|bless| bless != "0");
+    let mut failures = Vec::new();
+
+    for file in files("tests/fmt", "cae") {
+        let name = file.file_name().unwrap().to_string_lossy();
+        let actual = formatted(&name, &fs::read_to_string(&file).unwrap());
+        let expected_file = file.with_extension("expected");
+
+        if bless {
+            fs::write(&expected_file, actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&expected_file) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{name} should be formatted as\n{expected}\nbut is\n{actual}"
+            )),
+            Err(_) => failures.push(format!("{name} has no `.expected` file")),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nif caelis is right, run `CAELIS_BLESS=1 cargo test --test fmt` to update the expectations",
+        failures.join("\n\n")
+    );
+}
+
+#[test]
+fn idempotent() {
+    let mut files = files("tests/fmt", "cae");
+    files.extend(self::files("test", "cae"));
+    files.extend(self::files("test/programs", "cae"));
+    files.extend(self::files("test/errors", "cae"));
+
+    for file in files {
+        let src = fs::read_to_string(&file).unwrap();
+
+        // a file that doesn't parse can't be formatted at all
+        let name = file.file_name().unwrap().to_string_lossy();
+        let copy = scratch(&name, &src);
+        if !fmt(&copy, false).success() {
+            continue;
+        }
+
+        let once = fs::read_to_string(&copy).unwrap();
+        let twice = formatted(&name, &once);
+        assert_eq!(once, twice, "formatting {name} again changed it");
+    }
+}
+
+#[test]
+fn check() {
+    let src = "main = 1+2;\n";
+    let file = scratch("check.cae", src);
+
+    assert_eq!(fmt(&file, true).code(), Some(1));
+    assert_eq!(
+        fs::read_to_string(&file).unwrap(),
+        src,
+        "--check wrote the file"
+    );
+
+    assert!(fmt(&file, false).success());
+    assert_eq!(fmt(&file, true).code(), Some(0));
+
+    // so does a file that doesn't parse
+    let broken = scratch("broken.cae", "main = 1 +;\n");
+    assert_eq!(fmt(&broken, true).code(), Some(1));
+}
@@ -0,0 +1,169 @@
+// checks what caelis makes of every `.cae` file under test/ against the `.expected` file next to it,
+// all of it what someone using caelis would see: the syntax tree as JSON, the file formatted or why it
+// can't be, and what running it and its tests prints, with the diagnostics both as text and as JSON.
+// `CAELIS_BLESS=1 cargo test --test golden` writes what caelis does now as the expectation instead
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+#[test]
+fn golden() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let bless = std::env::var_os("CAELIS_BLESS").is_some_and(|bless| bless != "0");
+
+    let mut files = Vec::new();
+    walk(&root.join("test"), &mut files);
+    files.sort();
+
+    let mut failures = Vec::new();
+
+    for file in &files {
+        let expected_file = file.with_extension("expected");
+
+        if file.extension().is_some_and(|ext| ext == "expected") {
+            if !file.with_extension("cae").exists() {
+                if bless {
+                    fs::remove_file(file).unwrap();
+                } else {
+                    failures.push(format!("{} has no `.cae` file", relative(root, file)));
+                }
+            }
+            continue;
+        } else if file.extension().is_none_or(|ext| ext != "cae") {
+            continue;
+        }
+
+        // paths are relative so they come out the same in diagnostics wherever the repo is
+        let path = relative(root, file);
+        let mut actual = section(
+            &["parse", "--format", "json", &path],
+            &caelis(root, &["parse", "--format", "json", &path]),
+        );
+        actual += &formatted(root, &path);
+        actual += &[
+            vec!["run", &path],
+            vec!["run", "--error-format", "json", &path],
+            vec!["test", &path],
+        ]
+        .iter()
+        .map(|args| section(args, &caelis(root, args)))
+        .collect::<String>();
+
+        if bless {
+            fs::write(&expected_file, actual).unwrap();
+            continue;
+        }
+
+        match fs::read_to_string(&expected_file) {
+            Ok(expected) if expected == actual => {}
+            Ok(expected) => failures.push(format!(
+                "{path} doesn't match {}:\n{}",
+                relative(root, &expected_file),
+                diff(&expected, &actual)
+            )),
+            Err(_) => failures.push(format!("{path} has no `.expected` file")),
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{}\n\nif caelis is right, run `CAELIS_BLESS=1 cargo test --test golden` to update the expectations",
+        failures.join("\n\n")
+    );
+}
+
+// `caelis fmt` on a copy of the file at the same relative path, so the fixture is never touched and
+// diagnostics name it the same, then the file as it was left if it could be formatted
+fn formatted(root: &Path, path: &str) -> String {
+    let scratch = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    let copy = scratch.join(path);
+    fs::create_dir_all(copy.parent().unwrap()).unwrap();
+    fs::copy(root.join(path), &copy).unwrap();
+
+    let args = ["fmt", path];
+    let output = caelis(&scratch, &args);
+    let mut section = section(&args, &output);
+
+    if output.status.success() {
+        section += "-- formatted\n";
+        section += &fs::read_to_string(copy).unwrap();
+    }
+
+    section
+}
+
+fn caelis(dir: &Path, args: &[&str]) -> Output {
+    Command::new(CAELIS)
+        .args(args)
+        .current_dir(dir)
+        .env("RUST_BACKTRACE", "0")
+        .output()
+        .unwrap()
+}
+
+// caelis's output, headed with the command and how it exited
+fn section(args: &[&str], output: &Output) -> String {
+    let mut section = format!(
+        "== caelis {} (exit {})\n",
+        args.join(" "),
+        output
+            .status
+            .code()
+            .map_or("signal".to_string(), |code| code.to_string())
+    );
+
+    section += &String::from_utf8_lossy(&output.stdout);
+
+    if !output.stderr.is_empty() {
+        section += "-- stderr\n";
+        section += &String::from_utf8_lossy(&output.stderr);
+    }
+
+    section
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+
+        if path.is_dir() {
+            walk(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
+fn relative(root: &Path, file: &Path) -> String {
+    file.strip_prefix(root)
+        .unwrap()
+        .to_string_lossy()
+        .into_owned()
+}
+
+// the lines that differ, which is enough to see what changed without a diffing library
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<_> = expected.lines().collect();
+    let actual: Vec<_> = actual.lines().collect();
+    let mut out = String::new();
+
+    for line in 0..expected.len().max(actual.len()) {
+        match (expected.get(line), actual.get(line)) {
+            (Some(expected), Some(actual)) if expected == actual => {}
+            (expected, actual) => {
+                if let Some(expected) = expected {
+                    out += &format!("{:>4} - {expected}\n", line + 1);
+                }
+                if let Some(actual) = actual {
+                    out += &format!("{:>4} + {actual}\n", line + 1);
+                }
+            }
+        }
+    }
+
+    out
+}
@@ -0,0 +1,123 @@
+// caelis as a rules language inside a rust program, calling rust and being called from it
+use arcstr::ArcStr;
+use caelis::{
+    host::{Host, Value},
+    Code, Error,
+};
+
+const RULES: &str = "
+Limits | low :Int, high :Int;
+
+limits = Limits 0 (threshold 10);
+score = n :Int -> clamp (n * 3) limits.low limits.high;
+pass = n :Int -> score n >= 20;
+";
+
+fn host() -> Host {
+    let mut host = Host::new();
+
+    host.register("clamp", "Int -> Int -> Int -> Int", |args| match args {
+        [Value::Int(n), Value::Int(low), Value::Int(high)] => Ok(Value::Int(*n.clamp(low, high))),
+        _ => unreachable!("the signature is checked before the function is called"),
+    })
+    .unwrap();
+    host.register("threshold", "Int -> Int", |args| match args {
+        [Value::Int(n)] if *n > 0 => Ok(Value::Int(n * 3)),
+        _ => Err("the threshold has to be positive".to_string()),
+    })
+    .unwrap();
+
+    host
+}
+
+#[test]
+fn calls_both_ways() {
+    let script = host().load("rules.cae", &ArcStr::from(RULES)).unwrap();
+
+    assert_eq!(
+        script.call("score", &[Value::Int(4)]).unwrap(),
+        Value::Int(12)
+    );
+    assert_eq!(
+        script.call("score", &[Value::Int(40)]).unwrap(),
+        Value::Int(30)
+    );
+    assert_eq!(
+        script.call("pass", &[Value::Int(7)]).unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        script.call("limits", &[]).unwrap(),
+        Value::Record(
+            "Limits".to_string(),
+            vec![
+                ("low".to_string(), Value::Int(0)),
+                ("high".to_string(), Value::Int(30)),
+            ]
+        )
+    );
+}
+
+#[test]
+fn host_errors() {
+    let mut host = host();
+    let src = ArcStr::from("wrong = clamp 1.5 0 1;\nfails = threshold 0;\nbad = odd 1;\n");
+
+    host.register("odd", "Int -> Bool", |_| Ok(Value::Int(1)))
+        .unwrap();
+    let script = host.load("errors.cae", &src).unwrap();
+
+    let code = |name| match script.call(name, &[]) {
+        Err(Error::Diagnostics(diagnostics)) => diagnostics[0].code,
+        _ => panic!("`{name}` didn't fail"),
+    };
+    assert_eq!(code("wrong"), Code::InvalidOperands);
+    assert_eq!(code("fails"), Code::HostFunctionFailed);
+    assert_eq!(code("bad"), Code::HostFunctionFailed);
+
+    assert!(matches!(script.call("missing", &[]), Err(Error::Other(_))));
+}
+
+#[test]
+fn signatures() {
+    let mut host = Host::new();
+
+    assert!(matches!(
+        host.register("f", "Int ->", |_| Ok(Value::Int(0))),
+        Err(Error::Diagnostics(_))
+    ));
+    assert!(matches!(
+        host.register("f", "Int", |_| Ok(Value::Int(0))),
+        Err(Error::Other(_))
+    ));
+    assert!(matches!(
+        host.register("f", "(Int -> Int) -> Int", |_| Ok(Value::Int(0))),
+        Err(Error::Other(_))
+    ));
+
+    // only the built-in types are known, and none of them take arguments
+    let codes =
+        |host: &mut Host, signature| match host.register("f", signature, |_| Ok(Value::Int(0))) {
+            Err(Error::Diagnostics(diagnostics)) => diagnostics
+                .iter()
+                .map(|diagnostic| (diagnostic.code, &signature[diagnostic.range.clone()]))
+                .collect::<Vec<_>>(),
+            _ => panic!("`{signature}` was registered"),
+        };
+    assert_eq!(codes(&mut host, "Int -> Itn"), [(Code::UnknownType, "Itn")]);
+    assert_eq!(
+        codes(&mut host, "Limits -> Bool"),
+        [(Code::UnknownType, "Limits")]
+    );
+    assert_eq!(
+        codes(&mut host, "Int Bool -> Int"),
+        [(Code::WrongTypeArguments, "Int Bool")]
+    );
+
+    // without the function registered, the name isn't defined
+    let src = ArcStr::from("main = clamp 1 2 3;\n");
+    match host.load("main.cae", &src) {
+        Err(Error::Diagnostics(diagnostics)) => assert_eq!(diagnostics[0].code, Code::UnboundName),
+        _ => panic!("`clamp` was defined"),
+    }
+}
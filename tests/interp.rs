@@ -0,0 +1,57 @@
+// every program in test/programs has to print exactly the same, errors and all, and exit the same way
+// under the interpreter as when the VM runs it
+use std::{
+    fs,
+    path::Path,
+    process::{Command, Output},
+};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+fn run(file: &Path, interp: bool) -> Output {
+    let mut command = Command::new(CAELIS);
+    command.arg("run");
+    if interp {
+        command.arg("--interp");
+    }
+
+    command.arg(file).output().unwrap()
+}
+
+#[test]
+fn interpreter_agrees_with_the_vm() {
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/programs");
+
+    let mut files: Vec<_> = fs::read_dir(&programs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "cae"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty());
+
+    let mut failures = Vec::new();
+
+    for file in &files {
+        let name = file.file_stem().unwrap().to_string_lossy();
+        let expected = run(file, false);
+        let actual = run(file, true);
+
+        if actual.stdout != expected.stdout
+            || actual.stderr != expected.stderr
+            || actual.status.code() != expected.status.code()
+        {
+            failures.push(format!(
+                "{name}: the VM printed\n{}{}and exited with {:?}, the interpreter printed\n{}{}and exited with {:?}",
+                String::from_utf8_lossy(&expected.stdout),
+                String::from_utf8_lossy(&expected.stderr),
+                expected.status.code(),
+                String::from_utf8_lossy(&actual.stdout),
+                String::from_utf8_lossy(&actual.stderr),
+                actual.status.code(),
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
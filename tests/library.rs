@@ -0,0 +1,124 @@
+// the library API, used the way a tool embedding caelis would
+use arcstr::ArcStr;
+use caelis::{ast::Json, Artifact, Code, Config, Emit, Error, Limits, SourceFile, TokenKind};
+
+#[test]
+fn tokenize_and_parse() {
+    let src = ArcStr::from("main = 1 + 2;\n");
+
+    let (tokens, diagnostics) = caelis::tokenize(&src);
+    assert!(diagnostics.is_empty());
+    assert_eq!(tokens[0].kind, TokenKind::Name);
+    assert_eq!(tokens[0].span, "main");
+
+    let (root, diagnostics) = caelis::parse(&src);
+    assert!(diagnostics.is_empty());
+    assert_eq!(root.defs.len(), 1);
+
+    let (_, diagnostics) = caelis::parse(&ArcStr::from("main = 1 +;\n"));
+    assert_eq!(diagnostics[0].code, Code::UnexpectedToken);
+}
+
+#[test]
+fn ast_as_json() {
+    let src = ArcStr::from("double = x :Int -> x * 2;\n");
+    let (root, _) = caelis::parse(&src);
+    let file = SourceFile::new("double.cae", src);
+
+    let json = serde_json::to_value(Json::new(&root, &file)).unwrap();
+    let func = &json["defs"][0]["Value"]["body"]["Func"];
+    assert_eq!(func["param"]["text"], "x");
+    assert_eq!(func["param_type"]["Named"]["name"]["text"], "Int");
+    assert_eq!(func["ret_type"], serde_json::Value::Null);
+    assert_eq!(func["body"]["Call"]["span"]["column"], 20);
+    assert_eq!(func["body"]["Call"]["span"]["end_column"], 25);
+}
+
+#[test]
+fn resolve_finds_unbound_names() {
+    let src = ArcStr::from("main = x :Int -> y;\n");
+    let (root, _) = caelis::parse(&src);
+
+    let (analysis, diagnostics) = caelis::resolve(&root);
+    assert_eq!(analysis.top_level.len(), 1);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, Code::UnboundName);
+    assert_eq!(&src[diagnostics[0].range.clone()], "y");
+}
+
+#[test]
+fn compile_and_run() {
+    let src = ArcStr::from("double = x :Int -> x * 2;\nmain = double 21;\n");
+
+    let Ok(Artifact::Bytecode(program)) = caelis::compile("double.cae", &src, &Config::default())
+    else {
+        panic!("`double.cae` didn't compile to bytecode");
+    };
+    let value = caelis::run(&program, Limits::default()).unwrap();
+    assert_eq!(value.to_string(), "42");
+
+    let config = Config { emit: Emit::C };
+    let Ok(Artifact::C(c)) = caelis::compile("double.cae", &src, &config) else {
+        panic!("`double.cae` didn't compile to C");
+    };
+    assert!(c.contains("double.cae"));
+}
+
+#[test]
+fn compile_errors() {
+    let src = ArcStr::from("main = 1 +;\n");
+    let Err(Error::Diagnostics(diagnostics)) = caelis::compile("bad.cae", &src, &Config::default())
+    else {
+        panic!("`bad.cae` compiled");
+    };
+    assert_eq!(diagnostics[0].code, Code::UnexpectedToken);
+
+    let src = ArcStr::from("double = x :Int -> x * 2;\n");
+    let config = Config { emit: Emit::C };
+    assert!(matches!(
+        caelis::compile("no_main.cae", &src, &config),
+        Err(Error::Other(_))
+    ));
+
+    let Ok(Artifact::Bytecode(program)) = caelis::compile("no_main.cae", &src, &Config::default())
+    else {
+        panic!("`no_main.cae` didn't compile to bytecode");
+    };
+    assert!(matches!(
+        caelis::run(&program, Limits::default()),
+        Err(Error::Other(_))
+    ));
+
+    let src = ArcStr::from("main = 1 / 0;\n");
+    let Ok(Artifact::Bytecode(program)) = caelis::compile("zero.cae", &src, &Config::default())
+    else {
+        panic!("`zero.cae` didn't compile to bytecode");
+    };
+    let Err(Error::Diagnostics(diagnostics)) = caelis::run(&program, Limits::default()) else {
+        panic!("`zero.cae` ran");
+    };
+    assert_eq!(&src[diagnostics[0].range.clone()], "1 / 0");
+}
+
+#[test]
+fn syntax_errors_are_reported_once() {
+    let codes = |src: &str| -> Vec<Code> {
+        let (_, diagnostics) = caelis::parse(&ArcStr::from(src));
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.code)
+            .collect()
+    };
+
+    assert_eq!(codes("main = (1 + 2;\n"), [Code::UnclosedDelimiter]);
+    assert_eq!(codes("main = 1 + 2);\n"), [Code::UnmatchedDelimiter]);
+    assert_eq!(codes("main ` = 1;\n"), [Code::UnexpectedCharacter]);
+    assert_eq!(
+        codes("main = (1 ` 2;\nother = 2 +;\n"),
+        [
+            Code::UnclosedDelimiter,
+            Code::UnexpectedCharacter,
+            Code::UnexpectedToken
+        ]
+    );
+}
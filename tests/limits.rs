@@ -0,0 +1,146 @@
+// running code that can't be trusted to finish, through a host and through the interpreter
+use std::{fs, path::Path, process::Command, time::Duration};
+
+use arcstr::ArcStr;
+use caelis::{
+    host::{Host, Value},
+    Code, Error, Limits,
+};
+
+const PROGRAM: &str = "
+Pair | first :Int, second :Int;
+
+forever = n :Int -> forever (n + 1);
+sum = n :Int -> if n == 0 then 0 else n + sum (n - 1);
+build = n :Int -> if n == 0 then Pair 0 0 else Pair n (build (n - 1)).first;
+";
+
+fn code(limits: Limits, name: &str, arg: i64) -> Code {
+    let mut host = Host::new();
+    host.limit(limits);
+    let script = host.load("limits.cae", &ArcStr::from(PROGRAM)).unwrap();
+
+    match script.call(name, &[Value::Int(arg)]) {
+        Err(Error::Diagnostics(diagnostics)) => diagnostics[0].code,
+        _ => panic!("`{name} {arg}` didn't fail"),
+    }
+}
+
+#[test]
+fn each_limit() {
+    let fuel = Limits {
+        fuel: Some(1000),
+        ..Limits::default()
+    };
+    assert_eq!(code(fuel, "forever", 0), Code::OutOfFuel);
+
+    let depth = Limits {
+        depth: Some(100),
+        ..Limits::default()
+    };
+    assert_eq!(code(depth, "sum", 1000), Code::TooDeep);
+
+    let allocation = Limits {
+        allocation: Some(10_000),
+        ..Limits::default()
+    };
+    assert_eq!(code(allocation, "build", 10_000), Code::OutOfMemory);
+
+    let timeout = Limits {
+        timeout: Some(Duration::from_millis(100)),
+        ..Limits::default()
+    };
+    assert_eq!(code(timeout, "forever", 0), Code::TimedOut);
+}
+
+#[test]
+fn within_limits() {
+    let mut host = Host::new();
+    host.limit(Limits {
+        fuel: Some(100_000),
+        depth: Some(1000),
+        allocation: Some(1 << 20),
+        timeout: Some(Duration::from_secs(10)),
+    });
+    let script = host.load("limits.cae", &ArcStr::from(PROGRAM)).unwrap();
+
+    // every call gets the limits to itself
+    for _ in 0..3 {
+        assert_eq!(
+            script.call("sum", &[Value::Int(100)]).unwrap(),
+            Value::Int(5050)
+        );
+    }
+}
+
+// the interpreter is only reachable through `caelis run --interp`
+#[test]
+fn interpreter() {
+    let file = Path::new(env!("CARGO_TARGET_TMPDIR")).join("limits-interpreter.cae");
+    fs::write(&file, format!("{PROGRAM}main = sum 1000;\n")).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_caelis"))
+        .args([
+            "run",
+            "--interp",
+            "--max-depth",
+            "100",
+            "--error-format",
+            "json",
+        ])
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+
+    let diagnostic: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(diagnostic["code"], "E0410");
+    // the `sum`s inside `main`, and `main` calling the first of them, but neither `main` itself, which
+    // was asked for rather than called, nor the call that was one too many
+    let labels: Vec<_> = diagnostic["labels"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|label| label["message"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        labels,
+        [
+            "while working this out, 98 times over",
+            "while working this out"
+        ]
+    );
+}
+
+#[test]
+fn nesting() {
+    let chain = format!("main = {};\n", vec!["1"; 20_000].join(" + "));
+    let parens = format!("main = {}1{};\n", "(".repeat(20_000), ")".repeat(20_000));
+
+    // reported once, rather than overflowing the stack on the way
+    for src in [chain, parens] {
+        let (_, diagnostics) = caelis::parse(&ArcStr::from(src));
+        let codes: Vec<Code> = diagnostics.iter().map(|d| d.code).collect();
+        assert_eq!(codes, [Code::NestedTooDeeply]);
+    }
+
+    // long chains are only nested as deep as they're long, which is fine all the way through
+    let src = format!("count = n :Int -> n{};\n", " + 1".repeat(5000));
+    let script = Host::new().load("deep.cae", &ArcStr::from(src)).unwrap();
+    assert_eq!(
+        script.call("count", &[Value::Int(0)]).unwrap(),
+        Value::Int(5000)
+    );
+
+    let src = format!("all = b :Bool -> b{};\n", " && b".repeat(5000));
+    let script = Host::new().load("deep.cae", &ArcStr::from(src)).unwrap();
+    assert_eq!(
+        script.call("all", &[Value::Bool(true)]).unwrap(),
+        Value::Bool(true)
+    );
+
+    // and so is anything parenthesised just under the limit
+    let src = format!("main = {}1{};\n", "(".repeat(250), ")".repeat(250));
+    let (_, diagnostics) = caelis::parse(&ArcStr::from(src));
+    assert!(diagnostics.is_empty());
+}
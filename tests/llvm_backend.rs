@@ -0,0 +1,192 @@
+// the LLVM backend only compiles part of the language so far. the programs in test/programs it can
+// compile have to run the same under `lli` as under the VM, the rest have to be turned away with an
+// error, and what it does compile has to come with debug info. the LLVM tools are needed to check
+// the programs run, and those checks are skipped when they're missing
+#![cfg(feature = "llvm")]
+
+use std::{fs, path::Path, process::Command};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+// the ones that use nothing it can't compile yet
+const COMPILED: &[&str] = &["division", "fib", "names", "records", "trace"];
+
+const PROGRAM: &str = "
+Pair | left :Int, right :Bool;
+
+area = r :Float -> :Float
+  let pi = 3.14159;
+      square = r * r;
+  in pi * square;
+
+pick = n :Int ->
+  let big = n > 10;
+      half = n / 2;
+  in Pair half (assert (big || not big));
+
+main = let p = pick 42; in if area 2.0 > 12.0 then p.left % 8 else 0 - 1;
+";
+
+fn has(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok()
+}
+
+fn first_line(output: &[u8]) -> String {
+    String::from_utf8_lossy(output)
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[test]
+fn llvm_agrees_with_the_vm() {
+    let programs = Path::new(env!("CARGO_MANIFEST_DIR")).join("test/programs");
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("llvm_backend");
+    fs::create_dir_all(&out).unwrap();
+
+    let mut files: Vec<_> = fs::read_dir(&programs)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "cae"))
+        .collect();
+    files.sort();
+
+    let lli = has("lli");
+    let mut failures = Vec::new();
+
+    for file in &files {
+        let name = file.file_stem().unwrap().to_string_lossy();
+        let ll = out.join(format!("{name}.ll"));
+
+        let built = Command::new(CAELIS)
+            .args(["build", "--emit", "llvm", "-o"])
+            .arg(&ll)
+            .arg(file)
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&built.stderr);
+
+        if !COMPILED.contains(&&*name) {
+            if built.status.code() != Some(1) || !stderr.contains("compiled with LLVM yet") {
+                failures.push(format!("{name}: wasn't turned away properly:\n{stderr}"));
+            }
+            continue;
+        }
+        if !built.status.success() {
+            failures.push(format!("{name}: didn't compile:\n{stderr}"));
+            continue;
+        }
+        if !lli {
+            continue;
+        }
+
+        let expected = Command::new(CAELIS).arg("run").arg(file).output().unwrap();
+        let actual = Command::new("lli").arg(&ll).output().unwrap();
+
+        // errors are reported differently, but they should at least start with the same line
+        let agrees = if expected.status.success() {
+            actual.stdout == expected.stdout
+        } else {
+            first_line(&actual.stderr) == first_line(&expected.stdout)
+        };
+
+        if !agrees || actual.status.code() != expected.status.code() {
+            failures.push(format!(
+                "{name}: the VM printed {:?} and exited with {:?}, LLVM printed {:?} and {:?} and exited with {:?}",
+                String::from_utf8_lossy(&expected.stdout),
+                expected.status.code(),
+                String::from_utf8_lossy(&actual.stdout),
+                String::from_utf8_lossy(&actual.stderr),
+                actual.status.code(),
+            ));
+        }
+    }
+
+    if !lli {
+        eprintln!("skipped running the programs, there's no `lli`");
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn debug_info() {
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("llvm_debug_info");
+    fs::create_dir_all(&out).unwrap();
+    let file = out.join("pair.cae");
+    let ll = out.join("pair.ll");
+    fs::write(&file, PROGRAM).unwrap();
+
+    let built = Command::new(CAELIS)
+        .args(["build", "--emit", "llvm", "-o"])
+        .arg(&ll)
+        .arg(&file)
+        .output()
+        .unwrap();
+    assert!(
+        built.status.success(),
+        "{}",
+        String::from_utf8_lossy(&built.stderr)
+    );
+
+    // a subprogram for each definition, a lexical block for each `let`, and the variables and fields
+    // under their caelis names and types
+    let ir = fs::read_to_string(&ll).unwrap();
+    for expected in [
+        "!DICompileUnit(language: DW_LANG_C",
+        "!DIFile(filename: \"pair.cae\"",
+        "!DISubprogram(name: \"area\", linkageName: \"caelis.area\"",
+        "!DISubprogram(name: \"pick\"",
+        "!DILexicalBlock(",
+        "!DILocalVariable(name: \"r\", arg: 1",
+        "!DILocalVariable(name: \"square\"",
+        "!DILocalVariable(name: \"p\"",
+        "!DIBasicType(name: \"Float\", size: 64, encoding: DW_ATE_float)",
+        "!DICompositeType(tag: DW_TAG_structure_type, name: \"Pair\"",
+        "!DIDerivedType(tag: DW_TAG_member, name: \"right\"",
+        // `square = r * r`, on line 6
+        "!DILocation(line: 6, column: 16",
+    ] {
+        assert!(ir.contains(expected), "no `{expected}` in\n{ir}");
+    }
+
+    if has("lli") {
+        let ran = Command::new("lli").arg(&ll).output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&ran.stdout), "5\n");
+    }
+
+    if !has("llc") || !has("llvm-dwarfdump") {
+        eprintln!("skipped checking the DWARF, there's no `llc` or `llvm-dwarfdump`");
+        return;
+    }
+
+    let object = out.join("pair.o");
+    let compiled = Command::new("llc")
+        .args(["-filetype=obj", "-relocation-model=pic", "-o"])
+        .arg(&object)
+        .arg(&ll)
+        .output()
+        .unwrap();
+    assert!(
+        compiled.status.success(),
+        "{}",
+        String::from_utf8_lossy(&compiled.stderr)
+    );
+
+    let dump = Command::new("llvm-dwarfdump")
+        .arg("--debug-info")
+        .arg(&object)
+        .output()
+        .unwrap();
+    let dump = String::from_utf8_lossy(&dump.stdout);
+    for expected in [
+        "DW_TAG_subprogram",
+        "DW_AT_name\t(\"area\")",
+        "DW_TAG_lexical_block",
+        "DW_AT_name\t(\"half\")",
+        "DW_TAG_member",
+        "DW_AT_name\t(\"Pair\")",
+    ] {
+        assert!(dump.contains(expected), "no `{expected}` in\n{dump}");
+    }
+}
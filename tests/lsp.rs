@@ -0,0 +1,168 @@
+// `caelis lsp` driven over stdio the way an editor would, one message at a time
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+};
+
+use serde_json::{json, Value};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+const URI: &str = "file:///session.cae";
+
+struct Session {
+    server: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Session {
+    fn start() -> Self {
+        let mut server = Command::new(CAELIS)
+            .arg("lsp")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = server.stdin.take().unwrap();
+        let stdout = BufReader::new(server.stdout.take().unwrap());
+
+        let mut session = Self {
+            server,
+            stdin,
+            stdout,
+        };
+        let capabilities = session.request(1, "initialize", json!({ "capabilities": {} }));
+        assert!(capabilities["result"]["capabilities"]["hoverProvider"] == true);
+        session.notify("initialized", json!({}));
+
+        session
+    }
+
+    fn send(&mut self, message: Value) {
+        let body = message.to_string();
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{body}", body.len()).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    fn receive(&mut self) -> Value {
+        let mut length = None;
+
+        loop {
+            let mut header = String::new();
+            self.stdout.read_line(&mut header).unwrap();
+
+            match header.trim_end() {
+                "" => break,
+                header => {
+                    if let Some(value) = header.strip_prefix("Content-Length: ") {
+                        length = Some(value.parse().unwrap());
+                    }
+                }
+            }
+        }
+
+        let mut body = vec![0; length.expect("a message without a length")];
+        self.stdout.read_exact(&mut body).unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    fn request(&mut self, id: u64, method: &str, params: Value) -> Value {
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }));
+
+        // notifications can come in before the response
+        loop {
+            let message = self.receive();
+            if message["id"] == id {
+                return message;
+            }
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn open(&mut self, text: &str) -> Vec<Value> {
+        self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": URI, "languageId": "caelis", "version": 1, "text": text }
+            }),
+        );
+
+        let published = self.receive();
+        assert_eq!(published["method"], "textDocument/publishDiagnostics");
+        assert_eq!(published["params"]["uri"], URI);
+        published["params"]["diagnostics"]
+            .as_array()
+            .unwrap()
+            .clone()
+    }
+
+    fn finish(mut self) {
+        let response = self.request(99, "shutdown", Value::Null);
+        assert_eq!(response["result"], Value::Null);
+        self.notify("exit", Value::Null);
+
+        assert!(self.server.wait().unwrap().success());
+    }
+}
+
+#[test]
+fn publishes_diagnostics() {
+    let mut session = Session::start();
+
+    let diagnostics = session.open("main = x :Integer -> y;\nbroken = 1 +;\n");
+    let codes: Vec<&str> = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic["code"].as_str().unwrap())
+        .collect();
+    assert_eq!(codes, ["E0101", "E0201", "E0401"]);
+
+    // `y` is on the first line, after `main = x :Integer -> `
+    let unbound = &diagnostics[2];
+    assert_eq!(
+        unbound["range"]["start"],
+        json!({ "line": 0, "character": 21 })
+    );
+    assert_eq!(unbound["message"], "`y` isn't defined");
+
+    assert!(session.open("main = 1;\n").is_empty());
+
+    session.finish();
+}
+
+#[test]
+fn completes_later_definitions() {
+    let mut session = Session::start();
+    session.open("main = 1 + la\nlater = 2;\nf = x :Po -> x;\nPoint | x :Int;\n");
+
+    let labels = |session: &mut Session, id, line, character| -> Vec<String> {
+        let response = session.request(
+            id,
+            "textDocument/completion",
+            json!({
+                "textDocument": { "uri": URI },
+                "position": { "line": line, "character": character }
+            }),
+        );
+
+        response["result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|item| item["label"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let values = labels(&mut session, 2, 0, 13);
+    assert!(values.contains(&"later".to_string()));
+    assert!(values.contains(&"main".to_string()));
+    assert!(!values.contains(&"Point".to_string()));
+
+    let types = labels(&mut session, 3, 2, 9);
+    assert!(types.contains(&"Point".to_string()));
+    assert!(!types.contains(&"later".to_string()));
+
+    session.finish();
+}
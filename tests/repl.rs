@@ -0,0 +1,63 @@
+// `caelis repl` fed a session on stdin, the way someone would type it
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+const CAELIS: &str = env!("CARGO_BIN_EXE_caelis");
+
+// what the session printed, errors and all, in the order it printed them
+fn session(input: &str) -> String {
+    let mut repl = Command::new(CAELIS)
+        .arg("repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    repl.stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+
+    let output = repl.wait_with_output().unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn definitions_carry_over() {
+    let out = session(
+        "x = 1 + 2;\nf = n :Int -> n * x;\nf 4;\nlet\n  a = 2;\nin a * x;\nx = 10;\nf 1;\n",
+    );
+
+    assert_eq!(
+        out,
+        "x : Int = 3\nf : Int -> Int\n12 : Int\n6 : Int\nx : Int = 10\n10 : Int\n"
+    );
+}
+
+#[test]
+fn errors_leave_the_session_as_it_was() {
+    let out =
+        session("y = undefined;\n1 / 0;\ntest \"fails\" = 1 == 2;\n:type nope 1\ny = 2;\ny;\n");
+
+    assert!(out.contains("`undefined` isn't defined"));
+    assert!(out.contains("division by zero"));
+    assert!(out.contains("test `fails` came out false"));
+    assert!(out.contains("`nope` isn't defined"));
+    assert!(out.ends_with("───╯\ny : Int = 2\n2 : Int\n"), "{out}");
+}
+
+// the session is compiled as one program, but errors in an entry are shown against what was typed
+#[test]
+fn errors_point_into_the_entry() {
+    let out = session("x = 1;\ny = 2;\nx +\n  zz;\n");
+
+    assert!(out.contains("<repl>:2:3"), "{out}");
+    assert!(out.contains(" 2 │   zz;"), "{out}");
+    assert!(!out.contains("it ="), "{out}");
+}